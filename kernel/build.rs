@@ -60,6 +60,18 @@ _app_names:"#
         writeln!(f, r#"    .string "{}""#, app_name)?;
     }
 
+    // the numbered apps are the tests, which start at boot, the others only run by exec
+    writeln!(
+        f,
+        r#"
+.global _app_at_boot
+_app_at_boot:"#
+    )?;
+    for app in apps.iter() {
+        let at_boot = app.starts_with(|c: char| c.is_ascii_digit());
+        writeln!(f, r#"    .byte {}"#, at_boot as u8)?;
+    }
+
     writeln!(
         f,
         r#"
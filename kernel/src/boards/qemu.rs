@@ -1,6 +1,22 @@
+// Defaults of the virt machine, used only when SBI passes no usable device tree.
 pub const CLOCK_FREQ: usize = 0x989680;
 pub const MEMORY_END: usize = 0x8800_0000;
 
+pub const VIRTIO_MMIO_BASE: usize = 0x1000_1000;
+pub const VIRTIO_MMIO_SIZE: usize = 0x1000;
+pub const VIRTIO_MMIO_COUNT: usize = 8;
+pub const VIRTIO_IRQ_BASE: usize = 1;
+
+pub const UART_BASE: usize = 0x1000_0000;
+pub const UART_SIZE: usize = 0x100;
+pub const UART_IRQ: usize = 10;
+
+pub const RTC_BASE: usize = 0x0010_1000;
+pub const RTC_SIZE: usize = 0x1000;
+
+pub const PLIC_BASE: usize = 0x0c00_0000;
+pub const PLIC_SIZE: usize = 0x60_0000;
+
 pub const MMIO: &[(usize, usize)] = &[
     (0x0010_0000, 0x00_2000), // VIRT_TEST/RTC  in virt machine
 ];
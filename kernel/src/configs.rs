@@ -1 +1,4 @@
-pub use crate::board::{CLOCK_FREQ, MEMORY_END, MMIO};
+pub use crate::board::{
+    CLOCK_FREQ, MEMORY_END, MMIO, PLIC_BASE, PLIC_SIZE, RTC_BASE, RTC_SIZE, UART_BASE, UART_IRQ,
+    UART_SIZE, VIRTIO_IRQ_BASE, VIRTIO_MMIO_BASE, VIRTIO_MMIO_COUNT, VIRTIO_MMIO_SIZE,
+};
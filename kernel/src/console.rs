@@ -1,6 +1,6 @@
 use core::fmt::{self, Display, Write};
 
-use crate::sbi::console_putchar;
+use crate::{drivers::uart, sbi::console_putchar};
 
 #[allow(unused)]
 #[derive(PartialEq, PartialOrd)]
@@ -34,13 +34,21 @@ struct Stdout;
 
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            console_putchar(c as usize);
-        }
+        write(s.as_bytes());
         Ok(())
     }
 }
 
+/// Send `bytes` to the console as they are.
+pub fn write(bytes: &[u8]) {
+    // SBI is only used until the UART driver is up
+    if !uart::write(bytes) {
+        for &byte in bytes {
+            console_putchar(byte as usize);
+        }
+    }
+}
+
 pub fn _print(args: fmt::Arguments) {
     Stdout.write_fmt(args).unwrap();
 }
@@ -0,0 +1,431 @@
+//! Flattened device tree parsing, done once at boot with the blob passed by SBI in `a1`.
+//!
+//! Only the pieces the kernel cares about are kept, the blob itself lives in RAM which is
+//! handed to the frame allocator later, so nothing may refer into it after `init`.
+
+use alloc::{string::String, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{
+    configs::{
+        CLOCK_FREQ, MEMORY_END, PLIC_BASE, PLIC_SIZE, RTC_BASE, RTC_SIZE, UART_BASE, UART_IRQ,
+        UART_SIZE, VIRTIO_IRQ_BASE, VIRTIO_MMIO_BASE, VIRTIO_MMIO_COUNT, VIRTIO_MMIO_SIZE,
+    },
+    info, warn,
+};
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+/// Start of RAM on the virt machine, used when no device tree is available.
+const MEMORY_START: usize = 0x8000_0000;
+
+static DTB_ADDR: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static::lazy_static! {
+    static ref MACHINE: MachineInfo = {
+        let dtb = DTB_ADDR.load(Ordering::Relaxed);
+        match unsafe { MachineInfo::parse(dtb) } {
+            Some(machine) => machine,
+            None => {
+                warn!("no valid device tree at {:#x}, using board defaults", dtb);
+                MachineInfo::fallback()
+            }
+        }
+    };
+}
+
+/// A device node with a `compatible` property.
+pub struct Device {
+    pub name: String,
+    pub compatible: Vec<String>,
+    pub regs: Vec<(usize, usize)>,
+    pub interrupts: Vec<usize>,
+}
+
+impl Device {
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.compatible.iter().any(|c| c == compatible)
+    }
+    /// Base address and size of the first register range.
+    pub fn reg(&self) -> Option<(usize, usize)> {
+        self.regs.first().copied()
+    }
+}
+
+pub struct MachineInfo {
+    /// `[start, end)` of every RAM range.
+    pub memory: Vec<(usize, usize)>,
+    /// `[start, end)` of RAM which must not be handed out, the blob itself included.
+    pub reserved: Vec<(usize, usize)>,
+    pub timebase_frequency: usize,
+    pub harts: usize,
+    pub bootargs: String,
+    pub initrd: Option<(usize, usize)>,
+    pub devices: Vec<Device>,
+}
+
+impl MachineInfo {
+    fn fallback() -> Self {
+        let virtio = (0..VIRTIO_MMIO_COUNT).map(|slot| Device {
+            name: String::from("virtio_mmio"),
+            compatible: vec![String::from("virtio,mmio")],
+            regs: vec![(VIRTIO_MMIO_BASE + slot * VIRTIO_MMIO_SIZE, VIRTIO_MMIO_SIZE)],
+            interrupts: vec![VIRTIO_IRQ_BASE + slot],
+        });
+        let plic = Device {
+            name: String::from("plic"),
+            compatible: vec![String::from("riscv,plic0")],
+            regs: vec![(PLIC_BASE, PLIC_SIZE)],
+            interrupts: Vec::new(),
+        };
+        let uart = Device {
+            name: String::from("serial"),
+            compatible: vec![String::from("ns16550a")],
+            regs: vec![(UART_BASE, UART_SIZE)],
+            interrupts: vec![UART_IRQ],
+        };
+        let rtc = Device {
+            name: String::from("rtc"),
+            compatible: vec![String::from("google,goldfish-rtc")],
+            regs: vec![(RTC_BASE, RTC_SIZE)],
+            interrupts: Vec::new(),
+        };
+        Self {
+            memory: vec![(MEMORY_START, MEMORY_END)],
+            reserved: Vec::new(),
+            timebase_frequency: CLOCK_FREQ,
+            harts: 1,
+            bootargs: String::new(),
+            initrd: None,
+            devices: virtio.chain([plic, uart, rtc]).collect(),
+        }
+    }
+
+    unsafe fn parse(dtb: usize) -> Option<Self> {
+        if dtb == 0 || dtb % 4 != 0 || be32(dtb) != FDT_MAGIC {
+            return None;
+        }
+        let total_size = be32(dtb + 4) as usize;
+        let mut machine = Self {
+            memory: Vec::new(),
+            reserved: vec![(dtb, dtb + total_size)],
+            timebase_frequency: 0,
+            harts: 0,
+            bootargs: String::new(),
+            initrd: None,
+            devices: Vec::new(),
+        };
+        // memory reservation block, terminated by an empty entry
+        let mut rsv = dtb + be32(dtb + 16) as usize;
+        loop {
+            let (start, size) = (be64(rsv) as usize, be64(rsv + 8) as usize);
+            if size == 0 {
+                break;
+            }
+            machine.reserved.push((start, start + size));
+            rsv += 16;
+        }
+        Parser {
+            structs: dtb + be32(dtb + 8) as usize,
+            strings: dtb + be32(dtb + 12) as usize,
+        }
+        .walk(&mut machine);
+        if machine.memory.is_empty() {
+            return None;
+        }
+        if machine.timebase_frequency == 0 {
+            machine.timebase_frequency = CLOCK_FREQ;
+        }
+        machine.harts = machine.harts.max(1);
+        machine.memory.sort();
+        if let Some(initrd) = machine.initrd {
+            machine.reserved.push(initrd);
+        }
+        Some(machine)
+    }
+
+    pub fn find_compatible<'a>(&'a self, compatible: &'a str) -> impl Iterator<Item = &'a Device> {
+        self.devices
+            .iter()
+            .filter(move |device| device.is_compatible(compatible))
+    }
+
+    pub fn plic(&self) -> Option<&Device> {
+        self.find_compatible("riscv,plic0")
+            .chain(self.find_compatible("sifive,plic-1.0.0"))
+            .next()
+    }
+
+    #[allow(unused)]
+    pub fn clint(&self) -> Option<&Device> {
+        self.find_compatible("riscv,clint0")
+            .chain(self.find_compatible("sifive,clint0"))
+            .next()
+    }
+
+    /// End of the RAM range holding the kernel image.
+    pub fn memory_end(&self, kernel: usize) -> usize {
+        self.memory
+            .iter()
+            .find(|&&(start, end)| start <= kernel && kernel < end)
+            .expect("kernel is not loaded in RAM")
+            .1
+    }
+
+    /// RAM above `floor` which is not reserved, sorted by address.
+    pub fn usable_memory(&self, floor: usize) -> Vec<(usize, usize)> {
+        let mut usable: Vec<(usize, usize)> = self
+            .memory
+            .iter()
+            .map(|&(start, end)| (start.max(floor), end))
+            .filter(|&(start, end)| start < end)
+            .collect();
+        for &(rsv_start, rsv_end) in self.reserved.iter() {
+            usable = usable
+                .into_iter()
+                .flat_map(|(start, end)| [(start, end.min(rsv_start)), (start.max(rsv_end), end)])
+                .filter(|&(start, end)| start < end)
+                .collect();
+        }
+        usable
+    }
+}
+
+/// `#address-cells` and `#size-cells` in effect for the children of a node.
+#[derive(Clone, Copy)]
+struct Cells {
+    address: usize,
+    size: usize,
+}
+
+impl Default for Cells {
+    fn default() -> Self {
+        Self {
+            address: 2,
+            size: 1,
+        }
+    }
+}
+
+/// What a node turned out to be while reading its properties.
+#[derive(Default)]
+struct Node {
+    name: String,
+    path_kind: NodeKind,
+    device_type: String,
+    compatible: Vec<String>,
+    regs: Vec<(usize, usize)>,
+    interrupts: Vec<usize>,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+enum NodeKind {
+    #[default]
+    Other,
+    Cpus,
+    Chosen,
+    ReservedMemory,
+}
+
+struct Parser {
+    structs: usize,
+    strings: usize,
+}
+
+impl Parser {
+    unsafe fn walk(&self, machine: &mut MachineInfo) {
+        let mut ptr = self.structs;
+        // the cells of a node's parent describe its `reg`
+        let mut cells = vec![Cells::default()];
+        let mut nodes: Vec<Node> = Vec::new();
+        loop {
+            let token = be32(ptr);
+            ptr += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = cstr(ptr);
+                    ptr = align4(ptr + name.len() + 1);
+                    let path_kind = match (nodes.len(), name.as_str()) {
+                        (1, "cpus") => NodeKind::Cpus,
+                        (1, "chosen") => NodeKind::Chosen,
+                        (1, "reserved-memory") => NodeKind::ReservedMemory,
+                        _ => NodeKind::Other,
+                    };
+                    nodes.push(Node {
+                        name,
+                        path_kind,
+                        ..Default::default()
+                    });
+                    cells.push(Cells::default());
+                }
+                FDT_END_NODE => {
+                    let node = nodes.pop().unwrap();
+                    cells.pop();
+                    let parent = nodes.last().map(|node| node.path_kind);
+                    self.finish(machine, node, parent);
+                    if nodes.is_empty() {
+                        break;
+                    }
+                }
+                FDT_PROP => {
+                    let len = be32(ptr) as usize;
+                    let name = cstr(self.strings + be32(ptr + 4) as usize);
+                    let value = core::slice::from_raw_parts((ptr + 8) as *const u8, len);
+                    ptr = align4(ptr + 8 + len);
+                    let depth = cells.len();
+                    let parent_cells = cells[depth - 2];
+                    let in_cpus = nodes_in_cpus(&nodes);
+                    let node = nodes.last_mut().unwrap();
+                    match name.as_str() {
+                        "#address-cells" => cells[depth - 1].address = read_cells(value, 1),
+                        "#size-cells" => cells[depth - 1].size = read_cells(value, 1),
+                        "device_type" => {
+                            node.device_type = strings(value).next().unwrap_or_default()
+                        }
+                        "compatible" => node.compatible = strings(value).collect(),
+                        "reg" => node.regs = read_regs(value, parent_cells),
+                        "interrupts" => {
+                            node.interrupts =
+                                value.chunks_exact(4).map(|c| read_cells(c, 1)).collect()
+                        }
+                        "timebase-frequency" if in_cpus => {
+                            machine.timebase_frequency = read_cells(value, value.len() / 4)
+                        }
+                        "bootargs" if node.path_kind == NodeKind::Chosen => {
+                            machine.bootargs = strings(value).next().unwrap_or_default()
+                        }
+                        "linux,initrd-start" if node.path_kind == NodeKind::Chosen => {
+                            let start = read_cells(value, value.len() / 4);
+                            let end = machine.initrd.map_or(start, |(_, end)| end);
+                            machine.initrd = Some((start, end));
+                        }
+                        "linux,initrd-end" if node.path_kind == NodeKind::Chosen => {
+                            let end = read_cells(value, value.len() / 4);
+                            let start = machine.initrd.map_or(end, |(start, _)| start);
+                            machine.initrd = Some((start, end));
+                        }
+                        _ => {}
+                    }
+                }
+                FDT_NOP => {}
+                FDT_END => break,
+                _ => {
+                    warn!("unknown device tree token {:#x}", token);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn finish(&self, machine: &mut MachineInfo, node: Node, parent: Option<NodeKind>) {
+        if node.device_type == "memory" {
+            machine
+                .memory
+                .extend(node.regs.iter().map(|&(start, size)| (start, start + size)));
+        } else if node.device_type == "cpu" && parent == Some(NodeKind::Cpus) {
+            machine.harts += 1;
+        } else if parent == Some(NodeKind::ReservedMemory) {
+            machine
+                .reserved
+                .extend(node.regs.iter().map(|&(start, size)| (start, start + size)));
+        }
+        if !node.compatible.is_empty() && node.device_type != "cpu" {
+            machine.devices.push(Device {
+                name: node.name,
+                compatible: node.compatible,
+                regs: node.regs,
+                interrupts: node.interrupts,
+            });
+        }
+    }
+}
+
+/// `timebase-frequency` may sit on `/cpus` or on every `/cpus/cpu@N`.
+fn nodes_in_cpus(nodes: &[Node]) -> bool {
+    nodes
+        .get(1)
+        .is_some_and(|node| node.path_kind == NodeKind::Cpus)
+}
+
+unsafe fn be32(addr: usize) -> u32 {
+    u32::from_be((addr as *const u32).read_volatile())
+}
+
+unsafe fn be64(addr: usize) -> u64 {
+    (be32(addr) as u64) << 32 | be32(addr + 4) as u64
+}
+
+unsafe fn cstr(addr: usize) -> String {
+    let mut len = 0;
+    while *((addr + len) as *const u8) != 0 {
+        len += 1;
+    }
+    let bytes = core::slice::from_raw_parts(addr as *const u8, len);
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn align4(addr: usize) -> usize {
+    (addr + 3) & !3
+}
+
+/// Big-endian number made of the first `count` 32-bit cells of `value`.
+fn read_cells(value: &[u8], count: usize) -> usize {
+    value.chunks_exact(4).take(count).fold(0, |acc, cell| {
+        acc << 32 | u32::from_be_bytes(cell.try_into().unwrap()) as usize
+    })
+}
+
+fn read_regs(value: &[u8], cells: Cells) -> Vec<(usize, usize)> {
+    let entry = (cells.address + cells.size) * 4;
+    if entry == 0 {
+        return Vec::new();
+    }
+    value
+        .chunks_exact(entry)
+        .map(|reg| {
+            let (address, size) = reg.split_at(cells.address * 4);
+            (
+                read_cells(address, cells.address),
+                read_cells(size, cells.size),
+            )
+        })
+        .collect()
+}
+
+/// The NUL separated strings of a string list property.
+fn strings(value: &[u8]) -> impl Iterator<Item = String> + '_ {
+    value
+        .split(|&b| b == 0)
+        .filter(|s| !s.is_empty())
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+}
+
+/// Parse the blob at `dtb`, must run after the heap is ready and before frames are handed out.
+pub fn init(dtb: usize) {
+    DTB_ADDR.store(dtb, Ordering::Relaxed);
+    let machine = machine();
+    for &(start, end) in machine.memory.iter() {
+        info!("memory [{:#x}, {:#x})", start, end);
+    }
+    info!(
+        "{} hart(s), timebase {} Hz, {} device(s)",
+        machine.harts,
+        machine.timebase_frequency,
+        machine.devices.len()
+    );
+    if !machine.bootargs.is_empty() {
+        info!("bootargs: {}", machine.bootargs);
+    }
+    if let Some((start, end)) = machine.initrd {
+        info!("initrd [{:#x}, {:#x})", start, end);
+    }
+}
+
+pub fn machine() -> &'static MachineInfo {
+    &MACHINE
+}
@@ -0,0 +1,8 @@
+pub const BLOCK_SIZE: usize = 512;
+
+/// A device addressed by fixed size blocks, `buf.len()` must be a multiple of `BLOCK_SIZE`.
+pub trait BlockDevice: Send + Sync {
+    fn num_blocks(&self) -> usize;
+    fn read_blocks(&self, block_id: usize, buf: &mut [u8]);
+    fn write_blocks(&self, block_id: usize, buf: &[u8]);
+}
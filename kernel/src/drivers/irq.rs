@@ -0,0 +1,79 @@
+//! External interrupts routed by the PLIC to the boot hart.
+
+use alloc::{collections::BTreeMap, sync::Arc};
+use riscv::register::sie;
+
+use super::plic::Plic;
+use crate::{device_tree::machine, info, sync::UPSafeCell, trace, warn};
+
+type IrqHandler = Arc<dyn Fn() + Send + Sync>;
+
+lazy_static::lazy_static! {
+    static ref IRQ_MANAGER: UPSafeCell<Option<IrqManager>> = unsafe { UPSafeCell::new(None) };
+}
+
+struct IrqManager {
+    plic: Plic,
+    context: usize,
+    handlers: BTreeMap<usize, IrqHandler>,
+}
+
+pub fn init(hart: usize) {
+    let base = match machine().plic().and_then(|plic| plic.reg()) {
+        Some((base, _)) => base,
+        None => {
+            warn!("no PLIC found, external interrupts are disabled");
+            return;
+        }
+    };
+    let plic = Plic::new(base);
+    let context = Plic::supervisor_context(hart);
+    plic.set_threshold(context, 0);
+    *IRQ_MANAGER.get_mut() = Some(IrqManager {
+        plic,
+        context,
+        handlers: BTreeMap::new(),
+    });
+    unsafe { sie::set_sext() };
+    info!("PLIC at {:#x}, context {}", base, context);
+}
+
+/// Call `handler` whenever the source `irq` interrupts, returns false without a PLIC.
+///
+/// Handlers run in the trap handler, they should only acknowledge the device and record
+/// what happened.
+pub fn register_irq(irq: usize, handler: impl Fn() + Send + Sync + 'static) -> bool {
+    let mut manager = IRQ_MANAGER.get_mut();
+    let manager = match manager.as_mut() {
+        Some(manager) => manager,
+        None => return false,
+    };
+    manager.handlers.insert(irq, Arc::new(handler));
+    manager.plic.set_priority(irq, 1);
+    manager.plic.enable(manager.context, irq);
+    trace!("irq {} registered", irq);
+    true
+}
+
+/// Serve every pending source, called on a supervisor external interrupt.
+pub fn handle_external_interrupt() {
+    loop {
+        let (irq, handler) = {
+            let manager = IRQ_MANAGER.get();
+            let manager = manager.as_ref().expect("external interrupt without a PLIC");
+            let irq = manager.plic.claim(manager.context);
+            (irq, manager.handlers.get(&irq).cloned())
+        };
+        if irq == 0 {
+            break;
+        }
+        // the handler may register other sources, so no borrow is held while it runs
+        match handler {
+            Some(handler) => handler(),
+            None => warn!("unexpected irq {}", irq),
+        }
+        let manager = IRQ_MANAGER.get();
+        let manager = manager.as_ref().unwrap();
+        manager.plic.complete(manager.context, irq);
+    }
+}
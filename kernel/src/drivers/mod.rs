@@ -0,0 +1,73 @@
+mod block;
+mod irq;
+mod plic;
+mod rtc;
+pub mod uart;
+pub mod virtio_blk;
+
+use alloc::sync::Arc;
+
+use crate::{device_tree::machine, info, memory::ioremap, timer, warn};
+use rtc::GoldfishRtc;
+pub use block::{BlockDevice, BLOCK_SIZE};
+pub use irq::{handle_external_interrupt, register_irq};
+use virtio_blk::VirtIOBlk;
+
+lazy_static::lazy_static! {
+    static ref BLOCK_DEVICE: Option<Arc<dyn BlockDevice>> = {
+        let device = VirtIOBlk::probe().map(|blk| blk as Arc<dyn BlockDevice>);
+        if device.is_none() {
+            warn!("no virtio-blk device found");
+        }
+        device
+    };
+}
+
+/// Bring up the interrupt controller of `hart` first, so drivers can register their sources.
+pub fn init(hart: usize) {
+    irq::init(hart);
+    init_uart();
+    init_rtc();
+    lazy_static::initialize(&BLOCK_DEVICE);
+}
+
+pub fn block_device() -> Option<Arc<dyn BlockDevice>> {
+    BLOCK_DEVICE.clone()
+}
+
+/// Switch the console from SBI calls over to the UART.
+fn init_uart() {
+    let uart = match machine().find_compatible("ns16550a").next() {
+        Some(uart) => uart,
+        None => {
+            warn!("no ns16550a found, console stays on SBI");
+            return;
+        }
+    };
+    let (base, size) = match uart.reg() {
+        Some(reg) => reg,
+        None => return,
+    };
+    uart::init(ioremap(base, size));
+    if let Some(&irq) = uart.interrupts.first() {
+        register_irq(irq, uart::handle_irq);
+    }
+    info!("console on ns16550a at {:#x}", base);
+}
+
+/// Read the wall clock once, the timer keeps it going from there.
+fn init_rtc() {
+    let reg = machine()
+        .find_compatible("google,goldfish-rtc")
+        .find_map(|rtc| rtc.reg());
+    let (base, size) = match reg {
+        Some(reg) => reg,
+        None => {
+            warn!("no goldfish RTC found, wall clock starts at the epoch");
+            return;
+        }
+    };
+    let now = GoldfishRtc::new(ioremap(base, size)).read_time_ns();
+    timer::set_realtime_ns(now as usize);
+    info!("RTC at {:#x}, {}s since the epoch", base, now as usize / timer::NANO_PER_SEC);
+}
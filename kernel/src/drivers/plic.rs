@@ -0,0 +1,64 @@
+//! Platform-Level Interrupt Controller, as found on the virt machine.
+
+use core::ptr::{read_volatile, write_volatile};
+
+const PRIORITY: usize = 0x0000;
+const ENABLE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const CONTEXT: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+const THRESHOLD: usize = 0x0;
+const CLAIM: usize = 0x4;
+
+pub struct Plic {
+    base: usize,
+}
+
+impl Plic {
+    pub fn new(base: usize) -> Self {
+        Self { base }
+    }
+
+    /// Supervisor mode context of `hart`, every hart has a machine mode context before it.
+    pub fn supervisor_context(hart: usize) -> usize {
+        hart * 2 + 1
+    }
+
+    fn reg(&self, offset: usize) -> *mut u32 {
+        (self.base + offset) as *mut u32
+    }
+
+    /// Priority 0 never interrupts, larger values win on claim.
+    pub fn set_priority(&self, irq: usize, priority: u32) {
+        unsafe { write_volatile(self.reg(PRIORITY + irq * 4), priority) }
+    }
+
+    pub fn enable(&self, context: usize, irq: usize) {
+        let reg = self.reg(ENABLE + context * ENABLE_STRIDE + irq / 32 * 4);
+        unsafe { write_volatile(reg, read_volatile(reg) | 1 << (irq % 32)) }
+    }
+
+    #[allow(unused)]
+    pub fn disable(&self, context: usize, irq: usize) {
+        let reg = self.reg(ENABLE + context * ENABLE_STRIDE + irq / 32 * 4);
+        unsafe { write_volatile(reg, read_volatile(reg) & !(1 << (irq % 32))) }
+    }
+
+    /// Only sources with a priority above `threshold` reach `context`.
+    pub fn set_threshold(&self, context: usize, threshold: u32) {
+        let reg = self.reg(CONTEXT + context * CONTEXT_STRIDE + THRESHOLD);
+        unsafe { write_volatile(reg, threshold) }
+    }
+
+    /// Take the highest priority pending source of `context`, 0 if there is none.
+    pub fn claim(&self, context: usize) -> usize {
+        let reg = self.reg(CONTEXT + context * CONTEXT_STRIDE + CLAIM);
+        unsafe { read_volatile(reg) as usize }
+    }
+
+    /// Tell the source `irq` claimed before is served, so it may interrupt again.
+    pub fn complete(&self, context: usize, irq: usize) {
+        let reg = self.reg(CONTEXT + context * CONTEXT_STRIDE + CLAIM);
+        unsafe { write_volatile(reg, irq as u32) }
+    }
+}
@@ -0,0 +1,23 @@
+//! Goldfish RTC, nanoseconds since the unix epoch.
+
+use core::ptr::read_volatile;
+
+const TIME_LOW: usize = 0x00;
+const TIME_HIGH: usize = 0x04;
+
+pub struct GoldfishRtc {
+    base: usize,
+}
+
+impl GoldfishRtc {
+    pub fn new(base: usize) -> Self {
+        Self { base }
+    }
+
+    pub fn read_time_ns(&self) -> u64 {
+        // reading the low half latches the high half
+        let low = unsafe { read_volatile((self.base + TIME_LOW) as *const u32) };
+        let high = unsafe { read_volatile((self.base + TIME_HIGH) as *const u32) };
+        (high as u64) << 32 | low as u64
+    }
+}
@@ -0,0 +1,139 @@
+//! ns16550a UART, the console of the virt machine.
+//!
+//! Output goes straight to the transmit FIFO, input is moved into a ring buffer by the
+//! receive interrupt, which wakes the readers, or by polling when a reader finds it empty.
+
+use core::{
+    hint::spin_loop,
+    ptr::{read_volatile, write_volatile},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{process::WaitQueue, sync::UPSafeCell};
+
+const RBR: usize = 0;
+const THR: usize = 0;
+const IER: usize = 1;
+const FCR: usize = 2;
+const LCR: usize = 3;
+const MCR: usize = 4;
+const LSR: usize = 5;
+
+const IER_RX_AVAILABLE: u8 = 1;
+const FCR_ENABLE_CLEAR: u8 = 0b111;
+const LCR_8N1: u8 = 0b11;
+/// DTR, RTS and OUT2, the last one gates the interrupt line on real chips.
+const MCR_DTR_RTS_OUT2: u8 = 0b1011;
+const LSR_DATA_READY: u8 = 1;
+const LSR_THR_EMPTY: u8 = 1 << 5;
+
+const FIFO_SIZE: usize = 16;
+const RX_BUFFER_SIZE: usize = 256;
+
+/// Base address of the UART once it is set up, 0 before that.
+static UART_BASE: AtomicUsize = AtomicUsize::new(0);
+
+static RX_BUFFER: UPSafeCell<RingBuffer> = unsafe { UPSafeCell::new(RingBuffer::new()) };
+static RX_WAITERS: WaitQueue = WaitQueue::new();
+
+struct RingBuffer {
+    buf: [u8; RX_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl RingBuffer {
+    const fn new() -> Self {
+        Self {
+            buf: [0; RX_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+    /// The oldest byte is dropped when the buffer is full.
+    fn push(&mut self, byte: u8) {
+        self.buf[(self.head + self.len) % RX_BUFFER_SIZE] = byte;
+        if self.len == RX_BUFFER_SIZE {
+            self.head = (self.head + 1) % RX_BUFFER_SIZE;
+        } else {
+            self.len += 1;
+        }
+    }
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.buf[self.head];
+        self.head = (self.head + 1) % RX_BUFFER_SIZE;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
+fn read_reg(base: usize, reg: usize) -> u8 {
+    unsafe { read_volatile((base + reg) as *const u8) }
+}
+
+fn write_reg(base: usize, reg: usize, val: u8) {
+    unsafe { write_volatile((base + reg) as *mut u8, val) }
+}
+
+/// Set the line up as 8N1 with FIFOs and the receive interrupt enabled.
+pub fn init(base: usize) {
+    write_reg(base, IER, 0);
+    write_reg(base, LCR, LCR_8N1);
+    write_reg(base, FCR, FCR_ENABLE_CLEAR);
+    write_reg(base, MCR, MCR_DTR_RTS_OUT2);
+    write_reg(base, IER, IER_RX_AVAILABLE);
+    UART_BASE.store(base, Ordering::Relaxed);
+}
+
+/// Send `bytes`, returns false if the UART is not set up yet.
+pub fn write(bytes: &[u8]) -> bool {
+    let base = UART_BASE.load(Ordering::Relaxed);
+    if base == 0 {
+        return false;
+    }
+    // an empty holding register means the whole FIFO is free
+    for chunk in bytes.chunks(FIFO_SIZE) {
+        while read_reg(base, LSR) & LSR_THR_EMPTY == 0 {
+            spin_loop();
+        }
+        for &byte in chunk {
+            write_reg(base, THR, byte);
+        }
+    }
+    true
+}
+
+/// Move every received byte into the ring buffer.
+fn drain_rx() {
+    let base = UART_BASE.load(Ordering::Relaxed);
+    if base == 0 {
+        return;
+    }
+    let mut rx = RX_BUFFER.get_mut();
+    while read_reg(base, LSR) & LSR_DATA_READY != 0 {
+        rx.push(read_reg(base, RBR));
+    }
+}
+
+pub fn handle_irq() {
+    drain_rx();
+    RX_WAITERS.wake_all();
+}
+
+/// Block until a byte was received.
+pub fn wait_readable() {
+    RX_WAITERS.wait_event(|| {
+        drain_rx();
+        RX_BUFFER.get().len > 0
+    });
+}
+
+/// Take one received byte, if there is any.
+pub fn getchar() -> Option<u8> {
+    // bytes may have arrived before their interrupt was taken, so poll as well
+    drain_rx();
+    RX_BUFFER.get_mut().pop()
+}
@@ -0,0 +1,439 @@
+//! virtio-blk over the virtio-mmio transport, both legacy (v1) and modern (v2) devices.
+//!
+//! Up to `MAX_REQUESTS` requests are in flight at a time. A requester sleeps until the
+//! interrupt handler reaps its request from the used ring, only where it may not sleep, with
+//! a cell borrowed as when a frame is reclaimed for a page table, it polls the ring itself.
+//! All buffers handed to the device live in the kernel heap, which is identity mapped, so
+//! their addresses are physical addresses as well.
+
+use alloc::{
+    alloc::{alloc_zeroed, Layout},
+    boxed::Box,
+    sync::Arc,
+};
+use core::{
+    hint::spin_loop,
+    mem::offset_of,
+    ptr::{read_volatile, write_volatile},
+    sync::atomic::{fence, Ordering},
+};
+
+use super::{
+    block::{BlockDevice, BLOCK_SIZE},
+    register_irq,
+};
+use crate::{
+    device_tree::machine,
+    info,
+    memory::ioremap,
+    process::WaitQueue,
+    sync::{preempt::preemptible, UPSafeCell},
+    trace,
+};
+
+const MAGIC_VALUE: usize = 0x000;
+const VERSION: usize = 0x004;
+const DEVICE_ID: usize = 0x008;
+const DEVICE_FEATURES_SEL: usize = 0x014;
+const DRIVER_FEATURES: usize = 0x020;
+const DRIVER_FEATURES_SEL: usize = 0x024;
+const GUEST_PAGE_SIZE: usize = 0x028;
+const QUEUE_SEL: usize = 0x030;
+const QUEUE_NUM_MAX: usize = 0x034;
+const QUEUE_NUM: usize = 0x038;
+const QUEUE_ALIGN: usize = 0x03c;
+const QUEUE_PFN: usize = 0x040;
+const QUEUE_READY: usize = 0x044;
+const QUEUE_NOTIFY: usize = 0x050;
+const INTERRUPT_STATUS: usize = 0x060;
+const INTERRUPT_ACK: usize = 0x064;
+const STATUS: usize = 0x070;
+const QUEUE_DESC_LOW: usize = 0x080;
+const QUEUE_DESC_HIGH: usize = 0x084;
+const QUEUE_DRIVER_LOW: usize = 0x090;
+const QUEUE_DRIVER_HIGH: usize = 0x094;
+const QUEUE_DEVICE_LOW: usize = 0x0a0;
+const QUEUE_DEVICE_HIGH: usize = 0x0a4;
+const CONFIG: usize = 0x100;
+
+const MAGIC: u32 = 0x7472_6976;
+const DEVICE_ID_BLOCK: u32 = 2;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FEATURES_OK: u32 = 8;
+
+/// VIRTIO_F_VERSION_1, bit 32 of the feature set
+const FEATURE_VERSION_1_HIGH: u32 = 1;
+
+const VRING_DESC_F_NEXT: u16 = 1;
+const VRING_DESC_F_WRITE: u16 = 2;
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_S_OK: u8 = 0;
+
+const QUEUE_SIZE: usize = 16;
+const QUEUE_ALIGN_SIZE: usize = 4096;
+const MAX_TRANSFER: usize = 4096;
+/// each request takes a chain of three descriptors
+const MAX_REQUESTS: usize = QUEUE_SIZE / 3;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[allow(unused)]
+#[repr(C)]
+struct AvailRing {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE],
+    used_event: u16,
+}
+
+#[allow(unused)]
+#[repr(C)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+#[allow(unused)]
+#[repr(C, align(4096))]
+struct UsedRing {
+    flags: u16,
+    idx: u16,
+    ring: [UsedElem; QUEUE_SIZE],
+    avail_event: u16,
+}
+
+/// Legacy layout: the used ring starts at the next `QUEUE_ALIGN_SIZE` boundary.
+#[repr(C, align(4096))]
+struct VirtQueue {
+    desc: [Descriptor; QUEUE_SIZE],
+    avail: AvailRing,
+    used: UsedRing,
+}
+
+#[allow(unused)]
+#[repr(C)]
+struct BlkReqHeader {
+    req_type: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+#[repr(C)]
+struct BlkBuffer {
+    header: BlkReqHeader,
+    data: [u8; MAX_TRANSFER],
+    status: u8,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Request {
+    Free,
+    InFlight,
+    Done,
+}
+
+pub struct VirtIOBlk {
+    inner: UPSafeCell<VirtIOBlkInner>,
+    /// woken when a request completed or its slot was freed
+    waiters: WaitQueue,
+}
+
+struct VirtIOBlkInner {
+    base: usize,
+    capacity: usize,
+    last_used: u16,
+    /// whether completions are signalled, requesters poll without
+    irq: bool,
+    queue: Box<VirtQueue>,
+    /// the request in slot `i` uses `buffers[i]` and the descriptors from `3 * i` on
+    requests: [Request; MAX_REQUESTS],
+    buffers: Box<[BlkBuffer; MAX_REQUESTS]>,
+}
+
+/// Allocate a zeroed `T` directly on the heap, these are too large for a kernel stack.
+fn boxed_zeroed<T>() -> Box<T> {
+    unsafe {
+        let ptr = alloc_zeroed(Layout::new::<T>()) as *mut T;
+        assert!(!ptr.is_null(), "out of kernel heap");
+        Box::from_raw(ptr)
+    }
+}
+
+impl VirtIOBlk {
+    /// Scan the virtio-mmio nodes of the device tree and bring up the first block device.
+    pub fn probe() -> Option<Arc<Self>> {
+        let (base, irq) = machine()
+            .find_compatible("virtio,mmio")
+            .filter_map(|device| {
+                let (base, size) = device.reg()?;
+                Some((ioremap(base, size), device.interrupts.first().copied()))
+            })
+            .find(|&(base, _)| unsafe {
+                read_volatile((base + MAGIC_VALUE) as *const u32) == MAGIC
+                    && read_volatile((base + DEVICE_ID) as *const u32) == DEVICE_ID_BLOCK
+            })?;
+        let inner = VirtIOBlkInner::new(base)?;
+        info!("virtio-blk at {:#x}, {} blocks", inner.base, inner.capacity);
+        let blk = Arc::new(Self {
+            inner: unsafe { UPSafeCell::new(inner) },
+            waiters: WaitQueue::new(),
+        });
+        if let Some(irq) = irq {
+            let device = blk.clone();
+            blk.inner.get_mut().irq = register_irq(irq, move || device.handle_irq());
+        }
+        Some(blk)
+    }
+
+    /// Reap the completed requests and wake their requesters.
+    fn handle_irq(&self) {
+        let reaped = {
+            let mut inner = self.inner.get_mut();
+            let status = inner.read(INTERRUPT_STATUS);
+            inner.write(INTERRUPT_ACK, status);
+            trace!("virtio-blk interrupt, status {:#x}", status);
+            inner.reap()
+        };
+        if reaped > 0 {
+            self.waiters.wake_all();
+        }
+    }
+
+    /// Move `len` bytes from `data` or to `buf` between the disk and a request buffer, starting
+    /// at `sector`. Returns whether the device reported success.
+    fn transfer(
+        &self,
+        sector: usize,
+        len: usize,
+        data: Option<&[u8]>,
+        mut buf: Option<&mut [u8]>,
+    ) -> bool {
+        let slot = self.wait_until(|inner| {
+            let slot = inner
+                .requests
+                .iter()
+                .position(|&req| req == Request::Free)?;
+            inner.start(slot, sector, len, data);
+            Some(slot)
+        });
+        let ok = self.wait_until(|inner| {
+            (inner.requests[slot] == Request::Done).then(|| inner.finish(slot, buf.take()))
+        });
+        // the slot is free for someone else
+        self.waiters.wake_all();
+        ok
+    }
+
+    /// Retry `f` until it returns something, sleeping until the next completion in between.
+    ///
+    /// Without a way to sleep the used ring is polled instead, which reaps the requests of
+    /// sleeping requesters as well.
+    fn wait_until<R>(&self, mut f: impl FnMut(&mut VirtIOBlkInner) -> Option<R>) -> R {
+        if preemptible() && self.inner.get().irq {
+            let mut ret = None;
+            self.waiters.wait_event(|| {
+                ret = f(&mut self.inner.get_mut());
+                ret.is_some()
+            });
+            return ret.unwrap();
+        }
+        loop {
+            let mut inner = self.inner.get_mut();
+            let reaped = inner.reap();
+            let ret = f(&mut inner);
+            drop(inner);
+            if reaped > 0 {
+                self.waiters.wake_all();
+            }
+            match ret {
+                Some(ret) => return ret,
+                None => spin_loop(),
+            }
+        }
+    }
+}
+
+impl VirtIOBlkInner {
+    fn new(base: usize) -> Option<Self> {
+        let mut blk = Self {
+            base,
+            capacity: 0,
+            last_used: 0,
+            irq: false,
+            queue: boxed_zeroed(),
+            requests: [Request::Free; MAX_REQUESTS],
+            buffers: boxed_zeroed(),
+        };
+        let version = blk.read(VERSION);
+        let mut status = 0;
+        blk.write(STATUS, status);
+        status |= STATUS_ACKNOWLEDGE;
+        blk.write(STATUS, status);
+        status |= STATUS_DRIVER;
+        blk.write(STATUS, status);
+
+        // no optional features are needed
+        blk.write(DEVICE_FEATURES_SEL, 0);
+        blk.write(DRIVER_FEATURES_SEL, 0);
+        blk.write(DRIVER_FEATURES, 0);
+        if version >= 2 {
+            blk.write(DRIVER_FEATURES_SEL, 1);
+            blk.write(DRIVER_FEATURES, FEATURE_VERSION_1_HIGH);
+            status |= STATUS_FEATURES_OK;
+            blk.write(STATUS, status);
+            if blk.read(STATUS) & STATUS_FEATURES_OK == 0 {
+                return None;
+            }
+        }
+
+        blk.write(QUEUE_SEL, 0);
+        if (blk.read(QUEUE_NUM_MAX) as usize) < QUEUE_SIZE {
+            return None;
+        }
+        blk.write(QUEUE_NUM, QUEUE_SIZE as u32);
+        let queue = &*blk.queue as *const VirtQueue as usize;
+        if version == 1 {
+            blk.write(GUEST_PAGE_SIZE, QUEUE_ALIGN_SIZE as u32);
+            blk.write(QUEUE_ALIGN, QUEUE_ALIGN_SIZE as u32);
+            blk.write(QUEUE_PFN, (queue / QUEUE_ALIGN_SIZE) as u32);
+        } else {
+            let avail = queue + offset_of!(VirtQueue, avail);
+            let used = queue + offset_of!(VirtQueue, used);
+            blk.write(QUEUE_DESC_LOW, queue as u32);
+            blk.write(QUEUE_DESC_HIGH, (queue >> 32) as u32);
+            blk.write(QUEUE_DRIVER_LOW, avail as u32);
+            blk.write(QUEUE_DRIVER_HIGH, (avail >> 32) as u32);
+            blk.write(QUEUE_DEVICE_LOW, used as u32);
+            blk.write(QUEUE_DEVICE_HIGH, (used >> 32) as u32);
+            blk.write(QUEUE_READY, 1);
+        }
+        status |= STATUS_DRIVER_OK;
+        blk.write(STATUS, status);
+
+        blk.capacity = blk.read(CONFIG) as usize | (blk.read(CONFIG + 4) as usize) << 32;
+        Some(blk)
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write(&self, offset: usize, val: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, val) }
+    }
+
+    /// Hand a request in `slot` to the device, `data` is what to write if it is a write.
+    fn start(&mut self, slot: usize, sector: usize, len: usize, data: Option<&[u8]>) {
+        assert!(len % BLOCK_SIZE == 0 && len <= MAX_TRANSFER);
+        assert!(
+            sector + len / BLOCK_SIZE <= self.capacity,
+            "sector out of range"
+        );
+        let write = data.is_some();
+        trace!(
+            "virtio-blk {} sector {}",
+            if write { "write" } else { "read" },
+            sector
+        );
+        let buffer = &mut self.buffers[slot];
+        buffer.header = BlkReqHeader {
+            req_type: if write {
+                VIRTIO_BLK_T_OUT
+            } else {
+                VIRTIO_BLK_T_IN
+            },
+            reserved: 0,
+            sector: sector as u64,
+        };
+        buffer.status = 0xff;
+        if let Some(data) = data {
+            buffer.data[..len].copy_from_slice(data);
+        }
+        let buffer = buffer as *const BlkBuffer as u64;
+        let head = slot * 3;
+        self.queue.desc[head] = Descriptor {
+            addr: buffer + offset_of!(BlkBuffer, header) as u64,
+            len: core::mem::size_of::<BlkReqHeader>() as u32,
+            flags: VRING_DESC_F_NEXT,
+            next: head as u16 + 1,
+        };
+        self.queue.desc[head + 1] = Descriptor {
+            addr: buffer + offset_of!(BlkBuffer, data) as u64,
+            len: len as u32,
+            flags: VRING_DESC_F_NEXT | if write { 0 } else { VRING_DESC_F_WRITE },
+            next: head as u16 + 2,
+        };
+        self.queue.desc[head + 2] = Descriptor {
+            addr: buffer + offset_of!(BlkBuffer, status) as u64,
+            len: 1,
+            flags: VRING_DESC_F_WRITE,
+            next: 0,
+        };
+        self.requests[slot] = Request::InFlight;
+        let avail_idx = self.queue.avail.idx;
+        self.queue.avail.ring[avail_idx as usize % QUEUE_SIZE] = head as u16;
+        fence(Ordering::SeqCst);
+        self.queue.avail.idx = avail_idx.wrapping_add(1);
+        fence(Ordering::SeqCst);
+        self.write(QUEUE_NOTIFY, 0);
+    }
+
+    /// Mark the requests the device is done with, returns how many there were.
+    fn reap(&mut self) -> usize {
+        let mut reaped = 0;
+        while unsafe { read_volatile(&self.queue.used.idx) } != self.last_used {
+            fence(Ordering::SeqCst);
+            let elem = &self.queue.used.ring[self.last_used as usize % QUEUE_SIZE];
+            let head = unsafe { read_volatile(&elem.id) } as usize;
+            self.requests[head / 3] = Request::Done;
+            self.last_used = self.last_used.wrapping_add(1);
+            reaped += 1;
+        }
+        reaped
+    }
+
+    /// Free the completed request in `slot`, copying what it read to `buf`.
+    fn finish(&mut self, slot: usize, buf: Option<&mut [u8]>) -> bool {
+        fence(Ordering::SeqCst);
+        let buffer = &self.buffers[slot];
+        if let Some(buf) = buf {
+            buf.copy_from_slice(&buffer.data[..buf.len()]);
+        }
+        self.requests[slot] = Request::Free;
+        unsafe { read_volatile(&buffer.status) == VIRTIO_BLK_S_OK }
+    }
+}
+
+impl BlockDevice for VirtIOBlk {
+    fn num_blocks(&self) -> usize {
+        self.inner.get().capacity
+    }
+
+    fn read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+        assert!(
+            self.transfer(block_id, buf.len(), None, Some(buf)),
+            "virtio-blk read error at block {}",
+            block_id
+        );
+    }
+
+    fn write_blocks(&self, block_id: usize, buf: &[u8]) {
+        assert!(
+            self.transfer(block_id, buf.len(), Some(buf), None),
+            "virtio-blk write error at block {}",
+            block_id
+        );
+    }
+}
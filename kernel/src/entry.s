@@ -1,8 +1,21 @@
     .section .text.entry
     .globl _start
 _start:
+    # the first hart to arrive boots the kernel, the others are parked
+    la t0, boot_hart_claimed
+    li t1, 1
+    amoswap.w t1, t1, (t0)
+    bnez t1, park
+    # a0 = hartid, a1 = device tree blob, both passed on to rust_main
     la sp, boot_stack_top
     call rust_main
+park:
+    wfi
+    j park
+
+    .section .data
+boot_hart_claimed:
+    .word 0
 
     .section .boot_stack
     .globl boot_stack_lower_bound
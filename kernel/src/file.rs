@@ -0,0 +1,76 @@
+//! Open files and the table of them a process refers to by fd. There is no file system, the
+//! console and the pseudo-terminals are all there is to open.
+
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use crate::tty::{
+    self,
+    pty::{Master, Slave},
+    Tty,
+};
+
+/// How many files a process may have open at once.
+const MAX_FILES: usize = 64;
+
+pub enum File {
+    Console,
+    PtyMaster(Master),
+    PtySlave(Slave),
+}
+
+impl File {
+    /// The terminal of the file, for the master side of a pseudo-terminal its slave side.
+    pub fn tty(&self) -> &Tty {
+        match self {
+            File::Console => tty::console(),
+            File::PtyMaster(master) => master.tty(),
+            File::PtySlave(slave) => slave.tty(),
+        }
+    }
+}
+
+/// Forked processes get a copy, the files themselves are shared.
+#[derive(Clone)]
+pub struct FdTable {
+    files: Vec<Option<Arc<File>>>,
+}
+
+impl FdTable {
+    /// Standard input, output and error on the console.
+    pub fn new() -> Self {
+        Self {
+            files: vec![Some(Arc::new(File::Console)); 3],
+        }
+    }
+
+    pub fn get(&self, fd: usize) -> Option<Arc<File>> {
+        self.files.get(fd)?.clone()
+    }
+
+    /// Put `file` at the lowest free fd, returns `None` if there are too many open.
+    pub fn insert(&mut self, file: File) -> Option<usize> {
+        let file = Some(Arc::new(file));
+        match self.files.iter().position(Option::is_none) {
+            Some(fd) => {
+                self.files[fd] = file;
+                Some(fd)
+            }
+            None if self.files.len() < MAX_FILES => {
+                self.files.push(file);
+                Some(self.files.len() - 1)
+            }
+            None => None,
+        }
+    }
+
+    /// Take the file at `fd` out of the table. It is closed once it is dropped, which has to
+    /// wait until the table is no longer borrowed, closing may send signals.
+    pub fn remove(&mut self, fd: usize) -> Option<Arc<File>> {
+        self.files.get_mut(fd)?.take()
+    }
+
+    /// Take every file out of the table, see `remove`.
+    pub fn take_all(&mut self) -> Vec<Option<Arc<File>>> {
+        core::mem::take(&mut self.files)
+    }
+}
@@ -9,6 +9,9 @@ mod board;
 
 mod configs;
 mod console;
+mod device_tree;
+mod drivers;
+mod file;
 mod kernel_heap;
 mod memory;
 mod process;
@@ -16,6 +19,7 @@ mod sbi;
 mod sync;
 mod syscall;
 mod timer;
+mod tty;
 mod utils;
 
 use crate::kernel_address::*;
@@ -25,12 +29,7 @@ global_asm!(include_str!("entry.s"));
 global_asm!(include_str!("link_app.s"));
 
 #[no_mangle]
-pub fn rust_main(hartid: usize) -> ! {
-    if hartid != 0 {
-        unsafe {
-            riscv::asm::wfi();
-        }
-    }
+pub fn rust_main(hartid: usize, dtb: usize) -> ! {
     info!("RUSCV OS Booting on hart: {}", hartid);
     info!(".text [{:#x}, {:#x})", stext as usize, etext as usize);
     info!(".rodata [{:#x}, {:#x})", srodata as usize, erodata as usize);
@@ -50,9 +49,21 @@ pub fn rust_main(hartid: usize) -> ! {
     kernel_heap::init();
     kernel_heap::test();
 
+    device_tree::init(dtb);
+
     memory::init();
     memory::test();
 
+    process::kernel_trap::set_trap_entry();
+    process::kernel_trap::kernel_trap_test();
+
+    drivers::init(hartid);
+    memory::swap::init();
+    tty::init();
+
+    process::kthread::kthread_test();
+    sync::sync_test();
+
     process::enable_timer_interrupt();
     timer::set_next_trigger();
 
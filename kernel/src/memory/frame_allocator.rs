@@ -1,11 +1,15 @@
 use super::{
     address::{PhysAddr, PhysPageNum},
-    MEMORY_END, PAGE_SIZE,
+    swap, PAGE_SIZE,
 };
 use crate::{
-    kernel_address::{ekernel, skernel}, sync::UPSafeCell, info, trace
+    device_tree::machine,
+    info,
+    kernel_address::{ekernel, skernel},
+    sync::UPSafeCell,
+    trace,
 };
-use alloc::vec::Vec;
+use alloc::{collections::BTreeSet, vec::Vec};
 
 pub struct PageFrame {
     pub(crate) ppn: PhysPageNum,
@@ -30,19 +34,59 @@ impl Drop for PageFrame {
     }
 }
 
-static FRAME_ALLOCATOR: UPSafeCell<StackFrameAllocator> =
-    unsafe { UPSafeCell::new(StackFrameAllocator::new()) };
+/// `2^order` physically contiguous frames, released together on drop.
+pub struct ContiguousFrames {
+    pub(crate) ppn: PhysPageNum,
+    order: usize,
+}
+
+impl ContiguousFrames {
+    pub fn page_count(&self) -> usize {
+        1 << self.order
+    }
+    pub fn get_bytes_mut(&self) -> &mut [u8] {
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                PhysAddr::from(self.ppn).0 as *mut u8,
+                self.page_count() * PAGE_SIZE,
+            )
+        }
+    }
+}
+
+impl Drop for ContiguousFrames {
+    fn drop(&mut self) {
+        trace!("recycling ppn: {:?}, order {}", self.ppn, self.order);
+        FRAME_ALLOCATOR
+            .get_mut()
+            .dealloc_contiguous(self.ppn, self.order)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FrameStats {
+    pub total: usize,
+    pub free: usize,
+    pub used: usize,
+}
+
+static FRAME_ALLOCATOR: UPSafeCell<BuddyFrameAllocator> =
+    unsafe { UPSafeCell::new(BuddyFrameAllocator::new()) };
 
 pub trait FrameAllocator {
     fn alloc(&mut self) -> Option<PageFrame>;
     fn dealloc(&mut self, ppn: PhysPageNum);
+    fn alloc_contiguous(&mut self, order: usize) -> Option<ContiguousFrames>;
+    fn dealloc_contiguous(&mut self, ppn: PhysPageNum, order: usize);
+    fn stats(&self) -> FrameStats;
 }
 
 pub fn init_frame_allocator() {
-    FRAME_ALLOCATOR.get_mut().init(
-        PhysAddr::from(ekernel as usize).ceil(),
-        PhysAddr::from(MEMORY_END).floor(),
-    );
+    for (start, end) in machine().usable_memory(ekernel as usize) {
+        FRAME_ALLOCATOR
+            .get_mut()
+            .add_pages(PhysAddr::from(start).ceil(), PhysAddr::from(end).floor());
+    }
 }
 
 #[allow(unused)]
@@ -54,62 +98,200 @@ pub fn recycle_kernel_frames() {
     )
 }
 
-struct StackFrameAllocator {
-    begin: PhysPageNum,
-    end: PhysPageNum,
-    recycled: Vec<PhysPageNum>,
+pub const MAX_ORDER: usize = 16;
+
+/// One bit per frame in `[base, base + len)`, set while the frame is not free.
+struct FrameBitmap {
+    base: PhysPageNum,
+    len: usize,
+    bits: Vec<u64>,
 }
-impl StackFrameAllocator {
+
+impl FrameBitmap {
     const fn new() -> Self {
         Self {
-            begin: PhysPageNum(0),
-            end: PhysPageNum(0),
-            recycled: vec![],
+            base: PhysPageNum(0),
+            len: 0,
+            bits: Vec::new(),
+        }
+    }
+    fn end(&self) -> PhysPageNum {
+        PhysPageNum(self.base.0 + self.len)
+    }
+    /// Grow to cover `[begin, end)`, frames that were not covered yet are marked used.
+    fn cover(&mut self, begin: PhysPageNum, end: PhysPageNum) {
+        if self.len != 0 && self.base <= begin && end <= self.end() {
+            return;
+        }
+        let (begin, end) = match self.len {
+            0 => (begin, end),
+            _ => (begin.min(self.base), end.max(self.end())),
+        };
+        let mut covered = Self {
+            base: begin,
+            len: end.0 - begin.0,
+            bits: vec![u64::MAX; (end.0 - begin.0 + 63) / 64],
+        };
+        for ppn in self.base..self.end() {
+            if !self.is_used(ppn) {
+                covered.set(ppn, false);
+            }
         }
+        *self = covered;
     }
+    fn is_used(&self, ppn: PhysPageNum) -> bool {
+        match ppn.0.checked_sub(self.base.0) {
+            Some(idx) if idx < self.len => self.bits[idx / 64] & 1 << (idx % 64) != 0,
+            _ => true,
+        }
+    }
+    fn set(&mut self, ppn: PhysPageNum, used: bool) {
+        let idx = ppn.0 - self.base.0;
+        if used {
+            self.bits[idx / 64] |= 1 << (idx % 64);
+        } else {
+            self.bits[idx / 64] &= !(1 << (idx % 64));
+        }
+    }
+}
+
+/// Free blocks of `2^order` frames aligned to their size, split on alloc and merged with
+/// their buddy on dealloc.
+struct BuddyFrameAllocator {
+    free_lists: [BTreeSet<usize>; MAX_ORDER],
+    used: FrameBitmap,
+    total: usize,
+    free: usize,
+}
 
-    pub fn init(&mut self, begin: PhysPageNum, end: PhysPageNum) {
-        self.begin = begin;
-        self.end = end;
+impl BuddyFrameAllocator {
+    const fn new() -> Self {
+        Self {
+            free_lists: [const { BTreeSet::new() }; MAX_ORDER],
+            used: FrameBitmap::new(),
+            total: 0,
+            free: 0,
+        }
     }
+
     pub fn add_pages(&mut self, begin: PhysPageNum, end: PhysPageNum) {
-        for ppn in begin..end {
-            self.recycled.push(ppn)
+        if begin >= end {
+            return;
+        }
+        self.used.cover(begin, end);
+        let mut start = begin.0;
+        while start < end.0 {
+            // largest aligned block starting at `start` which fits into the range
+            let align = match start {
+                0 => usize::MAX,
+                _ => 1 << start.trailing_zeros(),
+            };
+            let fit = 1 << (usize::BITS - 1 - (end.0 - start).leading_zeros());
+            let size = align.min(fit).min(1 << (MAX_ORDER - 1));
+            for ppn in start..start + size {
+                self.used.set(PhysPageNum(ppn), false);
+            }
+            self.free_lists[size.trailing_zeros() as usize].insert(start);
+            start += size;
+        }
+        self.total += end.0 - begin.0;
+        self.free += end.0 - begin.0;
+    }
+    fn alloc_block(&mut self, order: usize) -> Option<PhysPageNum> {
+        let found = (order..MAX_ORDER).find(|&k| !self.free_lists[k].is_empty())?;
+        let ppn = self.free_lists[found].pop_first().unwrap();
+        // hand the upper halves back while splitting down to `order`
+        for k in order..found {
+            self.free_lists[k].insert(ppn + (1 << k));
+        }
+        for frame in ppn..ppn + (1 << order) {
+            self.used.set(PhysPageNum(frame), true);
         }
+        self.free -= 1 << order;
+        Some(PhysPageNum(ppn))
+    }
+    fn dealloc_block(&mut self, ppn: PhysPageNum, order: usize) {
+        for frame in ppn.0..ppn.0 + (1 << order) {
+            let managed = self.used.base.0 <= frame && frame < self.used.end().0;
+            if !managed || !self.used.is_used(PhysPageNum(frame)) {
+                panic!("deallocing page {} is not allocated yet", frame)
+            }
+            self.used.set(PhysPageNum(frame), false);
+        }
+        self.free += 1 << order;
+        let (mut ppn, mut order) = (ppn.0, order);
+        while order < MAX_ORDER - 1 && self.free_lists[order].remove(&(ppn ^ (1 << order))) {
+            ppn &= !(1 << order);
+            order += 1;
+        }
+        self.free_lists[order].insert(ppn);
     }
 }
-impl FrameAllocator for StackFrameAllocator {
+
+impl FrameAllocator for BuddyFrameAllocator {
     fn alloc(&mut self) -> Option<PageFrame> {
-        let frame = if let Some(ppn) = self.recycled.pop() {
-            Some(PageFrame::new(ppn))
-        } else {
-            if self.begin != self.end {
-                self.begin.0 += 1;
-                Some(PageFrame::new((self.begin.0 - 1).into()))
-            } else {
-                None
-            }
-        };
+        let frame = self.alloc_block(0).map(PageFrame::new);
         frame.and_then(|frame| {
             frame.get_bytes_array_mut().fill(0);
             Some(frame)
         })
     }
     fn dealloc(&mut self, ppn: PhysPageNum) {
-        if ppn >= self.begin || self.recycled.iter().find(|r| ppn == **r).is_some() {
-            panic!("deallocing page {} is not allocated yes", ppn.0)
-        }
-        self.recycled.push(ppn);
+        self.dealloc_block(ppn, 0);
         trace!("{} recycled!", ppn.0);
     }
+    fn alloc_contiguous(&mut self, order: usize) -> Option<ContiguousFrames> {
+        if order >= MAX_ORDER {
+            return None;
+        }
+        let frames = self
+            .alloc_block(order)
+            .map(|ppn| ContiguousFrames { ppn, order });
+        frames.and_then(|frames| {
+            frames.get_bytes_mut().fill(0);
+            Some(frames)
+        })
+    }
+    fn dealloc_contiguous(&mut self, ppn: PhysPageNum, order: usize) {
+        self.dealloc_block(ppn, order);
+        trace!("{}..{} recycled!", ppn.0, ppn.0 + (1 << order));
+    }
+    fn stats(&self) -> FrameStats {
+        FrameStats {
+            total: self.total,
+            free: self.free,
+            used: self.total - self.free,
+        }
+    }
 }
 
 pub fn frame_alloc() -> Option<PageFrame> {
-    FRAME_ALLOCATOR.get_mut().alloc()
+    let frame = FRAME_ALLOCATOR.get_mut().alloc();
+    frame.or_else(|| {
+        // out of frames, push cold user pages to swap until one is released
+        while swap::reclaim() {
+            if let Some(frame) = FRAME_ALLOCATOR.get_mut().alloc() {
+                return Some(frame);
+            }
+        }
+        None
+    })
+}
+
+/// Allocate `2^order` physically contiguous frames, e.g. for DMA buffers or huge pages.
+///
+/// Swap is not tried here, evicting single pages rarely frees a whole block.
+pub fn frame_alloc_contiguous(order: usize) -> Option<ContiguousFrames> {
+    FRAME_ALLOCATOR.get_mut().alloc_contiguous(order)
+}
+
+pub fn frame_stats() -> FrameStats {
+    FRAME_ALLOCATOR.get().stats()
 }
 
 #[allow(unused)]
 pub fn frame_allocator_test() {
+    let before = frame_stats();
     {
         let mut v: Vec<PageFrame> = vec![];
         for i in 0..5 {
@@ -121,6 +303,20 @@ pub fn frame_allocator_test() {
             let frame = frame_alloc().unwrap();
             v.push(frame);
         }
+        assert_eq!(frame_stats().used, before.used + 5);
+    }
+    {
+        let single = frame_alloc().unwrap();
+        let block = frame_alloc_contiguous(3).unwrap();
+        assert_eq!(block.ppn.0 % block.page_count(), 0);
+        let block_ppns = block.ppn.0..block.ppn.0 + block.page_count();
+        assert!(!block_ppns.contains(&single.ppn.0));
+        assert_eq!(frame_stats().used, before.used + 1 + 8);
     }
-    info!("frame_allocator_test passed!");
+    let after = frame_stats();
+    assert_eq!(after.free, before.free);
+    info!(
+        "frame_allocator_test passed! {}/{} frames free",
+        after.free, after.total
+    );
 }
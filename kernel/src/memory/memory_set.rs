@@ -2,17 +2,19 @@ extern crate alloc;
 
 use core::{arch::asm, slice};
 
-use alloc::{collections::BTreeMap, vec::Vec};
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
 use bitflags::bitflags;
 use riscv::register::satp;
 
 use crate::{
+    configs::MMIO,
+    device_tree::machine,
     info,
     kernel_address::{
         bstack, ebss, edata, ekernel, erodata, etext, sbss, sdata, srodata, stext, strampoline,
         tstack,
     },
-    memory::{KERNEL_SPACE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE},
+    memory::{trap_ctx_position, user_stack_position, KERNEL_SPACE, MMAP_BASE, TRAMPOLINE},
     trace,
 };
 
@@ -20,13 +22,15 @@ use super::{
     address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum},
     frame_allocator::{frame_alloc, PageFrame},
     page_table::{PTEFlags, PageTable},
-    MEMORY_END, PAGE_SIZE,
+    shared_memory::SharedMemory,
+    swap, PAGE_SIZE,
 };
 
 #[allow(unused)]
 pub enum SegmentType {
     Framed,
     Linear(usize),
+    Shared(Arc<SharedMemory>),
 }
 
 bitflags! {
@@ -38,6 +42,13 @@ bitflags! {
     }
 }
 
+/// What backs a user page which is not mapped yet.
+#[derive(Clone, Copy, PartialEq)]
+pub enum LazyPage {
+    Zero,
+    Swapped(usize),
+}
+
 struct Segment {
     start: VirtPageNum,
     end: VirtPageNum,
@@ -93,7 +104,7 @@ impl Segment {
     }
     fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
         let flags = PTEFlags::from_bits(self.seg_perm.bits).unwrap();
-        let ppn = match self.seg_type {
+        let ppn = match &self.seg_type {
             SegmentType::Framed => {
                 let frame = frame_alloc().unwrap();
                 let ppn = frame.ppn;
@@ -101,18 +112,43 @@ impl Segment {
                 ppn
             }
             SegmentType::Linear(offset) => PhysPageNum(vpn.0 - offset),
+            SegmentType::Shared(shm) => shm.ppn(vpn.0 - self.start.0),
         };
         page_table.map(vpn, ppn, flags);
     }
     fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
         match self.seg_type {
             SegmentType::Framed => {
-                self.data_frames.remove(&vpn);
+                if self.data_frames.remove(&vpn).is_none() {
+                    match page_table.swapped_slot(vpn) {
+                        Some(slot) => swap::free_slot(slot),
+                        // never touched since it is mapped lazily
+                        None => return,
+                    }
+                }
             }
-            SegmentType::Linear(_) => {}
+            SegmentType::Linear(_) | SegmentType::Shared(_) => {}
         }
         page_table.unmap(vpn)
     }
+    fn populate(&mut self, page_table: &mut PageTable, vpn: VirtPageNum, frame: PageFrame) {
+        match page_table.swapped_slot(vpn) {
+            Some(_) => page_table.swap_in(vpn, frame.ppn),
+            None => page_table.map(
+                vpn,
+                frame.ppn,
+                PTEFlags::from_bits(self.seg_perm.bits).unwrap(),
+            ),
+        }
+        self.data_frames.insert(vpn, frame);
+    }
+    fn contains(&self, vpn: VirtPageNum) -> bool {
+        self.start <= vpn && vpn < self.end
+    }
+    /// Only private user pages can be swapped out.
+    fn swappable(&self) -> bool {
+        matches!(self.seg_type, SegmentType::Framed) && self.seg_perm.contains(SegmentPermission::U)
+    }
 }
 
 pub struct MemorySet {
@@ -121,18 +157,10 @@ pub struct MemorySet {
 }
 
 impl MemorySet {
-    // /// Include sections in elf and trampoline and TrapContext and user stack,
-    // /// also returns user_sp and entry point.
+    // /// Include sections in elf and trampoline and the TrapContext and user stack of the
+    // /// first thread, also returns the base of the user stacks and entry point.
     pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
-        let mut memory_set = Self::new();
-        // map trampoline
-        memory_set.map_trampoline(
-            KERNEL_SPACE
-                .get()
-                .translate(VirtAddr::from(strampoline as usize))
-                .expect("text seg should be mapped!")
-                .floor(),
-        );
+        let mut memory_set = Self::new_user();
         // map program headers of elf, with U flag
         let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
         let elf_header = elf.header;
@@ -158,51 +186,50 @@ impl MemorySet {
                 }
                 let seg = Segment::new(start_va, end_va, SegmentType::Framed, map_perm);
                 max_end_vpn = seg.end;
-                memory_set.push(
+                memory_set.push_lazy(
                     seg,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
+                    &elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize],
                 );
-                trace!(
-                    "map [0x{:x}, 0x{:x}) to [0x{:x}, 0x{:x})",
-                    start_va.0,
-                    end_va.0,
-                    memory_set.translate(start_va).unwrap().floor().0,
-                    memory_set.translate(end_va).unwrap().ceil().0,
-                )
+                trace!("map [0x{:x}, 0x{:x})", start_va.0, end_va.0)
             }
         }
-        // map user stack with U flags
+        // the user stacks start after a guard page
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.into();
-        // guard page
-        user_stack_bottom += PAGE_SIZE;
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
-        memory_set.push(
-            Segment::new(
-                user_stack_bottom.into(),
-                user_stack_top.into(),
-                SegmentType::Framed,
-                SegmentPermission::R | SegmentPermission::W | SegmentPermission::U,
-            ),
-            None,
-        );
-        // map TrapContext
-        memory_set.push(
-            Segment::new(
-                TRAP_CONTEXT.into(),
-                TRAMPOLINE.into(),
-                SegmentType::Framed,
-                SegmentPermission::R | SegmentPermission::W,
-            ),
-            None,
-        );
+        let user_stack_base = max_end_va.0 + PAGE_SIZE;
+        memory_set.map_thread(user_stack_base, 0);
         (
             memory_set,
-            user_stack_top,
+            user_stack_base,
             elf.header.pt2.entry_point() as usize,
         )
     }
 
+    /// A copy of the user segments for a forked process, shared memory stays shared. The
+    /// private pages are left out, the caller copies in the returned ones with `populate`.
+    pub fn fork_layout(&self) -> (Self, Vec<VirtPageNum>) {
+        let mut memory_set = Self::new_user();
+        let mut pages = Vec::new();
+        for seg in self.segments.iter() {
+            let seg_type = match &seg.seg_type {
+                SegmentType::Framed => SegmentType::Framed,
+                SegmentType::Linear(offset) => SegmentType::Linear(*offset),
+                SegmentType::Shared(shm) => SegmentType::Shared(shm.clone()),
+            };
+            let copy = Segment::new(seg.start.into(), seg.end.into(), seg_type, seg.seg_perm);
+            match copy.seg_type {
+                SegmentType::Framed => {
+                    pages.extend((seg.start..seg.end).filter(|vpn| {
+                        seg.data_frames.contains_key(vpn)
+                            || self.page_table.swapped_slot(*vpn).is_some()
+                    }));
+                    memory_set.segments.push(copy);
+                }
+                _ => memory_set.push(copy, None),
+            }
+        }
+        (memory_set, pages)
+    }
+
     pub fn new_kernel() -> Self {
         let mut kernel = MemorySet::new();
         kernel.push(
@@ -249,16 +276,43 @@ impl MemorySet {
             ),
             None,
         );
-        trace!("kernel heap: [{:x}, {:x})", ekernel as usize, MEMORY_END);
-        kernel.push(
-            Segment::new(
-                (ekernel as usize).into(),
-                (MEMORY_END as usize).into(),
-                SegmentType::Linear(0),
-                SegmentPermission::R | SegmentPermission::W,
-            ),
-            None,
-        );
+        for &(start, end) in machine().memory.iter() {
+            let start = start.max(ekernel as usize);
+            if start >= end {
+                continue;
+            }
+            trace!("physical memory: [{:x}, {:x})", start, end);
+            kernel.push(
+                Segment::new(
+                    start.into(),
+                    end.into(),
+                    SegmentType::Linear(0),
+                    SegmentPermission::R | SegmentPermission::W,
+                ),
+                None,
+            );
+        }
+        let machine = machine();
+        let devices = machine
+            .devices
+            .iter()
+            .flat_map(|device| device.regs.iter())
+            .map(|&(start, size)| (start, start + size))
+            .filter(|&(start, end)| {
+                // reserved memory nodes may carry a compatible as well
+                !machine
+                    .memory
+                    .iter()
+                    .any(|&(ram_start, ram_end)| start < ram_end && ram_start < end)
+            });
+        for (start, end) in MMIO
+            .iter()
+            .map(|&(start, size)| (start, start + size))
+            .chain(devices)
+        {
+            kernel.push_io(start.into(), end.into());
+        }
+
         // trampoline page need to be manually configured.
         kernel.map_trampoline(
             kernel
@@ -290,6 +344,191 @@ impl MemorySet {
         );
     }
 
+    /// Unmap the segment starting at `start` and free its frames.
+    pub fn remove_seg(&mut self, start: VirtAddr) -> bool {
+        let vpn = start.floor();
+        match self.segments.iter().position(|seg| seg.start == vpn) {
+            Some(idx) => {
+                self.segments.remove(idx).unmap(&mut self.page_table);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn push_linear(&mut self, start: VirtAddr, end: VirtAddr, seg_perm: SegmentPermission) {
+        self.push(
+            Segment::new(start, end, SegmentType::Linear(0), seg_perm),
+            None,
+        );
+    }
+
+    /// Identity map device registers in `[start, end)` as R|W, pages mapped before are kept.
+    pub fn push_io(&mut self, start: VirtAddr, end: VirtAddr) {
+        let (mut vpn, end) = (start.floor(), end.ceil());
+        while vpn < end {
+            let run = vpn;
+            while vpn < end && self.page_table.get_pte(vpn).is_none() {
+                vpn.0 += 1;
+            }
+            if run < vpn {
+                trace!(
+                    "mmio: [{:x}, {:x})",
+                    VirtAddr::from(run).0,
+                    VirtAddr::from(vpn).0
+                );
+                self.push_linear(
+                    run.into(),
+                    vpn.into(),
+                    SegmentPermission::R | SegmentPermission::W,
+                );
+            }
+            while vpn < end && self.page_table.get_pte(vpn).is_some() {
+                vpn.0 += 1;
+            }
+        }
+    }
+
+    /// Map every page of `shm` at a free address above `MMAP_BASE`.
+    pub fn attach_shared(
+        &mut self,
+        shm: Arc<SharedMemory>,
+        seg_perm: SegmentPermission,
+    ) -> VirtAddr {
+        let len = shm.page_count() * PAGE_SIZE;
+        let start: VirtAddr = self.find_free_area(len).into();
+        self.push(
+            Segment::new(
+                start,
+                (start.0 + len).into(),
+                SegmentType::Shared(shm),
+                seg_perm,
+            ),
+            None,
+        );
+        start
+    }
+
+    pub fn detach_shared(&mut self, start: VirtAddr) -> bool {
+        let vpn = start.floor();
+        match self
+            .segments
+            .iter()
+            .position(|seg| seg.start == vpn && matches!(seg.seg_type, SegmentType::Shared(_)))
+        {
+            Some(idx) => {
+                self.segments.remove(idx).unmap(&mut self.page_table);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Release all segments of an exited process, the page table itself is kept
+    /// until the `MemorySet` is dropped.
+    pub fn recycle_data_pages(&mut self) {
+        for mut seg in self.segments.drain(..) {
+            seg.unmap(&mut self.page_table);
+        }
+    }
+
+    /// Pages of user memory backed by frames right now.
+    pub fn resident_pages(&self) -> usize {
+        self.segments
+            .iter()
+            .filter(|seg| seg.seg_perm.contains(SegmentPermission::U))
+            .map(|seg| match seg.seg_type {
+                SegmentType::Framed => seg.data_frames.len(),
+                SegmentType::Shared(_) => seg.end.0 - seg.start.0,
+                SegmentType::Linear(_) => 0,
+            })
+            .sum()
+    }
+
+    /// Whether `vpn` is shared memory, which stays at the same frames while attached.
+    pub fn is_shared(&self, vpn: VirtPageNum) -> bool {
+        self.segments
+            .iter()
+            .any(|seg| seg.contains(vpn) && matches!(seg.seg_type, SegmentType::Shared(_)))
+    }
+
+    /// Tell how a fault at `vpn` can be resolved, `None` if the page is not a lazy one.
+    pub fn lazy_page(&self, vpn: VirtPageNum) -> Option<LazyPage> {
+        self.segments
+            .iter()
+            .find(|seg| seg.swappable() && seg.contains(vpn))
+            .filter(|seg| !seg.data_frames.contains_key(&vpn))
+            .map(|_| match self.page_table.swapped_slot(vpn) {
+                Some(slot) => LazyPage::Swapped(slot),
+                None => LazyPage::Zero,
+            })
+    }
+
+    /// Back the lazy page `vpn` with `frame`, which already holds its content.
+    pub fn populate(&mut self, vpn: VirtPageNum, frame: PageFrame) {
+        self.segments
+            .iter_mut()
+            .find(|seg| seg.contains(vpn))
+            .expect("populating a page out of any segment")
+            .populate(&mut self.page_table, vpn, frame)
+    }
+
+    /// Copy the page `vpn` into `dst`, returns false if it is not resident.
+    pub fn copy_page(&self, vpn: VirtPageNum, dst: &mut [u8; PAGE_SIZE]) -> bool {
+        let frame = self
+            .segments
+            .iter()
+            .find(|seg| seg.contains(vpn))
+            .and_then(|seg| seg.data_frames.get(&vpn));
+        match frame {
+            Some(frame) => {
+                dst.copy_from_slice(frame.get_bytes_array_mut());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Pick a resident swappable page from `hand` on, clearing accessed bits on the way.
+    pub fn clock_victim(&mut self, hand: VirtPageNum) -> Option<VirtPageNum> {
+        let mut segments: Vec<&Segment> =
+            self.segments.iter().filter(|seg| seg.swappable()).collect();
+        segments.sort_by_key(|seg| seg.start);
+        for seg in segments {
+            for &vpn in seg.data_frames.range(hand..).map(|(vpn, _)| vpn) {
+                if !self.page_table.test_and_clear_accessed(vpn) {
+                    return Some(vpn);
+                }
+            }
+        }
+        None
+    }
+
+    /// Mark `vpn` as living in swap `slot`, the caller writes the returned frame out.
+    pub fn swap_out(&mut self, vpn: VirtPageNum, slot: usize) -> PageFrame {
+        let frame = self
+            .segments
+            .iter_mut()
+            .find(|seg| seg.contains(vpn))
+            .and_then(|seg| seg.data_frames.remove(&vpn))
+            .expect("swapping out a page not resident");
+        self.page_table.swap_out(vpn, slot);
+        frame
+    }
+
+    fn find_free_area(&self, len: usize) -> VirtPageNum {
+        let pages = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+        let mut start = VirtAddr::from(MMAP_BASE).floor();
+        while let Some(seg) = self
+            .segments
+            .iter()
+            .find(|seg| seg.start.0 < start.0 + pages && start < seg.end)
+        {
+            start = seg.end;
+        }
+        start
+    }
+
     pub fn token(&self) -> usize {
         self.page_table.token()
     }
@@ -302,8 +541,32 @@ impl MemorySet {
         self.page_table.translate_user(va, expect)
     }
 
-    pub fn trap_ctx(&self) -> Option<PhysAddr> {
-        self.translate(VirtAddr::from(TRAP_CONTEXT))
+    pub fn trap_ctx(&self, slot: usize) -> Option<PhysAddr> {
+        self.translate(VirtAddr::from(trap_ctx_position(slot)))
+    }
+
+    /// Map the user stack and the TrapContext of the thread in `slot`, returns the stack top.
+    pub fn map_thread(&mut self, user_stack_base: usize, slot: usize) -> usize {
+        let (bottom, top) = user_stack_position(user_stack_base, slot);
+        self.push_empty_seg(
+            bottom.into(),
+            top.into(),
+            SegmentPermission::R | SegmentPermission::W | SegmentPermission::U,
+        );
+        let trap_ctx = trap_ctx_position(slot);
+        self.push_empty_seg(
+            trap_ctx.into(),
+            (trap_ctx + PAGE_SIZE).into(),
+            SegmentPermission::R | SegmentPermission::W,
+        );
+        top
+    }
+
+    /// Undo `map_thread` once the thread in `slot` exited.
+    pub fn unmap_thread(&mut self, user_stack_base: usize, slot: usize) {
+        let (bottom, _) = user_stack_position(user_stack_base, slot);
+        self.remove_seg(bottom.into());
+        self.remove_seg(trap_ctx_position(slot).into());
     }
 
     fn map_trampoline(&mut self, trampoline_ppn: PhysPageNum) {
@@ -321,6 +584,19 @@ impl MemorySet {
         }
     }
 
+    /// An empty user address space, with only the trampoline mapped.
+    fn new_user() -> Self {
+        let mut memory_set = Self::new();
+        memory_set.map_trampoline(
+            KERNEL_SPACE
+                .get()
+                .translate(VirtAddr::from(strampoline as usize))
+                .expect("text seg should be mapped!")
+                .floor(),
+        );
+        memory_set
+    }
+
     fn push(&mut self, mut segment: Segment, data: Option<&[u8]>) {
         segment.map(&mut self.page_table);
         if let Some(data) = data {
@@ -329,6 +605,19 @@ impl MemorySet {
         self.segments.push(segment);
     }
 
+    /// Like `push`, but only pages holding `data` are backed now, the rest are zero filled
+    /// by the page fault handler once touched.
+    fn push_lazy(&mut self, mut segment: Segment, data: &[u8]) {
+        let data_end = VirtAddr::from(VirtAddr::from(segment.start).0 + data.len()).ceil();
+        for vpn in segment.start..data_end {
+            segment.map_one(&mut self.page_table, vpn);
+        }
+        if !data.is_empty() {
+            segment.copy_data(data);
+        }
+        self.segments.push(segment);
+    }
+
     #[inline(always)]
     fn activate(&self) {
         let satp = self.token();
@@ -2,10 +2,13 @@ pub mod address;
 pub mod frame_allocator;
 pub mod memory_set;
 mod page_table;
+pub mod shared_memory;
+pub mod swap;
 
 use crate::{error, sync::UPSafeCell};
 use alloc::sync::Arc;
-use memory_set::MemorySet;
+use core::arch::asm;
+use memory_set::{LazyPage, MemorySet};
 
 pub use self::{address::VirtAddr, page_table::PTEFlags};
 
@@ -15,24 +18,105 @@ const PAGE_SIZE_BITS: usize = 12;
 const PTE_SIZE: usize = 8;
 const PPN_WIDTH: usize = PA_WIDTH - PAGE_SIZE_BITS;
 const VPN_WIDTH: usize = VA_WIDTH - PAGE_SIZE_BITS;
-const PAGE_SIZE: usize = 1 << PAGE_SIZE_BITS;
+pub const PAGE_SIZE: usize = 1 << PAGE_SIZE_BITS;
 const PTE_PER_PAGE: usize = PAGE_SIZE / PTE_SIZE;
-pub const MEMORY_END: usize = 0x8080_0000;
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
 pub const USER_STACK_SIZE: usize = 4096 * 2;
-pub const KERNEL_STACK_SIZE: usize = 4096;
+// two pages, as kernel traps may nest on top of a syscall
+pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
+pub const MMAP_BASE: usize = 0x10_0000_0000;
 
 pub fn kernel_stack_position(app_id: usize) -> (usize, usize) {
     let top = TRAMPOLINE - app_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
     let bottom = top - KERNEL_STACK_SIZE;
     (bottom, top)
 }
+
+/// Trap context page of the thread in `slot`, slots count down from `TRAP_CONTEXT`.
+pub fn trap_ctx_position(slot: usize) -> usize {
+    TRAP_CONTEXT - slot * PAGE_SIZE
+}
+
+/// User stack of the thread in `slot`, the stacks count up from `base` with a guard page
+/// between them.
+pub fn user_stack_position(base: usize, slot: usize) -> (usize, usize) {
+    let bottom = base + slot * (USER_STACK_SIZE + PAGE_SIZE);
+    (bottom, bottom + USER_STACK_SIZE)
+}
 lazy_static::lazy_static! {
     pub static ref KERNEL_SPACE: Arc<UPSafeCell<MemorySet>> =
         Arc::new(unsafe { UPSafeCell::new(MemorySet::new_kernel()) });
 }
 
+/// Make the device registers at physical `[pa, pa + size)` accessible to the kernel and
+/// return the virtual address they are mapped at.
+///
+/// Ranges from the board configuration and the device tree are mapped at boot already, this
+/// is for devices found later on.
+pub fn ioremap(pa: usize, size: usize) -> usize {
+    KERNEL_SPACE
+        .get_mut()
+        .push_io(pa.into(), (pa + size).into());
+    unsafe { asm!("sfence.vma") };
+    pa
+}
+
+/// How a page fault was resolved.
+pub enum PageFault {
+    /// a zeroed frame was mapped
+    Minor,
+    /// the page was read back from swap
+    Major,
+}
+
+/// Back a lazy user page of `space` with a zeroed frame or its content from swap, `None` if
+/// the fault cannot be resolved.
+pub fn handle_page_fault(space: &Arc<UPSafeCell<MemorySet>>, va: VirtAddr) -> Option<PageFault> {
+    let vpn = va.floor();
+    let lazy = space.get().lazy_page(vpn)?;
+    // allocate before borrowing the space, so pages of it can be reclaimed as well
+    let frame = frame_allocator::frame_alloc()?;
+    let fault = match lazy {
+        LazyPage::Swapped(slot) => {
+            swap::swap_in(slot, frame.get_bytes_array_mut());
+            PageFault::Major
+        }
+        LazyPage::Zero => PageFault::Minor,
+    };
+    let mut space = space.get_mut();
+    // another thread may have resolved it while this one slept for the frame or the read
+    if space.lazy_page(vpn) != Some(lazy) {
+        return Some(PageFault::Minor);
+    }
+    space.populate(vpn, frame);
+    if let LazyPage::Swapped(slot) = lazy {
+        swap::free_slot(slot);
+    }
+    Some(fault)
+}
+
+/// A copy of the user memory of `space` for a forked process, `None` if memory ran out.
+pub fn fork_space(space: &Arc<UPSafeCell<MemorySet>>) -> Option<MemorySet> {
+    let (mut child, pages) = space.get().fork_layout();
+    for vpn in pages {
+        loop {
+            // allocate before borrowing the space, so pages of it can be reclaimed as well
+            let frame = frame_allocator::frame_alloc()?;
+            if space.get().copy_page(vpn, frame.get_bytes_array_mut()) {
+                child.populate(vpn, frame);
+                break;
+            }
+            // swapped out, read it back in first. It may also be gone with a thread which
+            // exited meanwhile, and its slot is not handed down anyway.
+            if handle_page_fault(space, vpn.into()).is_none() {
+                break;
+            }
+        }
+    }
+    Some(child)
+}
+
 pub fn init() {
     frame_allocator::init_frame_allocator();
     lazy_static::initialize(&KERNEL_SPACE);
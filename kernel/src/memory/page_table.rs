@@ -28,6 +28,9 @@ pub struct PageTableEntry {
     pub bits: usize,
 }
 
+/// RSW bit marking an invalid leaf whose page lives in swap, the slot takes the PPN field.
+const PTE_SWAPPED: usize = 1 << 8;
+
 impl PageTableEntry {
     pub fn new(ppn: PhysPageNum, flags: PTEFlags) -> Self {
         PageTableEntry {
@@ -37,6 +40,11 @@ impl PageTableEntry {
     pub fn empty() -> Self {
         PageTableEntry { bits: 0 }
     }
+    pub fn swapped(slot: usize, flags: PTEFlags) -> Self {
+        PageTableEntry {
+            bits: slot << 10 | PTE_SWAPPED | (flags - PTEFlags::V).bits as usize,
+        }
+    }
     pub fn ppn(&self) -> PhysPageNum {
         (self.bits >> 10 & ((1usize << 44) - 1)).into()
     }
@@ -58,6 +66,12 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    pub fn is_swapped(&self) -> bool {
+        !self.is_valid() && self.bits & PTE_SWAPPED != 0
+    }
+    pub fn swap_slot(&self) -> usize {
+        self.bits >> 10
+    }
 }
 
 struct PageTableFrame {
@@ -72,6 +86,9 @@ impl PageTableFrame {
     fn get(&self, idx: usize) -> &'static PageTableEntry {
         return &self.get_pte_array_mut()[idx];
     }
+    fn get_mut(&self, idx: usize) -> &'static mut PageTableEntry {
+        &mut self.get_pte_array_mut()[idx]
+    }
     fn map(&mut self, idx: usize, ppn: PhysPageNum, mode: PTEFlags) {
         self.mc += 1;
         let pte = &mut self.get_pte_array_mut()[idx];
@@ -126,7 +143,7 @@ impl PageTable {
         let idx = idxs[iidx];
         let sub_pte = self.frames.get(&ppn).unwrap().get(idx);
         assert!(
-            sub_pte.is_valid(),
+            sub_pte.is_valid() || sub_pte.is_swapped(),
             "vpn {:?} is invalid before unmapping",
             vpn.0
         );
@@ -138,6 +155,43 @@ impl PageTable {
         }
     }
 
+    /// Replace a mapped leaf with a swapped one, the entry still counts as used.
+    pub fn swap_out(&mut self, vpn: VirtPageNum, slot: usize) {
+        let pte = self.find_leaf(vpn).filter(|pte| pte.is_valid()).unwrap();
+        *pte = PageTableEntry::swapped(slot, pte.flags());
+    }
+    pub fn swap_in(&mut self, vpn: VirtPageNum, ppn: PhysPageNum) {
+        let pte = self.find_leaf(vpn).filter(|pte| pte.is_swapped()).unwrap();
+        *pte = PageTableEntry::new(ppn, pte.flags() | PTEFlags::V);
+    }
+    pub fn swapped_slot(&self, vpn: VirtPageNum) -> Option<usize> {
+        self.find_leaf(vpn)
+            .filter(|pte| pte.is_swapped())
+            .map(|pte| pte.swap_slot())
+    }
+    /// Clear the accessed bit of a mapped leaf, returning whether it was set.
+    pub fn test_and_clear_accessed(&mut self, vpn: VirtPageNum) -> bool {
+        match self.find_leaf(vpn).filter(|pte| pte.is_valid()) {
+            Some(pte) if pte.flags().contains(PTEFlags::A) => {
+                pte.bits &= !(PTEFlags::A.bits as usize);
+                true
+            }
+            _ => false,
+        }
+    }
+    fn find_leaf(&self, vpn: VirtPageNum) -> Option<&'static mut PageTableEntry> {
+        let idxs = vpn.indexes();
+        let mut ppn = self.root;
+        for &idx in &idxs[..3] {
+            let pte = self.frames.get(&ppn)?.get(idx);
+            if !pte.is_valid() {
+                return None;
+            }
+            ppn = pte.ppn();
+        }
+        self.frames.get(&ppn).map(|frame| frame.get_mut(idxs[3]))
+    }
+
     pub fn token(&self) -> usize {
         9usize << 60 | self.root.0
     }
@@ -0,0 +1,104 @@
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+
+use super::{
+    address::PhysPageNum,
+    frame_allocator::{frame_alloc, PageFrame},
+    PAGE_SIZE,
+};
+use crate::{sync::UPSafeCell, trace};
+
+pub const IPC_PRIVATE: usize = 0;
+pub const IPC_CREAT: usize = 0o1000;
+pub const IPC_EXCL: usize = 0o2000;
+
+lazy_static::lazy_static! {
+    static ref SHM_MANAGER: UPSafeCell<ShmManager> = unsafe {
+        UPSafeCell::new(ShmManager::new())
+    };
+}
+
+/// A set of physical frames which can be mapped into several `MemorySet`s.
+///
+/// Every attached segment holds an `Arc` to the object and the manager holds one more
+/// until the id is removed, so frames are released after the last of them is gone.
+pub struct SharedMemory {
+    frames: Vec<PageFrame>,
+}
+
+impl SharedMemory {
+    fn new(size: usize) -> Option<Self> {
+        let pages = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+        let mut frames = Vec::with_capacity(pages);
+        for _ in 0..pages {
+            frames.push(frame_alloc()?);
+        }
+        Some(Self { frames })
+    }
+    pub fn page_count(&self) -> usize {
+        self.frames.len()
+    }
+    pub fn ppn(&self, idx: usize) -> PhysPageNum {
+        self.frames[idx].ppn
+    }
+}
+
+struct ShmEntry {
+    key: usize,
+    object: Arc<SharedMemory>,
+}
+
+struct ShmManager {
+    next_id: usize,
+    objects: BTreeMap<usize, ShmEntry>,
+}
+
+impl ShmManager {
+    fn new() -> Self {
+        Self {
+            next_id: 1,
+            objects: BTreeMap::new(),
+        }
+    }
+    fn get(&mut self, key: usize, size: usize, flags: usize) -> Option<usize> {
+        if key != IPC_PRIVATE {
+            if let Some((&id, entry)) = self.objects.iter().find(|(_, e)| e.key == key) {
+                if flags & (IPC_CREAT | IPC_EXCL) == IPC_CREAT | IPC_EXCL
+                    || size > entry.object.page_count() * PAGE_SIZE
+                {
+                    return None;
+                }
+                return Some(id);
+            }
+            if flags & IPC_CREAT == 0 {
+                return None;
+            }
+        }
+        if size == 0 {
+            return None;
+        }
+        let object = Arc::new(SharedMemory::new(size)?);
+        let id = self.next_id;
+        self.next_id += 1;
+        trace!("shm {} created with {} pages", id, object.page_count());
+        self.objects.insert(id, ShmEntry { key, object });
+        Some(id)
+    }
+}
+
+/// Look up the object of `key`, creating one of `size` bytes if `IPC_CREAT` is set.
+pub fn shm_get(key: usize, size: usize, flags: usize) -> Option<usize> {
+    SHM_MANAGER.get_mut().get(key, size, flags)
+}
+
+pub fn shm_object(id: usize) -> Option<Arc<SharedMemory>> {
+    SHM_MANAGER
+        .get()
+        .objects
+        .get(&id)
+        .map(|entry| entry.object.clone())
+}
+
+/// Remove the id, frames stay alive until every attached segment is detached.
+pub fn shm_remove(id: usize) -> bool {
+    SHM_MANAGER.get_mut().objects.remove(&id).is_some()
+}
@@ -0,0 +1,208 @@
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::arch::asm;
+
+use super::{
+    address::{PhysAddr, PhysPageNum, VirtPageNum},
+    frame_allocator::PageFrame,
+    memory_set::MemorySet,
+    PAGE_SIZE,
+};
+use crate::{
+    drivers::{block_device, BlockDevice, BLOCK_SIZE},
+    info,
+    sync::UPSafeCell,
+    trace, warn,
+};
+
+const BLOCKS_PER_SLOT: usize = PAGE_SIZE / BLOCK_SIZE;
+
+lazy_static::lazy_static! {
+    static ref SWAP_MANAGER: UPSafeCell<SwapManager> = unsafe {
+        UPSafeCell::new(SwapManager::new())
+    };
+}
+
+/// Pages of registered address spaces are picked by a clock (second chance) hand which
+/// walks the spaces one after another.
+struct SwapManager {
+    device: Option<Arc<dyn BlockDevice>>,
+    /// a set bit is a used slot, or one past the end of the device
+    slots: Vec<u64>,
+    count: usize,
+    spaces: Vec<Weak<UPSafeCell<MemorySet>>>,
+    hand: (usize, VirtPageNum),
+    /// slots being written, the page is still in its frame until that is done
+    writeback: BTreeMap<usize, Writeback>,
+}
+
+struct Writeback {
+    ppn: PhysPageNum,
+    /// the slot was freed meanwhile, the writer frees it once it is done
+    freed: bool,
+}
+
+impl SwapManager {
+    fn new() -> Self {
+        Self {
+            device: None,
+            slots: Vec::new(),
+            count: 0,
+            spaces: Vec::new(),
+            hand: (0, VirtPageNum(0)),
+            writeback: BTreeMap::new(),
+        }
+    }
+    fn alloc_slot(&mut self) -> Option<usize> {
+        let (idx, bits) = self
+            .slots
+            .iter_mut()
+            .enumerate()
+            .find(|(_, bits)| **bits != u64::MAX)?;
+        let bit = bits.trailing_ones() as usize;
+        *bits |= 1 << bit;
+        Some(idx * 64 + bit)
+    }
+    fn free_slot(&mut self, slot: usize) {
+        let bits = &mut self.slots[slot / 64];
+        assert!(
+            *bits & 1 << (slot % 64) != 0,
+            "swap slot {} is not used",
+            slot
+        );
+        *bits &= !(1 << (slot % 64));
+    }
+}
+
+pub fn init() {
+    let device = match block_device() {
+        Some(device) => device,
+        None => {
+            warn!("swap is disabled");
+            return;
+        }
+    };
+    let mut manager = SWAP_MANAGER.get_mut();
+    let count = device.num_blocks() / BLOCKS_PER_SLOT;
+    manager.slots = vec![u64::MAX; (count + 63) / 64];
+    for slot in 0..count {
+        manager.slots[slot / 64] &= !(1 << (slot % 64));
+    }
+    manager.count = count;
+    manager.device = Some(device);
+    info!("swap enabled with {} slots", count);
+}
+
+/// Make pages of `space` candidates for reclaim.
+pub fn register(space: &Arc<UPSafeCell<MemorySet>>) {
+    SWAP_MANAGER.get_mut().spaces.push(Arc::downgrade(space));
+}
+
+/// Write one cold user page out and release its frame, returns false if nothing could go.
+///
+/// Spaces borrowed by the caller are skipped, so allocate frames for a space before
+/// borrowing it where it should be able to evict its own pages. The caller sleeps while the
+/// page is written unless it has a cell borrowed.
+pub fn reclaim() -> bool {
+    let (device, slot, frame) = match evict() {
+        Some(evicted) => evicted,
+        None => return false,
+    };
+    device.write_blocks(slot * BLOCKS_PER_SLOT, frame.get_bytes_array_mut());
+    let mut manager = SWAP_MANAGER.get_mut();
+    let writeback = manager.writeback.remove(&slot).unwrap();
+    if writeback.freed {
+        manager.free_slot(slot);
+    }
+    true
+}
+
+/// Take a cold page out of its space and give it a slot, the caller writes the returned frame
+/// to it.
+fn evict() -> Option<(Arc<dyn BlockDevice>, usize, PageFrame)> {
+    let mut manager = SWAP_MANAGER.get_mut();
+    let device = manager.device.clone()?;
+    manager.spaces.retain(|space| space.strong_count() > 0);
+    if manager.spaces.is_empty() {
+        return None;
+    }
+    // the first visit of a space may only clear accessed bits, the second one finds a victim
+    for _ in 0..=2 * manager.spaces.len() {
+        let (idx, hand) = manager.hand;
+        let idx = idx % manager.spaces.len();
+        let space = match manager.spaces[idx].upgrade() {
+            Some(space) => space,
+            None => continue,
+        };
+        let slot = manager.alloc_slot()?;
+        let evicted = space.try_get_mut().and_then(|mut mem_set| {
+            let vpn = mem_set.clock_victim(hand)?;
+            Some((vpn, mem_set.swap_out(vpn, slot)))
+        });
+        match evicted {
+            Some((vpn, frame)) => {
+                trace!("swap out vpn {:#x} to slot {}", vpn.0, slot);
+                let writeback = Writeback {
+                    ppn: frame.ppn,
+                    freed: false,
+                };
+                manager.writeback.insert(slot, writeback);
+                manager.hand = (idx, VirtPageNum(vpn.0 + 1));
+                unsafe { asm!("sfence.vma") };
+                return Some((device, slot, frame));
+            }
+            None => {
+                manager.free_slot(slot);
+                manager.hand = (idx + 1, VirtPageNum(0));
+            }
+        }
+    }
+    None
+}
+
+/// Read the page in `slot` back, the slot stays used until `free_slot`.
+pub fn swap_in(slot: usize, buf: &mut [u8; PAGE_SIZE]) {
+    let device = {
+        let manager = SWAP_MANAGER.get();
+        trace!("swap in slot {}", slot);
+        if let Some(writeback) = manager.writeback.get(&slot) {
+            // it is not on the device yet
+            let page = unsafe { PhysAddr::from(writeback.ppn).get_mut::<[u8; PAGE_SIZE]>() };
+            buf.copy_from_slice(page.unwrap());
+            return;
+        }
+        manager
+            .device
+            .clone()
+            .expect("page is swapped without a swap device")
+    };
+    device.read_blocks(slot * BLOCKS_PER_SLOT, buf);
+}
+
+pub fn free_slot(slot: usize) {
+    let mut manager = SWAP_MANAGER.get_mut();
+    match manager.writeback.get_mut(&slot) {
+        Some(writeback) => writeback.freed = true,
+        None => manager.free_slot(slot),
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SwapStats {
+    /// in pages, both 0 if there is no swap device
+    pub total: usize,
+    pub free: usize,
+}
+
+pub fn stats() -> SwapStats {
+    let manager = SWAP_MANAGER.get();
+    let used: u32 = manager.slots.iter().map(|bits| bits.count_ones()).sum();
+    let padding = manager.slots.len() * 64 - manager.count;
+    SwapStats {
+        total: manager.count,
+        free: manager.count - (used as usize - padding),
+    }
+}
@@ -0,0 +1,119 @@
+//! Deadlock detection over the mutexes and semaphores of a process, with the safety check of
+//! the banker's algorithm.
+//!
+//! Allocations and requests are always tracked, so detection can be turned on at any time,
+//! but only checked once it is. A semaphore used to signal rather than to count resources
+//! looks like a deadlock to it, as nothing holds what the waiter needs.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Resource {
+    Mutex(usize),
+    Semaphore(usize),
+}
+
+/// What a thread holds and what it waits for, by resource.
+#[derive(Default)]
+struct Claims {
+    allocation: BTreeMap<Resource, usize>,
+    need: BTreeMap<Resource, usize>,
+}
+
+pub struct DeadlockDetector {
+    enabled: bool,
+    available: BTreeMap<Resource, usize>,
+    /// by tid
+    claims: BTreeMap<usize, Claims>,
+}
+
+impl DeadlockDetector {
+    pub const fn new() -> Self {
+        Self {
+            enabled: false,
+            available: BTreeMap::new(),
+            claims: BTreeMap::new(),
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn add_resource(&mut self, res: Resource, count: usize) {
+        self.available.insert(res, count);
+    }
+
+    /// Thread `tid` is about to wait for one of `res`. Returns false and forgets about the
+    /// request if detection is on and waiting could leave some thread waiting forever.
+    pub fn request(&mut self, tid: usize, res: Resource) -> bool {
+        let claims = self.claims.entry(tid).or_default();
+        *claims.need.entry(res).or_default() += 1;
+        if self.enabled && !self.is_safe() {
+            self.take_one(tid, res, false);
+            return false;
+        }
+        true
+    }
+
+    /// Thread `tid` got the `res` it requested.
+    pub fn grant(&mut self, tid: usize, res: Resource) {
+        self.take_one(tid, res, false);
+        let claims = self.claims.entry(tid).or_default();
+        *claims.allocation.entry(res).or_default() += 1;
+        *self.available.entry(res).or_default() -= 1;
+    }
+
+    /// Thread `tid` gave one of `res` back, or added one if it did not hold any, as with a
+    /// semaphore.
+    pub fn release(&mut self, tid: usize, res: Resource) {
+        self.take_one(tid, res, true);
+        *self.available.entry(res).or_default() += 1;
+    }
+
+    /// Take one of `res` off what `tid` holds, or off what it needs.
+    fn take_one(&mut self, tid: usize, res: Resource, allocated: bool) {
+        let claims = match self.claims.get_mut(&tid) {
+            Some(claims) => claims,
+            None => return,
+        };
+        let counts = match allocated {
+            true => &mut claims.allocation,
+            false => &mut claims.need,
+        };
+        if let Some(count) = counts.get_mut(&res) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&res);
+            }
+        }
+        if claims.allocation.is_empty() && claims.need.is_empty() {
+            self.claims.remove(&tid);
+        }
+    }
+
+    /// Whether every thread can get what it waits for in some order, each giving back what
+    /// it holds once it got it.
+    fn is_safe(&self) -> bool {
+        let mut work = self.available.clone();
+        let mut finished = BTreeSet::new();
+        loop {
+            let next = self.claims.iter().find(|(tid, claims)| {
+                !finished.contains(*tid)
+                    && claims
+                        .need
+                        .iter()
+                        .all(|(res, n)| work.get(res).copied().unwrap_or(0) >= *n)
+            });
+            let (tid, claims) = match next {
+                Some(next) => next,
+                None => break,
+            };
+            for (res, n) in claims.allocation.iter() {
+                *work.entry(*res).or_default() += n;
+            }
+            finished.insert(*tid);
+        }
+        finished.len() == self.claims.len()
+    }
+}
@@ -0,0 +1,49 @@
+//! Kernel objects a process refers to by index.
+
+use alloc::{sync::Arc, vec::Vec};
+
+use super::deadlock::DeadlockDetector;
+use crate::sync::{Condvar, Mutex, Semaphore};
+
+/// Ids count up from 0 and are never reused, objects live as long as their process.
+pub struct HandleTable<T> {
+    objects: Vec<Arc<T>>,
+}
+
+impl<T> HandleTable<T> {
+    pub const fn new() -> Self {
+        Self {
+            objects: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, object: Arc<T>) -> usize {
+        self.objects.push(object);
+        self.objects.len() - 1
+    }
+
+    pub fn get(&self, id: usize) -> Option<Arc<T>> {
+        self.objects.get(id).cloned()
+    }
+}
+
+/// The synchronization objects shared by the threads of a process.
+pub struct SyncHandles {
+    /// user space locks hold no data, a locked one has its guard forgotten
+    pub mutexes: HandleTable<Mutex<()>>,
+    pub semaphores: HandleTable<Semaphore>,
+    pub condvars: HandleTable<Condvar>,
+    /// mirrors who holds and waits for the mutexes and semaphores
+    pub deadlock: DeadlockDetector,
+}
+
+impl SyncHandles {
+    pub const fn new() -> Self {
+        Self {
+            mutexes: HandleTable::new(),
+            semaphores: HandleTable::new(),
+            condvars: HandleTable::new(),
+            deadlock: DeadlockDetector::new(),
+        }
+    }
+}
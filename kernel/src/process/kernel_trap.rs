@@ -0,0 +1,125 @@
+//! Traps taken while the kernel itself is running.
+
+use core::arch::global_asm;
+use riscv::register::{
+    scause::{self, Interrupt, Trap},
+    sip, stval, stvec,
+    utvec::TrapMode,
+};
+
+use super::{handle_timer_interrupt, suspend_current};
+use crate::{
+    drivers::handle_external_interrupt,
+    info, print, println,
+    sync::preempt::{set_need_resched, take_need_resched},
+};
+
+global_asm!(include_str!("kernel_trap.s"));
+extern "C" {
+    fn __kernel_trap();
+    fn __copy_user(dst: *mut u8, src: *const u8, len: usize) -> usize;
+    fn sfixup();
+    fn efixup();
+}
+
+const REG_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+/// Registers of the interrupted kernel code, pushed on its stack by `__kernel_trap`.
+#[repr(C)]
+struct KernelTrapFrame {
+    x: [usize; 32],
+    sstatus: usize,
+    sepc: usize,
+}
+
+/// An instruction which may fault, and where to continue if it does.
+#[repr(C)]
+struct FixupEntry {
+    inst: usize,
+    fixup: usize,
+}
+
+fn find_fixup(pc: usize) -> Option<usize> {
+    let len = (efixup as usize - sfixup as usize) / core::mem::size_of::<FixupEntry>();
+    let table = unsafe { core::slice::from_raw_parts(sfixup as *const FixupEntry, len) };
+    table
+        .iter()
+        .find(|entry| entry.inst == pc)
+        .map(|entry| entry.fixup)
+}
+
+pub fn set_trap_entry() {
+    unsafe {
+        stvec::write(__kernel_trap as usize, TrapMode::Direct);
+    }
+}
+
+#[no_mangle]
+fn kernel_trap_handler(frame: &mut KernelTrapFrame) {
+    match scause::read().cause() {
+        Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            if handle_timer_interrupt() {
+                set_need_resched();
+            }
+        }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => handle_external_interrupt(),
+        Trap::Interrupt(Interrupt::SupervisorSoft) => unsafe { sip::clear_ssoft() },
+        cause @ Trap::Exception(_) => match find_fixup(frame.sepc) {
+            Some(fixup) => frame.sepc = fixup,
+            None => fatal(frame, cause),
+        },
+        cause => fatal(frame, cause),
+    }
+    // interrupts only come in while no cell is borrowed, so this is a safe point
+    if take_need_resched() {
+        suspend_current();
+    }
+}
+
+fn fatal(frame: &KernelTrapFrame, cause: Trap) -> ! {
+    println!(
+        "[kernel] unhandled {:?} in kernel, scause = {:#x}, stval = {:#x}, sepc = {:#x}",
+        cause,
+        scause::read().bits(),
+        stval::read(),
+        frame.sepc
+    );
+    println!("sstatus = {:#x}", frame.sstatus);
+    for (names, regs) in REG_NAMES.chunks(4).zip(frame.x.chunks(4)) {
+        for (name, reg) in names.iter().zip(regs) {
+            print_reg(name, *reg);
+        }
+        println!("");
+    }
+    panic!("fatal trap in kernel");
+}
+
+fn print_reg(name: &str, val: usize) {
+    print!("{:>4} = {:#018x}  ", name, val);
+}
+
+/// Copy `len` bytes where either side may be unmapped, a fault makes it fail instead of
+/// bringing the kernel down.
+pub unsafe fn copy_user(dst: *mut u8, src: *const u8, len: usize) -> Result<(), ()> {
+    match __copy_user(dst, src, len) {
+        0 => Ok(()),
+        _ => Err(()),
+    }
+}
+
+#[allow(unused)]
+pub fn kernel_trap_test() {
+    let mut buf = [0u8; 8];
+    let src = [1u8; 8];
+    unsafe {
+        assert!(copy_user(buf.as_mut_ptr(), src.as_ptr(), buf.len()).is_ok());
+        assert_eq!(buf, src);
+        // nothing is mapped at the first page
+        assert!(copy_user(buf.as_mut_ptr(), core::ptr::null(), buf.len()).is_err());
+    }
+    info!("kernel_trap_test passed!");
+}
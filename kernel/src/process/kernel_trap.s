@@ -0,0 +1,67 @@
+.altmacro
+.macro SAVE_GP n
+    sd x\n, \n*8(sp)
+.endm
+.macro LOAD_GP n
+    ld x\n, \n*8(sp)
+.endm
+
+    .section .text
+    .globl __kernel_trap
+    .align 2
+__kernel_trap:
+    # a KernelTrapFrame is pushed onto the current kernel stack
+    addi sp, sp, -34*8
+    sd x1, 1*8(sp)
+    # save x3~x31, sp(x2) is saved below
+    .set n, 3
+    .rept 29
+        SAVE_GP %n
+        .set n, n+1
+    .endr
+    addi t0, sp, 34*8
+    sd t0, 2*8(sp)
+    csrr t0, sstatus
+    csrr t1, sepc
+    sd t0, 32*8(sp)
+    sd t1, 33*8(sp)
+    mv a0, sp
+    call kernel_trap_handler
+    # the handler may have changed sepc or registers, e.g. to run a fixup
+    ld t0, 32*8(sp)
+    ld t1, 33*8(sp)
+    csrw sstatus, t0
+    csrw sepc, t1
+    ld x1, 1*8(sp)
+    .set n, 3
+    .rept 29
+        LOAD_GP %n
+        .set n, n+1
+    .endr
+    addi sp, sp, 34*8
+    sret
+
+    .globl __copy_user
+    .align 2
+# a0: dst, a1: src, a2: len; returns 0, or 1 if a fault hit either side
+__copy_user:
+    beqz a2, 2f
+.Lcopy_load:
+    lb t0, 0(a1)
+.Lcopy_store:
+    sb t0, 0(a0)
+    addi a0, a0, 1
+    addi a1, a1, 1
+    addi a2, a2, -1
+    bnez a2, .Lcopy_load
+2:
+    li a0, 0
+    ret
+.Lcopy_fault:
+    li a0, 1
+    ret
+
+    .section .fixup_table, "a"
+    .balign 8
+    .dword .Lcopy_load, .Lcopy_fault
+    .dword .Lcopy_store, .Lcopy_fault
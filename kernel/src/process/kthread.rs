@@ -0,0 +1,46 @@
+//! Kernel threads: a closure on its own kernel stack in the kernel address space, scheduled
+//! like any process but never entering user mode.
+
+use alloc::{boxed::Box, sync::Arc};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use riscv::register::sstatus;
+
+use super::{
+    exit_current, get_current_process, suspend_current, ProcessControlBlock, PROCESS_MANAGER,
+};
+use crate::info;
+
+/// Start a kernel thread running `f`, it exits with code 0 once `f` returns.
+pub fn spawn<F>(f: F) -> Arc<ProcessControlBlock>
+where
+    F: FnOnce() + Send + 'static,
+{
+    let pcb = ProcessControlBlock::new_kthread(Box::new(f));
+    PROCESS_MANAGER.add(pcb.clone());
+    pcb
+}
+
+/// The first `__switch` to a kernel thread returns here, on its fresh stack.
+pub(super) fn kthread_entry() -> ! {
+    let entry = get_current_process().inner.get_mut().kthread_entry.take();
+    // switched to with interrupts off, a process would turn them on by `sret`
+    unsafe { sstatus::set_sie() };
+    entry.expect("kernel thread started twice")();
+    exit_current(0)
+}
+
+#[allow(unused)]
+pub fn kthread_test() {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let worker = spawn(|| {
+        for _ in 0..3 {
+            COUNTER.fetch_add(1, Ordering::Relaxed);
+            suspend_current();
+        }
+    });
+    spawn(move || {
+        assert_eq!(worker.wait_exit(), 0);
+        assert_eq!(COUNTER.load(Ordering::Relaxed), 3);
+        info!("kthread_test passed!");
+    });
+}
@@ -1,12 +1,30 @@
+pub mod deadlock;
+mod handle_table;
+pub mod kernel_trap;
+pub mod kthread;
 mod process_control_block;
+mod scheduler;
+pub mod signal;
 mod status;
+mod thread_group;
+mod usage;
+mod wait_queue;
+use self::scheduler::{DefaultScheduler, Scheduler};
+use self::signal::{SIGILL, SIGSEGV};
 use self::status::ProcessStatus;
-use crate::timer::set_next_trigger;
+use crate::timer::{
+    check_timers, get_time_us, set_idle_trigger, set_next_trigger, start_time_slice,
+    time_slice_expired,
+};
 use crate::{
+    drivers::handle_external_interrupt,
     error,
-    memory::{TRAMPOLINE, TRAP_CONTEXT},
+    memory::TRAMPOLINE,
     sbi::shutdown,
-    sync::UPSafeCell,
+    sync::{
+        preempt::{clear_need_resched, preempt_disable, preempt_enable, without_interrupts},
+        UPSafeCell,
+    },
     syscall::{syscall, MAX_MSG_LEN},
     trace,
 };
@@ -23,6 +41,8 @@ use riscv::register::{
 };
 
 pub use crate::process::process_control_block::ProcessControlBlock;
+pub use crate::process::thread_group::{ThreadGroup, WaitEvent, WaitOptions};
+pub use crate::process::wait_queue::WaitQueue;
 
 global_asm!(include_str!("switch.s"));
 extern "C" {
@@ -61,6 +81,12 @@ lazy_static::lazy_static! {
         }
         v
     };
+    /// Whether each app starts at boot, or only by `exec`. See build.rs.
+    static ref APP_AT_BOOT: Vec<bool> = {
+        extern "C" { fn _app_at_boot();}
+        let start = _app_at_boot as *const u8;
+        (0..get_num_app()).map(|i| unsafe { start.add(i).read_volatile() } != 0).collect()
+    };
     static ref APP_BINS: Vec<&'static [u8]> = {
         let num_apps = get_num_app();
                 extern "C" {
@@ -98,6 +124,10 @@ struct ProcessManager {
 struct ProcessManagerInner {
     current: usize,
     load: VecDeque<Arc<ProcessControlBlock>>,
+    /// context of the idle loop, which runs on the boot stack of this hart
+    idle_ctx: SwitchCtx,
+    /// holds the processes that are ready
+    scheduler: DefaultScheduler,
 }
 
 fn get_num_app() -> usize {
@@ -110,66 +140,155 @@ fn get_num_app() -> usize {
 
 impl ProcessManager {
     unsafe fn new() -> Self {
-        let mut load = APP_BINS
+        let load = APP_BINS
             .iter()
-            .map(|elf| Arc::new(ProcessControlBlock::from_elf(*elf)))
+            .zip(APP_AT_BOOT.iter())
+            .filter(|(_, at_boot)| **at_boot)
+            .map(|(elf, _)| ProcessControlBlock::from_elf(*elf))
             .collect::<VecDeque<_>>();
-        // FIXME: remove initproc & user_shell for now
-        load.pop_back();
-        load.pop_back();
         trace!("bin num: {}", load.len());
+        let mut scheduler = DefaultScheduler::new();
+        for pcb in load.iter() {
+            scheduler.push(pcb.clone(), false);
+        }
         let inner = UPSafeCell::new(ProcessManagerInner {
             current: load.len() - 1,
             load,
+            idle_ctx: SwitchCtx::zero(),
+            scheduler,
         });
         Self { inner }
     }
 
+    /// The idle loop, processes switch back here whenever none of them is ready.
     fn start(&self) -> ! {
-        self.run_next_process(&mut SwitchCtx::zero() as *mut SwitchCtx);
-        unreachable!("unreachable in ProcessManager::start")
+        let idle_ctx = &mut self.inner.get_mut().idle_ctx as *mut SwitchCtx;
+        loop {
+            match self.find_next_ready_task() {
+                Some(pcb) => self.switch_to(idle_ctx, pcb),
+                None if self.should_shutdown() => shutdown(false),
+                None => idle(),
+            }
+        }
     }
 
     pub fn run_next_process(&self, current_ctx: *mut SwitchCtx) {
         match self.find_next_ready_task() {
-            Some(pcb) => {
-                let next_ctx = {
-                    let mut inner = pcb.inner.get_mut();
-                    inner.status = ProcessStatus::Running;
-                    &inner.switch_ctx as *const SwitchCtx
-                };
-                {
-                    let mut inner = self.inner.get_mut();
-                    inner.current = pcb.pid();
-                }
-                unsafe { __switch(current_ctx, next_ctx) }
+            Some(pcb) => self.switch_to(current_ctx, pcb),
+            None => {
+                let idle_ctx = &self.inner.get().idle_ctx as *const SwitchCtx;
+                unsafe { __switch(current_ctx, idle_ctx) }
             }
-            None => shutdown(false),
         }
     }
+
+    /// Run `pcb`, its time slice re-arms the tick which `idle` left to the sleepers alone.
+    fn switch_to(&self, current_ctx: *mut SwitchCtx, pcb: Arc<ProcessControlBlock>) {
+        let slice = self.inner.get().scheduler.time_slice(&pcb);
+        start_time_slice(slice);
+        clear_need_resched();
+        let next_ctx = {
+            let mut inner = pcb.inner.get_mut();
+            inner.status = ProcessStatus::Running;
+            inner.scheduled_at = get_time_us();
+            inner.stamp = inner.scheduled_at;
+            &inner.switch_ctx as *const SwitchCtx
+        };
+        {
+            let mut inner = self.inner.get_mut();
+            inner.current = pcb.tid();
+        }
+        unsafe { __switch(current_ctx, next_ctx) }
+    }
+    /// Power off once every process exited, kernel threads may well run forever.
+    fn should_shutdown(&self) -> bool {
+        let inner = self.inner.get();
+        inner
+            .load
+            .iter()
+            .filter(|pcb| !pcb.is_kernel_thread())
+            .all(|pcb| pcb.inner.get().status == ProcessStatus::Exited)
+    }
     fn find_next_ready_task(&self) -> Option<Arc<ProcessControlBlock>> {
-        let inner = self.inner.get_mut();
-        let current = inner.current;
-        // FIXME: after using ready queue, this loop based finding needs to be deprecated.
-        (current..current + inner.load.len())
-            .map(|idx| inner.load[idx % inner.load.len()].clone())
+        let mut inner = self.inner.get_mut();
+        core::iter::from_fn(|| inner.scheduler.pop())
             .find(|pcb| pcb.inner.get().status == ProcessStatus::Ready)
     }
-    fn mark_current_ready(&self) {
-        let inner = self.inner.get_mut();
-        let mut pcb_inner = inner.load[inner.current - 1].inner.get_mut();
-        pcb_inner.status = ProcessStatus::Ready
+    fn push_ready(&self, pcb: Arc<ProcessControlBlock>, expired: bool) {
+        self.inner.get_mut().scheduler.push(pcb, expired);
+    }
+    fn add(&self, pcb: Arc<ProcessControlBlock>) {
+        let mut inner = self.inner.get_mut();
+        inner.load.push_back(pcb.clone());
+        inner.scheduler.push(pcb, false);
+    }
+    fn mark_current(&self, status: ProcessStatus) {
+        let pcb = self.get_current_process().unwrap();
+        let expired = time_slice_expired();
+        let ran = {
+            let mut pcb_inner = pcb.inner.get_mut();
+            let now = get_time_us();
+            pcb_inner.usage.stime += now - pcb_inner.stamp;
+            match status {
+                ProcessStatus::Ready if expired => pcb_inner.usage.nivcsw += 1,
+                ProcessStatus::Ready | ProcessStatus::Pending => pcb_inner.usage.nvcsw += 1,
+                _ => {}
+            }
+            pcb_inner.status = status;
+            now - pcb_inner.scheduled_at
+        };
+        self.inner.get_mut().scheduler.account(&pcb, ran);
+        match status {
+            ProcessStatus::Ready => self.push_ready(pcb, expired),
+            ProcessStatus::Exited => self.reclaim(),
+            _ => {}
+        }
+    }
+    /// Drop the threads which are done with, they exited and were switched away from for the
+    /// last time, and their process was reaped. Their tids are free again then.
+    fn reclaim(&self) {
+        let reclaimed = {
+            let mut inner = self.inner.get_mut();
+            let current = inner.current;
+            let load = core::mem::take(&mut inner.load);
+            let (reclaimed, load): (Vec<_>, VecDeque<_>) = load.into_iter().partition(|pcb| {
+                pcb.tid() != current
+                    && pcb.inner.get().status == ProcessStatus::Exited
+                    && pcb.group().is_reaped()
+            });
+            inner.load = load;
+            reclaimed
+        };
+        // the last of a process takes its address space with it, which borrows other cells
+        drop(reclaimed);
+    }
+    fn processes(&self) -> Vec<Arc<ThreadGroup>> {
+        let inner = self.inner.get();
+        let mut groups = inner
+            .load
+            .iter()
+            .filter(|pcb| !pcb.is_kernel_thread())
+            .filter(|pcb| pcb.inner.get().status != ProcessStatus::Exited)
+            // the last thread may still be on its way out, closing its files
+            .filter(|pcb| !pcb.group().has_exited())
+            .map(|pcb| pcb.group().clone())
+            .collect::<Vec<_>>();
+        groups.sort_by_key(|group| group.pid());
+        groups.dedup_by_key(|group| group.pid());
+        groups
     }
-    fn mark_current_exited(&self) {
-        let inner = self.inner.get_mut();
-        let mut pcb_inner = inner.load[inner.current - 1].inner.get_mut();
-        pcb_inner.status = ProcessStatus::Exited
+    fn find_thread(&self, tid: usize) -> Option<Arc<ProcessControlBlock>> {
+        let inner = self.inner.get();
+        inner.load.iter().find(|pcb| pcb.tid() == tid).cloned()
     }
     pub fn get_current_process(&self) -> Option<Arc<ProcessControlBlock>> {
         let inner = self.inner.get();
         error!("try get pid: {}", inner.current);
-        // FIXME: this is a slot vec and the pid does not really need to be corresponding to the index
-        inner.load.get(inner.current - 1).map(|pcb| pcb.clone())
+        inner
+            .load
+            .iter()
+            .find(|pcb| pcb.tid() == inner.current)
+            .cloned()
     }
     fn get_current_switch_ctx(&self) -> *mut SwitchCtx {
         let pcb = self.get_current_process().unwrap();
@@ -183,6 +302,7 @@ impl ProcessManager {
 }
 
 #[repr(C)]
+#[derive(Clone)]
 pub struct TrapCtx {
     x: [usize; 32],
     sstatus: Sstatus,
@@ -232,12 +352,17 @@ impl SwitchCtx {
             sp,
         }
     }
+    pub fn kthread(sp: usize) -> Self {
+        Self {
+            ra: kthread::kthread_entry as usize,
+            s: [0; 12],
+            sp,
+        }
+    }
 }
 
 fn set_kernel_trap_entry() {
-    unsafe {
-        stvec::write(trap_from_kernel as usize, TrapMode::Direct);
-    }
+    kernel_trap::set_trap_entry();
 }
 
 fn set_user_trap_entry() {
@@ -254,20 +379,172 @@ pub fn get_current_process() -> Arc<ProcessControlBlock> {
     PROCESS_MANAGER.get_current_process().unwrap()
 }
 
+/// The first thread of process `pid`.
+pub fn find_process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
+    find_thread(pid).filter(|pcb| pcb.pid() == pid)
+}
+
+pub fn find_thread(tid: usize) -> Option<Arc<ProcessControlBlock>> {
+    PROCESS_MANAGER.find_thread(tid)
+}
+
+/// See `ProcessManager::reclaim`.
+fn reclaim() {
+    PROCESS_MANAGER.reclaim()
+}
+
+/// Every user process which has not exited.
+pub fn processes() -> Vec<Arc<ThreadGroup>> {
+    PROCESS_MANAGER.processes()
+}
+
+/// Start a new thread of the current process, see `ProcessControlBlock::clone_thread`.
+pub fn clone_current(stack: usize) -> Arc<ProcessControlBlock> {
+    let pcb = get_current_process().clone_thread(stack);
+    PROCESS_MANAGER.add(pcb.clone());
+    pcb
+}
+
+/// Fork the current process, returns the first thread of the child or `None` if memory ran
+/// out. See `ProcessControlBlock::fork`.
+pub fn fork_current() -> Option<Arc<ProcessControlBlock>> {
+    let pcb = get_current_process().fork()?;
+    PROCESS_MANAGER.add(pcb.clone());
+    Some(pcb)
+}
+
+/// Replace the image of the current process with the app `name`, the other threads exit.
+/// Returns false if there is no such app.
+pub fn exec_current(name: &str) -> bool {
+    let elf = match app_by_name(name) {
+        Some(elf) => elf,
+        None => return false,
+    };
+    let pcb = get_current_process();
+    retire_others(pcb.group(), 0);
+    // they may still be using the old image
+    let alone = pcb.group().threads().iter().all(|thread| {
+        Arc::ptr_eq(thread, &pcb) || thread.exit_waiters.wait_event_killable(|| has_left(thread))
+    });
+    // killed meanwhile, it exits on its way out instead
+    if alone {
+        pcb.exec(elf);
+    }
+    true
+}
+
+/// Leave the CPU with the current process put in `status`, yielding, blocking and exiting
+/// all go through here.
+fn switch_out(status: ProcessStatus) {
+    // an interrupt must not find the context half switched
+    without_interrupts(|| {
+        PROCESS_MANAGER.mark_current(status);
+        PROCESS_MANAGER.run_next_process(PROCESS_MANAGER.get_current_switch_ctx())
+    })
+}
+
 pub fn suspend_current() {
-    PROCESS_MANAGER.mark_current_ready();
-    PROCESS_MANAGER.run_next_process(PROCESS_MANAGER.get_current_switch_ctx())
+    switch_out(ProcessStatus::Ready)
+}
+
+/// Give up the CPU until `wakeup` is called on the current process, mostly through a
+/// `WaitQueue`.
+pub fn block_current() {
+    switch_out(ProcessStatus::Pending)
+}
+
+/// Make a process blocked by `block_current` runnable again.
+pub fn wakeup(pcb: &Arc<ProcessControlBlock>) {
+    let mut inner = pcb.inner.get_mut();
+    if inner.status == ProcessStatus::Pending {
+        inner.status = ProcessStatus::Ready;
+        drop(inner);
+        PROCESS_MANAGER.push_ready(pcb.clone(), false);
+    }
+}
+
+/// Timer interrupt, returns true once the time slice of the current process is used up.
+pub fn handle_timer_interrupt() -> bool {
+    check_timers();
+    let expired = time_slice_expired();
+    set_next_trigger();
+    expired
+}
+
+/// Wait for an interrupt with the scheduler tick stopped, only sleepers program the timer.
+///
+/// There is no current process while idle, so the handlers must not preempt.
+fn idle() {
+    set_idle_trigger();
+    preempt_disable();
+    unsafe {
+        sstatus::set_sie();
+        riscv::asm::wfi();
+        sstatus::clear_sie();
+    }
+    preempt_enable();
+}
+
+/// Hand `exit_code` to the threads joining `pcb` and take it out of its process.
+fn retire(pcb: &Arc<ProcessControlBlock>, exit_code: i32) {
+    pcb.inner.get_mut().exit_code = exit_code;
+    pcb.group().leave(pcb, exit_code);
+    pcb.exit_waiters.wake_all();
+}
+
+/// Whether `pcb` is no longer one of the threads of its process.
+fn has_left(pcb: &Arc<ProcessControlBlock>) -> bool {
+    !pcb.group()
+        .threads()
+        .iter()
+        .any(|thread| Arc::ptr_eq(thread, pcb))
 }
 
-pub fn exit_current() -> ! {
-    PROCESS_MANAGER.mark_current_exited();
-    PROCESS_MANAGER.run_next_process(PROCESS_MANAGER.get_current_switch_ctx());
+/// Exit the current thread, the process exits with it if it is the last one.
+pub fn exit_current(exit_code: i32) -> ! {
+    let pcb = get_current_process();
+    retire(&pcb, exit_code);
+    drop(pcb);
+    switch_out(ProcessStatus::Exited);
     unreachable!("process is exited");
 }
 
-#[no_mangle]
-fn trap_from_kernel() -> ! {
-    panic!("a trap from kernel!");
+/// Have `pcb` exit with `exit_code` on its way back to user mode, it is woken if it is
+/// blocked. A killed thread may still be in the kernel for a while, it gives up the waits
+/// which could take indefinitely and finishes the others, so it lets go of what it holds.
+fn kill(pcb: &Arc<ProcessControlBlock>, exit_code: i32) {
+    without_interrupts(|| {
+        pcb.inner.get_mut().killed.get_or_insert(exit_code);
+        wakeup(pcb);
+    });
+}
+
+/// Exit every thread of `group` but the current one, see `kill`.
+fn retire_others(group: &ThreadGroup, exit_code: i32) {
+    let current = get_current_process();
+    for thread in group.threads() {
+        if !Arc::ptr_eq(&thread, &current) {
+            kill(&thread, exit_code);
+        }
+    }
+}
+
+/// Exit every thread of the current process.
+pub fn exit_group_current(exit_code: i32) -> ! {
+    let group = get_current_process().group().clone();
+    retire_others(&group, exit_code);
+    drop(group);
+    exit_current(exit_code)
+}
+
+/// Exit every thread of the process of `group`, the current one included if it belongs to
+/// it, see `kill`.
+fn kill_group(group: &ThreadGroup, exit_code: i32) {
+    retire_others(group, exit_code);
+    let current = get_current_process();
+    if current.pid() == group.pid() {
+        kill(&current, exit_code);
+    }
 }
 
 #[no_mangle]
@@ -275,19 +552,34 @@ fn trap_from_user() -> ! {
     trace!("trap in");
     set_kernel_trap_entry();
     let mut buf = [0u8; MAX_MSG_LEN];
+    let (cause, stval) = (scause::read().cause(), stval::read());
     let pcb = get_current_process();
+    pcb.enter_kernel();
     let ctx = pcb.trap_ctx();
-    match match scause::read().cause() {
+    match match cause {
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
-            set_next_trigger();
-            suspend_current();
+            if handle_timer_interrupt() {
+                suspend_current();
+            }
+            Ok(())
+        }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            handle_external_interrupt();
             Ok(())
         }
         Trap::Exception(Exception::UserEnvCall) => {
             trace!("user call id: 0x{:x}", ctx.x[17]);
-            let res = syscall(ctx.x[17], [ctx.x[10], ctx.x[11], ctx.x[12]], &mut buf);
+            // syscalls may take long, let interrupts and preemption in
+            unsafe { sstatus::set_sie() };
+            let res = syscall(
+                ctx.x[17],
+                [ctx.x[10], ctx.x[11], ctx.x[12], ctx.x[13]],
+                &mut buf,
+            );
             match res {
                 Ok(len) => {
+                    // exec moves it
+                    let ctx = pcb.trap_ctx();
                     ctx.sepc += 4;
                     ctx.x[10] = len as usize;
                     Ok(())
@@ -295,14 +587,35 @@ fn trap_from_user() -> ! {
                 Err(_) => Err(unsafe { core::str::from_utf8_unchecked(&buf) }),
             }
         }
-        Trap::Exception(Exception::StoreFault) | Trap::Exception(Exception::StorePageFault) => {
-            Err("PageFault in application, kernel killed it.")
+        Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::InstructionPageFault)
+            if pcb.handle_page_fault(stval.into()) =>
+        {
+            Ok(())
+        }
+        Trap::Exception(Exception::StoreFault)
+        | Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::LoadFault)
+        | Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::InstructionPageFault) => {
+            fault(
+                ctx.sepc,
+                SIGSEGV,
+                "PageFault in application, kernel killed it.",
+            );
+            Ok(())
         }
         Trap::Exception(Exception::IllegalInstruction) => {
-            Err("IllegalInstruction in application, kernel killed it.")
+            fault(
+                ctx.sepc,
+                SIGILL,
+                "IllegalInstruction in application, kernel killed it.",
+            );
+            Ok(())
         }
         x @ _ => {
-            panic!("Unsupported trap {:?}, stval = {:#x}!", x, stval::read());
+            panic!("Unsupported trap {:?}, stval = {:#x}!", x, stval);
         }
     } {
         Ok(_) => restore_to_user(),
@@ -310,17 +623,34 @@ fn trap_from_user() -> ! {
     }
 }
 
+/// The current thread raises `sig` for a fault at `inst_addr`, the process dies unless a
+/// handler takes it.
+fn fault(inst_addr: usize, sig: usize, hint: &str) {
+    if !signal::force_current(sig) {
+        report_fail(inst_addr, hint);
+        signal::kill_current(sig);
+    }
+}
+
 fn kernel_fail(inst_addr: usize, hint: &str) -> ! {
+    report_fail(inst_addr, hint);
+    exit_group_current(-1);
+}
+
+fn report_fail(inst_addr: usize, hint: &str) {
     let pid = get_current_process().pid();
     error!("[kernel] {} pid: {}", hint, pid);
     error!("[kernel] instrument at {:#x}", inst_addr);
-
-    exit_current();
 }
 
 fn restore_to_user() -> ! {
+    signal::handle_signals();
+    // traps must not reach the trampoline before we are back in user mode
+    unsafe { sstatus::clear_sie() };
+    get_current_process().leave_kernel();
     set_user_trap_entry();
     let satp = PROCESS_MANAGER.get_current_satp();
+    let trap_ctx_va = get_current_process().trap_ctx_va();
     extern "C" {
         fn __alltraps();
         fn __restore();
@@ -332,7 +662,7 @@ fn restore_to_user() -> ! {
             "fence.i",
             "jr {restore_va}",
             restore_va = in(reg) restore_va,
-            in("a0") TRAP_CONTEXT,
+            in("a0") trap_ctx_va,
             in("a1") satp,
             options(noreturn)
         );
@@ -1,4 +1,13 @@
-use crate::{memory::{address::PhysAddr, memory_set::{MemorySet, SegmentPermission}, *}, process::*, sync::UPSafeCell};
+use crate::{memory::{address::PhysAddr, memory_set::{MemorySet, SegmentPermission}, shared_memory::SharedMemory, swap, *}, process::*, sync::UPSafeCell, timer::get_time_us};
+
+use super::{
+    scheduler::{SchedEntity, MAX_NICE, MIN_NICE, MIN_PRIORITY},
+    thread_group::ThreadGroup,
+    usage::Usage,
+};
+use crate::memory::PageFault;
+use crate::sync::futex::FutexKey;
+use alloc::boxed::Box;
 
 lazy_static::lazy_static! {
     static ref PID_ALLOCATOR: UPSafeCell<PIDAllocator> = unsafe {
@@ -31,7 +40,7 @@ impl PIDAllocator {
     }
 }
 
-struct PID(usize);
+pub(super) struct PID(pub(super) usize);
 
 impl Drop for PID {
     fn drop(&mut self) {
@@ -39,63 +48,319 @@ impl Drop for PID {
     }
 }
 
+/// Kernel stack in the slot of a tid, unmapped again once its thread is dropped.
+struct KernelStack {
+    bottom: usize,
+    top: usize,
+}
+
+impl KernelStack {
+    fn new(pid: usize) -> Self {
+        let (bottom, top) = kernel_stack_position(pid);
+        KERNEL_SPACE.get_mut().push_empty_seg(
+            bottom.into(),
+            top.into(),
+            SegmentPermission::R | SegmentPermission::W,
+        );
+        Self { bottom, top }
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        KERNEL_SPACE.get_mut().remove_seg(self.bottom.into());
+        // the slot goes to the next thread with this tid
+        unsafe { core::arch::asm!("sfence.vma") };
+    }
+}
+
+/// A thread, the unit the scheduler deals with. What it shares with the other threads of its
+/// process lives in the `ThreadGroup`.
 pub struct ProcessControlBlock {
-    pid: PID,
+    tid: Arc<PID>,
+    /// where its user stack and TrapContext are in the address space
+    slot: usize,
+    group: Arc<ThreadGroup>,
+    _kernel_stack: KernelStack,
     pub(super) inner: UPSafeCell<ProcessControlBlockInner>,
+    /// threads in `waittid` on this one
+    pub(super) exit_waiters: WaitQueue,
 }
 // TODO: remove all these pub(super)
 pub(super) struct ProcessControlBlockInner {
     pub(super) status: ProcessStatus,
     pub(super) switch_ctx: SwitchCtx,
-    pub(super) trap_ctx_addr: PhysAddr,
-    pub(super) mem_set: MemorySet,
+    /// `None` for kernel threads, which never return to user mode
+    pub(super) trap_ctx_addr: Option<PhysAddr>,
+    pub(super) scheduled_at: usize,
+    /// start of the current stretch in user mode or in the kernel, in microseconds
+    pub(super) stamp: usize,
+    pub(super) usage: Usage,
+    pub(super) exit_code: i32,
+    pub(super) sched: SchedEntity,
+    /// what a kernel thread runs, taken when it starts
+    pub(super) kthread_entry: Option<Box<dyn FnOnce() + Send>>,
+    /// signals it blocks
+    pub(super) sig_mask: usize,
+    /// signals sent to this thread alone
+    pub(super) sig_pending: usize,
+    /// the exit code it was killed with, it exits on its way back to user mode
+    pub(super) killed: Option<i32>,
 }
 
 impl ProcessControlBlock {
+    /// The pid of its process.
     pub fn pid(&self) -> usize {
-        self.pid.0
+        self.group.pid()
+    }
+    pub fn tid(&self) -> usize {
+        self.tid.0
+    }
+    pub fn group(&self) -> &Arc<ThreadGroup> {
+        &self.group
+    }
+    pub(super) fn slot(&self) -> usize {
+        self.slot
     }
     pub(super) fn trap_ctx(&self) -> &'static mut TrapCtx {
-        unsafe { self.inner.get().trap_ctx_addr.get_mut().unwrap() }
+        let trap_ctx_addr = self.inner.get().trap_ctx_addr;
+        let trap_ctx_addr = trap_ctx_addr.expect("kernel threads have no trap context");
+        unsafe { trap_ctx_addr.get_mut().unwrap() }
+    }
+    /// Where the TrapContext is in the address space of the process.
+    pub(super) fn trap_ctx_va(&self) -> usize {
+        trap_ctx_position(self.slot)
+    }
+    pub fn is_kernel_thread(&self) -> bool {
+        self.inner.get().trap_ctx_addr.is_none()
     }
     pub(super) fn satp(&self) -> usize {
-        self.inner.get().mem_set.token()
+        self.group.mem_set.get().token()
     }
-    pub(super) fn from_elf(elf: &[u8]) -> Self {
-        let pid = PID_ALLOCATOR.get_mut().alloc();
-        let id = pid.0;
-        Self {
-            pid,
-            inner: unsafe { UPSafeCell::new(ProcessControlBlockInner::from_elf(elf, id)) },
+    /// The first thread of a new process running `elf`.
+    pub(super) fn from_elf(elf: &[u8]) -> Arc<Self> {
+        let (mem_set, user_stack_base, entry) = MemorySet::from_elf(elf);
+        let trap_ctx_addr = mem_set.trap_ctx(0).expect("TRAP_CONTEXT should be mapped");
+        let sp = user_stack_position(user_stack_base, 0).1;
+        let mem_set = Arc::new(unsafe { UPSafeCell::new(mem_set) });
+        swap::register(&mem_set);
+        let tid = Arc::new(PID_ALLOCATOR.get_mut().alloc());
+        let group = ThreadGroup::new(tid.clone(), mem_set, user_stack_base);
+        let kernel_stack = KernelStack::new(tid.0);
+        unsafe {
+            *trap_ctx_addr.get_mut().unwrap() =
+                TrapCtx::new_app(entry, sp, KERNEL_SPACE.get().token(), kernel_stack.top);
+        }
+        let inner = ProcessControlBlockInner::new(
+            Some(trap_ctx_addr),
+            SwitchCtx::restore(kernel_stack.top),
+        );
+        Self::new(tid, 0, Arc::new(group), kernel_stack, inner)
+    }
+    /// A kernel thread running `entry` in the kernel address space.
+    pub(super) fn new_kthread(entry: Box<dyn FnOnce() + Send>) -> Arc<Self> {
+        let tid = Arc::new(PID_ALLOCATOR.get_mut().alloc());
+        let group = ThreadGroup::new(tid.clone(), KERNEL_SPACE.clone(), 0);
+        let kernel_stack = KernelStack::new(tid.0);
+        let mut inner = ProcessControlBlockInner::new(None, SwitchCtx::kthread(kernel_stack.top));
+        inner.kthread_entry = Some(entry);
+        Self::new(tid, 0, Arc::new(group), kernel_stack, inner)
+    }
+    /// A new thread of the same process, it returns from the syscall with 0 on the user stack
+    /// at `stack`, or on a stack of its own if that is 0.
+    pub(super) fn clone_thread(&self, stack: usize) -> Arc<Self> {
+        let tid = Arc::new(PID_ALLOCATOR.get_mut().alloc());
+        let kernel_stack = KernelStack::new(tid.0);
+        let (slot, sp, trap_ctx_addr) = self.group.alloc_thread();
+        let trap_ctx = unsafe { trap_ctx_addr.get_mut::<TrapCtx>().unwrap() };
+        *trap_ctx = self.trap_ctx().clone();
+        trap_ctx.x[2] = if stack != 0 { stack } else { sp };
+        trap_ctx.x[10] = 0;
+        trap_ctx.sepc += 4;
+        trap_ctx.kernel_sp = kernel_stack.top;
+        let mut inner = ProcessControlBlockInner::new(
+            Some(trap_ctx_addr),
+            SwitchCtx::restore(kernel_stack.top),
+        );
+        inner.sched.priority = self.inner.get().sched.priority;
+        inner.sched.nice = self.nice();
+        inner.sig_mask = self.inner.get().sig_mask;
+        Self::new(tid, slot, self.group.clone(), kernel_stack, inner)
+    }
+    /// The only thread of a child process with a copy of the memory of this one, it returns
+    /// from the syscall with 0. `None` if memory ran out.
+    pub(super) fn fork(&self) -> Option<Arc<Self>> {
+        let mem_set = crate::memory::fork_space(&self.group.mem_set)?;
+        let trap_ctx_addr = mem_set
+            .trap_ctx(self.slot)
+            .expect("TRAP_CONTEXT should be mapped");
+        let tid = Arc::new(PID_ALLOCATOR.get_mut().alloc());
+        let group = self.group.fork(tid.clone(), mem_set, self.slot);
+        let kernel_stack = KernelStack::new(tid.0);
+        let trap_ctx = unsafe { trap_ctx_addr.get_mut::<TrapCtx>().unwrap() };
+        *trap_ctx = self.trap_ctx().clone();
+        trap_ctx.x[10] = 0;
+        trap_ctx.sepc += 4;
+        trap_ctx.kernel_sp = kernel_stack.top;
+        let mut inner = ProcessControlBlockInner::new(
+            Some(trap_ctx_addr),
+            SwitchCtx::restore(kernel_stack.top),
+        );
+        inner.sched.priority = self.inner.get().sched.priority;
+        inner.sched.nice = self.nice();
+        inner.sig_mask = self.inner.get().sig_mask;
+        Some(Self::new(tid, self.slot, group, kernel_stack, inner))
+    }
+    /// Run `elf` from its entry point, the other threads of the process must be gone. The
+    /// thread keeps its slot.
+    pub(super) fn exec(&self, elf: &[u8]) {
+        let (mut mem_set, user_stack_base, entry) = MemorySet::from_elf(elf);
+        if self.slot != 0 {
+            mem_set.unmap_thread(user_stack_base, 0);
+            mem_set.map_thread(user_stack_base, self.slot);
         }
+        let trap_ctx_addr = mem_set
+            .trap_ctx(self.slot)
+            .expect("TRAP_CONTEXT should be mapped");
+        let sp = user_stack_position(user_stack_base, self.slot).1;
+        let kernel_sp = self.trap_ctx().kernel_sp;
+        self.group.exec(mem_set, user_stack_base);
+        let mut trap_ctx = TrapCtx::new_app(entry, sp, KERNEL_SPACE.get().token(), kernel_sp);
+        // the syscall path steps over the ecall, the new image starts elsewhere
+        trap_ctx.sepc = entry.wrapping_sub(4);
+        unsafe { *trap_ctx_addr.get_mut().unwrap() = trap_ctx };
+        self.inner.get_mut().trap_ctx_addr = Some(trap_ctx_addr);
+    }
+    fn new(
+        tid: Arc<PID>,
+        slot: usize,
+        group: Arc<ThreadGroup>,
+        kernel_stack: KernelStack,
+        inner: ProcessControlBlockInner,
+    ) -> Arc<Self> {
+        let pcb = Arc::new(Self {
+            tid,
+            slot,
+            group,
+            _kernel_stack: kernel_stack,
+            inner: unsafe { UPSafeCell::new(inner) },
+            exit_waiters: WaitQueue::new(),
+        });
+        pcb.group.add_thread(pcb.clone());
+        pcb
     }
     pub fn translate(&self, va: VirtAddr, expect: PTEFlags) -> Result<PhysAddr, ()> {
-        self.inner.get().mem_set.translate_user(va, expect)
+        let mem_set = &self.group.mem_set;
+        let pa = mem_set.get().translate_user(va, expect);
+        // the page may be swapped out or not touched yet
+        pa.or_else(|_| match self.handle_page_fault(va) {
+            true => mem_set.get().translate_user(va, expect),
+            false => Err(()),
+        })
+    }
+    /// Usage of this thread so far, the current stretch counts as kernel time as that is where
+    /// the caller is.
+    pub fn usage(&self) -> Usage {
+        let inner = self.inner.get();
+        let mut usage = inner.usage;
+        if inner.status == ProcessStatus::Running {
+            usage.stime += get_time_us() - inner.stamp;
+        }
+        usage.maxrss = usage.maxrss.max(self.group.mem_set.get().resident_pages());
+        usage
+    }
+    /// The thread trapped into the kernel, the time since it left was spent in user mode.
+    pub(super) fn enter_kernel(&self) {
+        let mut inner = self.inner.get_mut();
+        let now = get_time_us();
+        inner.usage.utime += now - inner.stamp;
+        inner.stamp = now;
+    }
+    /// The thread returns to user mode, the time since it trapped was spent in the kernel.
+    pub(super) fn leave_kernel(&self) {
+        let mut inner = self.inner.get_mut();
+        let now = get_time_us();
+        inner.usage.stime += now - inner.stamp;
+        inner.stamp = now;
+    }
+    /// Weight of this thread under the stride policy, the others ignore it.
+    pub fn set_priority(&self, priority: usize) -> bool {
+        if priority < MIN_PRIORITY {
+            return false;
+        }
+        self.inner.get_mut().sched.priority = priority;
+        true
+    }
+    pub fn nice(&self) -> isize {
+        self.inner.get().sched.nice
+    }
+    /// Only the CFS policy takes the nice value into account, it is clamped to
+    /// `MIN_NICE..=MAX_NICE`.
+    pub fn set_nice(&self, nice: isize) {
+        self.inner.get_mut().sched.nice = nice.clamp(MIN_NICE, MAX_NICE);
+    }
+    /// Whether it was killed and is yet to exit.
+    pub fn is_killed(&self) -> bool {
+        self.inner.get().killed.is_some()
+    }
+    /// Block until this thread exited, then hand out its exit code. Gives up once the caller
+    /// is being killed.
+    pub fn wait_exit(&self) -> i32 {
+        self.exit_waiters
+            .wait_event_killable(|| self.inner.get().status == ProcessStatus::Exited);
+        self.inner.get().exit_code
+    }
+    pub fn handle_page_fault(&self, va: VirtAddr) -> bool {
+        let mem_set = &self.group.mem_set;
+        let fault = match crate::memory::handle_page_fault(mem_set, va) {
+            Some(fault) => fault,
+            None => return false,
+        };
+        let resident = mem_set.get().resident_pages();
+        let mut inner = self.inner.get_mut();
+        match fault {
+            PageFault::Minor => inner.usage.minflt += 1,
+            PageFault::Major => inner.usage.majflt += 1,
+        }
+        inner.usage.maxrss = inner.usage.maxrss.max(resident);
+        true
+    }
+    /// Like `translate`, but fails rather than faulting a page in.
+    pub fn translate_resident(&self, va: VirtAddr, expect: PTEFlags) -> Result<PhysAddr, ()> {
+        self.group.mem_set.get().translate_user(va, expect)
+    }
+    /// Where a futex at `va` is queued: private pages may be swapped out and come back at
+    /// another frame, so only shared memory is keyed by its physical address.
+    pub fn futex_key(&self, va: VirtAddr, pa: PhysAddr) -> FutexKey {
+        let mem_set = &self.group.mem_set;
+        match mem_set.get().is_shared(va.floor()) {
+            true => FutexKey::Shared(pa.0),
+            false => FutexKey::Private(Arc::as_ptr(mem_set) as usize, va.0),
+        }
+    }
+    pub fn attach_shm(&self, shm: Arc<SharedMemory>, perm: SegmentPermission) -> VirtAddr {
+        self.group.mem_set.get_mut().attach_shared(shm, perm)
+    }
+    pub fn detach_shm(&self, va: VirtAddr) -> bool {
+        self.group.mem_set.get_mut().detach_shared(va)
     }
 }
 
 impl ProcessControlBlockInner {
-    fn from_elf(elf: &[u8], task_id: usize) -> Self {
-        let (mem_set, sp, entry) = MemorySet::from_elf(elf);
-        let trap_ctx_addr = mem_set.trap_ctx().expect("TRAP_CONTEXT should be mapped");
-        let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(task_id);
-        {
-            KERNEL_SPACE.get_mut().push_empty_seg(
-                kernel_stack_bottom.into(),
-                kernel_stack_top.into(),
-                SegmentPermission::R | SegmentPermission::W,
-            );
-        }
-        unsafe {
-            *trap_ctx_addr.get_mut().unwrap() =
-                TrapCtx::new_app(entry, sp, KERNEL_SPACE.get().token(), kernel_stack_top);
-        }
-        let switch_ctx = SwitchCtx::restore(kernel_stack_top);
+    fn new(trap_ctx_addr: Option<PhysAddr>, switch_ctx: SwitchCtx) -> Self {
         ProcessControlBlockInner {
             status: ProcessStatus::Ready,
             switch_ctx,
             trap_ctx_addr,
-            mem_set,
+            scheduled_at: 0,
+            stamp: 0,
+            usage: Usage::default(),
+            exit_code: 0,
+            sched: SchedEntity::new(),
+            kthread_entry: None,
+            sig_mask: 0,
+            sig_pending: 0,
+            killed: None,
         }
     }
 }
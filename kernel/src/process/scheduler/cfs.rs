@@ -0,0 +1,65 @@
+use alloc::{collections::BTreeMap, sync::Arc};
+
+use super::{Scheduler, NICE_0_WEIGHT};
+use crate::process::ProcessControlBlock;
+
+/// Every ready process should get to run once within this period...
+const SCHED_LATENCY_US: usize = 20_000;
+/// ...unless that leaves slices shorter than this.
+const MIN_GRANULARITY_US: usize = 4_000;
+
+/// Completely fair: the process with the least virtual runtime runs, and virtual runtime
+/// advances slower the higher the weight, so CPU time is shared in proportion to weights.
+#[allow(unused)]
+pub struct CfsScheduler {
+    /// keyed by vruntime, then tid to keep the keys unique, along with the weight it was
+    /// added with as the nice value may change meanwhile
+    ready: BTreeMap<(usize, usize), (Arc<ProcessControlBlock>, usize)>,
+    /// total weight of `ready`
+    load: usize,
+    /// never goes back, so a process returning from a long sleep cannot monopolize the CPU
+    min_vruntime: usize,
+}
+
+#[allow(unused)]
+impl CfsScheduler {
+    pub fn new() -> Self {
+        Self {
+            ready: BTreeMap::new(),
+            load: 0,
+            min_vruntime: 0,
+        }
+    }
+}
+
+impl Scheduler for CfsScheduler {
+    fn push(&mut self, pcb: Arc<ProcessControlBlock>, expired: bool) {
+        let (vruntime, weight) = {
+            let mut inner = pcb.inner.get_mut();
+            if !expired {
+                // sleepers come back slightly ahead, but not with all the time they missed
+                let floor = self.min_vruntime.saturating_sub(SCHED_LATENCY_US / 2);
+                inner.sched.vruntime = inner.sched.vruntime.max(floor);
+            }
+            (inner.sched.vruntime, inner.sched.weight())
+        };
+        self.load += weight;
+        self.ready.insert((vruntime, pcb.tid()), (pcb, weight));
+    }
+    fn pop(&mut self) -> Option<Arc<ProcessControlBlock>> {
+        let ((vruntime, _), (pcb, weight)) = self.ready.pop_first()?;
+        self.load -= weight;
+        self.min_vruntime = self.min_vruntime.max(vruntime);
+        Some(pcb)
+    }
+    fn time_slice(&self, pcb: &ProcessControlBlock) -> usize {
+        let weight = pcb.inner.get().sched.weight();
+        let nr_running = self.ready.len() + 1;
+        let period = SCHED_LATENCY_US.max(nr_running * MIN_GRANULARITY_US);
+        (period * weight / (self.load + weight)).max(MIN_GRANULARITY_US)
+    }
+    fn account(&mut self, pcb: &ProcessControlBlock, us: usize) {
+        let mut inner = pcb.inner.get_mut();
+        inner.sched.vruntime += us * NICE_0_WEIGHT / inner.sched.weight();
+    }
+}
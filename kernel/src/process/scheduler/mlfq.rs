@@ -0,0 +1,70 @@
+use alloc::{collections::VecDeque, sync::Arc};
+
+use super::{Scheduler, BASE_SLICE_US};
+use crate::{process::ProcessControlBlock, timer::get_time_us};
+
+const LEVELS: usize = 4;
+/// Everyone goes back to the top level this often, so CPU-bound processes do not starve.
+const BOOST_PERIOD_US: usize = 1_000_000;
+
+/// Multi-level feedback queue: a process using up its time slice moves one level down,
+/// where slices are twice as long, while one that blocks or yields early keeps its level.
+/// Interactive processes thus stay on top and run first.
+#[allow(unused)]
+pub struct MlfqScheduler {
+    queues: [VecDeque<Arc<ProcessControlBlock>>; LEVELS],
+    last_boost: usize,
+    /// number of boosts so far, processes away from the queues catch up on the next push
+    epoch: usize,
+}
+
+#[allow(unused)]
+impl MlfqScheduler {
+    pub fn new() -> Self {
+        Self {
+            queues: [const { VecDeque::new() }; LEVELS],
+            last_boost: 0,
+            epoch: 0,
+        }
+    }
+
+    fn boost(&mut self) {
+        self.epoch += 1;
+        for level in 1..LEVELS {
+            while let Some(pcb) = self.queues[level].pop_front() {
+                let mut inner = pcb.inner.get_mut();
+                inner.sched.level = 0;
+                inner.sched.epoch = self.epoch;
+                drop(inner);
+                self.queues[0].push_back(pcb);
+            }
+        }
+    }
+}
+
+impl Scheduler for MlfqScheduler {
+    fn push(&mut self, pcb: Arc<ProcessControlBlock>, expired: bool) {
+        let level = {
+            let mut inner = pcb.inner.get_mut();
+            if inner.sched.epoch != self.epoch {
+                inner.sched.epoch = self.epoch;
+                inner.sched.level = 0;
+            } else if expired && inner.sched.level + 1 < LEVELS {
+                inner.sched.level += 1;
+            }
+            inner.sched.level
+        };
+        self.queues[level].push_back(pcb);
+    }
+    fn pop(&mut self) -> Option<Arc<ProcessControlBlock>> {
+        let now = get_time_us();
+        if now - self.last_boost >= BOOST_PERIOD_US {
+            self.last_boost = now;
+            self.boost();
+        }
+        self.queues.iter_mut().find_map(|queue| queue.pop_front())
+    }
+    fn time_slice(&self, pcb: &ProcessControlBlock) -> usize {
+        BASE_SLICE_US << pcb.inner.get().sched.level
+    }
+}
@@ -0,0 +1,94 @@
+//! Scheduling policies, one of them is picked at build time by a `sched-*` cargo feature.
+//!
+//! A policy only orders the ready processes and sizes their time slices, the process
+//! manager does the switching and the timer ends the slices.
+
+mod cfs;
+mod mlfq;
+mod round_robin;
+mod stride;
+
+use alloc::sync::Arc;
+
+use super::ProcessControlBlock;
+
+pub trait Scheduler {
+    /// `pcb` became ready, `expired` is set if it was preempted at the end of its time slice
+    /// rather than woken up or yielding.
+    fn push(&mut self, pcb: Arc<ProcessControlBlock>, expired: bool);
+    /// Take the process to run next.
+    fn pop(&mut self) -> Option<Arc<ProcessControlBlock>>;
+    /// Length of the next time slice of `pcb`, in microseconds.
+    fn time_slice(&self, _pcb: &ProcessControlBlock) -> usize {
+        BASE_SLICE_US
+    }
+    /// `pcb` ran for `us` microseconds and left the CPU.
+    fn account(&mut self, _pcb: &ProcessControlBlock, _us: usize) {}
+}
+
+#[cfg(feature = "sched-cfs")]
+pub type DefaultScheduler = cfs::CfsScheduler;
+#[cfg(all(feature = "sched-stride", not(feature = "sched-cfs")))]
+pub type DefaultScheduler = stride::StrideScheduler;
+#[cfg(all(
+    feature = "sched-mlfq",
+    not(any(feature = "sched-cfs", feature = "sched-stride"))
+))]
+pub type DefaultScheduler = mlfq::MlfqScheduler;
+#[cfg(not(any(
+    feature = "sched-cfs",
+    feature = "sched-stride",
+    feature = "sched-mlfq"
+)))]
+pub type DefaultScheduler = round_robin::RoundRobinScheduler;
+
+/// Time slice of the policies which do not size them by weight.
+const BASE_SLICE_US: usize = 10_000;
+
+pub const DEFAULT_PRIORITY: usize = 16;
+/// Smaller priorities would make the stride overtake `BIG_STRIDE / 2`.
+pub const MIN_PRIORITY: usize = 2;
+
+pub const MIN_NICE: isize = -20;
+pub const MAX_NICE: isize = 19;
+/// Weight of nice 0, the weights of the other levels are relative to it.
+const NICE_0_WEIGHT: usize = 1024;
+
+/// Each nice level is worth about 10% CPU time, the same table as Linux.
+const NICE_TO_WEIGHT: [usize; 40] = [
+    88761, 71755, 56483, 46273, 36291, 29154, 23254, 18705, 14949, 11916, 9548, 7620, 6100, 4904,
+    3906, 3121, 2501, 1991, 1586, 1277, 1024, 820, 655, 526, 423, 335, 272, 215, 172, 137, 110, 87,
+    70, 56, 45, 36, 29, 23, 18, 15,
+];
+
+/// Per process state of the scheduling policies.
+pub struct SchedEntity {
+    /// weight for the stride policy, a higher one gets more CPU time
+    pub priority: usize,
+    /// `MIN_NICE..=MAX_NICE`, a lower one gets more CPU time under the CFS policy
+    pub nice: isize,
+    /// virtual time of the stride policy
+    pub pass: usize,
+    /// weighted CPU time of the CFS policy, in microseconds
+    pub vruntime: usize,
+    /// queue of the MLFQ policy, 0 is the highest
+    pub level: usize,
+    /// MLFQ boost this process last took part in
+    pub epoch: usize,
+}
+
+impl SchedEntity {
+    pub fn new() -> Self {
+        Self {
+            priority: DEFAULT_PRIORITY,
+            nice: 0,
+            pass: 0,
+            vruntime: 0,
+            level: 0,
+            epoch: 0,
+        }
+    }
+    pub fn weight(&self) -> usize {
+        NICE_TO_WEIGHT[(self.nice - MIN_NICE) as usize]
+    }
+}
@@ -0,0 +1,28 @@
+use alloc::{collections::VecDeque, sync::Arc};
+
+use super::Scheduler;
+use crate::process::ProcessControlBlock;
+
+/// Everyone gets one tick in turn.
+#[allow(unused)]
+pub struct RoundRobinScheduler {
+    ready: VecDeque<Arc<ProcessControlBlock>>,
+}
+
+#[allow(unused)]
+impl RoundRobinScheduler {
+    pub fn new() -> Self {
+        Self {
+            ready: VecDeque::new(),
+        }
+    }
+}
+
+impl Scheduler for RoundRobinScheduler {
+    fn push(&mut self, pcb: Arc<ProcessControlBlock>, _expired: bool) {
+        self.ready.push_back(pcb);
+    }
+    fn pop(&mut self) -> Option<Arc<ProcessControlBlock>> {
+        self.ready.pop_front()
+    }
+}
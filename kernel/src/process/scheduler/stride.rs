@@ -0,0 +1,61 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use super::Scheduler;
+use crate::process::ProcessControlBlock;
+
+const BIG_STRIDE: usize = 1 << 20;
+
+/// Passes are compared with wrapping arithmetic, which is sound as long as they stay
+/// within `BIG_STRIDE / 2` of each other.
+fn pass_before(a: usize, b: usize) -> bool {
+    (a.wrapping_sub(b) as isize) < 0
+}
+
+/// The ready process with the smallest pass runs and advances it by `BIG_STRIDE / priority`,
+/// so CPU time is shared in proportion to the priorities.
+#[allow(unused)]
+pub struct StrideScheduler {
+    ready: Vec<Arc<ProcessControlBlock>>,
+    /// pass of the last process picked, sleepers do not come back with an older one
+    min_pass: usize,
+}
+
+#[allow(unused)]
+impl StrideScheduler {
+    pub fn new() -> Self {
+        Self {
+            ready: Vec::new(),
+            min_pass: 0,
+        }
+    }
+}
+
+impl Scheduler for StrideScheduler {
+    fn push(&mut self, pcb: Arc<ProcessControlBlock>, expired: bool) {
+        if !expired {
+            let mut inner = pcb.inner.get_mut();
+            if pass_before(inner.sched.pass, self.min_pass) {
+                inner.sched.pass = self.min_pass;
+            }
+        }
+        self.ready.push(pcb);
+    }
+    fn pop(&mut self) -> Option<Arc<ProcessControlBlock>> {
+        let (idx, _) = self
+            .ready
+            .iter()
+            .map(|pcb| pcb.inner.get().sched.pass)
+            .enumerate()
+            .reduce(|min, cur| if pass_before(cur.1, min.1) { cur } else { min })?;
+        let pcb = self.ready.swap_remove(idx);
+        {
+            let mut inner = pcb.inner.get_mut();
+            self.min_pass = inner.sched.pass;
+            inner.sched.pass = inner
+                .sched
+                .pass
+                .wrapping_add(BIG_STRIDE / inner.sched.priority);
+        }
+        Some(pcb)
+    }
+}
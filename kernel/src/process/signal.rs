@@ -0,0 +1,345 @@
+//! POSIX-style signals. The actions and the signals sent to the process belong to the
+//! `ThreadGroup`, the mask and the signals raised by a thread itself, faults, to the thread.
+//!
+//! Signals are taken on the way back to user mode, a thread blocked in the kernel sees them
+//! once its wait is over. Those which end the process do not wait for that, they kill every
+//! thread when they are sent, which wakes those blocked.
+
+use alloc::sync::Arc;
+
+use super::{
+    exit_current, exit_group_current, get_current_process, kill_group, processes,
+    thread_group::ThreadGroup, ProcessControlBlock,
+};
+use crate::{
+    error,
+    syscall::{copy_from_user, copy_to_user},
+};
+
+pub const SIGHUP: usize = 1;
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL: usize = 4;
+pub const SIGKILL: usize = 9;
+pub const SIGSEGV: usize = 11;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGSTOP: usize = 19;
+pub const SIGTSTP: usize = 20;
+pub const SIGTTIN: usize = 21;
+pub const SIGTTOU: usize = 22;
+pub const SIGURG: usize = 23;
+pub const SIGWINCH: usize = 28;
+/// signals are numbered from 1 to `NSIG`, one bit each in a mask
+pub const NSIG: usize = 64;
+
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+/// do not block the signal while its handler runs
+pub const SA_NODEFER: usize = 0x4000_0000;
+/// back to `SIG_DFL` once the handler was called
+pub const SA_RESETHAND: usize = 0x8000_0000;
+
+pub const fn sig_bit(sig: usize) -> usize {
+    1 << (sig - 1)
+}
+
+const UNBLOCKABLE: usize = sig_bit(SIGKILL) | sig_bit(SIGSTOP);
+const STOP_SIGNALS: usize =
+    sig_bit(SIGSTOP) | sig_bit(SIGTSTP) | sig_bit(SIGTTIN) | sig_bit(SIGTTOU);
+
+pub fn is_valid(sig: usize) -> bool {
+    (1..=NSIG).contains(&sig)
+}
+
+/// What `sigaction` takes and hands back.
+///
+/// There is no vDSO to return to from a handler, so user space tells where that is with
+/// `restorer`, which has to make the `sigreturn` syscall on the stack the handler left.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct SigAction {
+    pub handler: usize,
+    pub flags: usize,
+    pub restorer: usize,
+    /// blocked on top of the mask of the thread while the handler runs
+    pub mask: usize,
+}
+
+/// Registers of the interrupted code, pushed on the user stack while a handler runs.
+#[repr(C)]
+struct SignalFrame {
+    x: [usize; 32],
+    sepc: usize,
+    /// the mask to restore on `sigreturn`
+    mask: usize,
+}
+
+enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+fn default_action(sig: usize) -> DefaultAction {
+    match sig {
+        SIGCHLD | SIGURG | SIGWINCH => DefaultAction::Ignore,
+        SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => DefaultAction::Stop,
+        SIGCONT => DefaultAction::Continue,
+        _ => DefaultAction::Terminate,
+    }
+}
+
+fn is_ignored(sig: usize, action: &SigAction) -> bool {
+    match action.handler {
+        SIG_IGN => true,
+        SIG_DFL => matches!(
+            default_action(sig),
+            DefaultAction::Ignore | DefaultAction::Continue
+        ),
+        _ => false,
+    }
+}
+
+/// What joining a thread killed by `sig` hands out, as a shell would report it. `waitpid`
+/// tells the signal instead.
+fn exit_code(sig: usize) -> i32 {
+    128 + sig as i32
+}
+
+/// Signal state shared by the threads of a process.
+pub struct ProcessSignals {
+    actions: [SigAction; NSIG],
+    /// sent to the process, the first thread not blocking one takes it
+    pending: usize,
+    pub(super) stopped: bool,
+}
+
+impl ProcessSignals {
+    pub(super) fn new() -> Self {
+        Self {
+            actions: [SigAction::default(); NSIG],
+            pending: 0,
+            stopped: false,
+        }
+    }
+    /// What a forked child starts with, the same actions and nothing pending.
+    pub(super) fn fork(&self) -> Self {
+        Self {
+            actions: self.actions,
+            ..Self::new()
+        }
+    }
+    /// The handlers are gone with the image, ignored signals stay ignored.
+    pub(super) fn exec(&mut self) {
+        for action in self.actions.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SigAction::default();
+            }
+        }
+    }
+}
+
+/// Send `sig` to the process of `group`.
+///
+/// `SIGKILL`, and signals left to a default action which terminates, kill every thread here
+/// unless every thread blocks them, the caller too if it is one of them. `SIGCONT` resumes a
+/// stopped process right away as well.
+pub fn send(group: &Arc<ThreadGroup>, sig: usize) {
+    let bit = sig_bit(sig);
+    match sig {
+        SIGKILL => return kill(group, sig),
+        SIGCONT => {
+            group.signals().pending &= !STOP_SIGNALS;
+            group.resume();
+        }
+        _ if bit & STOP_SIGNALS != 0 => group.signals().pending &= !sig_bit(SIGCONT),
+        _ => {}
+    }
+    let action = group.signals().actions[sig - 1];
+    if is_ignored(sig, &action) {
+        return;
+    }
+    let unblocked = group
+        .threads()
+        .iter()
+        .any(|thread| thread.inner.get().sig_mask & bit == 0);
+    match default_action(sig) {
+        DefaultAction::Terminate if action.handler == SIG_DFL && unblocked => kill(group, sig),
+        _ => group.signals().pending |= bit,
+    }
+}
+
+fn kill(group: &Arc<ThreadGroup>, sig: usize) {
+    error!("[kernel] pid {} killed by signal {}", group.pid(), sig);
+    group.killed_by(sig);
+    kill_group(group, exit_code(sig))
+}
+
+/// Send `sig` to every process in group `pgid`, returns false if there is none.
+pub fn send_to_group(pgid: usize, sig: usize) -> bool {
+    let mut groups = processes();
+    groups.retain(|group| group.pgid() == pgid);
+    for group in groups.iter() {
+        send(group, sig);
+    }
+    !groups.is_empty()
+}
+
+/// The current thread faulted with `sig`, returns false if it has to die for it.
+///
+/// Blocking or ignoring a fault would only have it fault again, then the action is reset
+/// to the default, which terminates.
+pub fn force_current(sig: usize) -> bool {
+    let pcb = get_current_process();
+    let blocked = pcb.inner.get().sig_mask & sig_bit(sig) != 0;
+    let handled = {
+        let mut signals = pcb.group().signals();
+        let action = &mut signals.actions[sig - 1];
+        if action.handler == SIG_IGN || blocked {
+            *action = SigAction::default();
+        }
+        action.handler != SIG_DFL
+    };
+    if handled {
+        pcb.inner.get_mut().sig_pending |= sig_bit(sig);
+    }
+    handled
+}
+
+/// Act on the signals the current thread does not block, right before it returns to user
+/// mode. One handler is set up at a time, the next signal is taken once `sigreturn` returns.
+///
+/// A killed thread exits here instead.
+pub(super) fn handle_signals() {
+    let pcb = get_current_process();
+    loop {
+        pcb.group().wait_while_stopped();
+        let killed = pcb.inner.get().killed;
+        if let Some(exit_code) = killed {
+            drop(pcb);
+            exit_current(exit_code);
+        }
+        let sig = match take_signal(&pcb) {
+            Some(sig) => sig,
+            None => return,
+        };
+        let action = pcb.group().signals().actions[sig - 1];
+        match action.handler {
+            SIG_IGN => {}
+            SIG_DFL => match default_action(sig) {
+                DefaultAction::Terminate => terminate(pcb, sig),
+                DefaultAction::Stop => pcb.group().stop(sig),
+                DefaultAction::Ignore | DefaultAction::Continue => {}
+            },
+            _ => return deliver(pcb, sig, &action),
+        }
+    }
+}
+
+fn take_signal(pcb: &ProcessControlBlock) -> Option<usize> {
+    let mut inner = pcb.inner.get_mut();
+    let mut signals = pcb.group().signals();
+    let ready = (inner.sig_pending | signals.pending) & !inner.sig_mask;
+    if ready == 0 {
+        return None;
+    }
+    let sig = ready.trailing_zeros() as usize + 1;
+    inner.sig_pending &= !sig_bit(sig);
+    signals.pending &= !sig_bit(sig);
+    Some(sig)
+}
+
+fn terminate(pcb: Arc<ProcessControlBlock>, sig: usize) -> ! {
+    error!("[kernel] pid {} killed by signal {}", pcb.pid(), sig);
+    drop(pcb);
+    kill_current(sig)
+}
+
+/// End the current process for `sig`.
+pub(super) fn kill_current(sig: usize) -> ! {
+    get_current_process().group().killed_by(sig);
+    exit_group_current(exit_code(sig))
+}
+
+/// Push the registers on the user stack and return into the handler as
+/// `handler(sig, 0, frame)`, with `ra` at the restorer.
+fn deliver(pcb: Arc<ProcessControlBlock>, sig: usize, action: &SigAction) {
+    let ctx = pcb.trap_ctx();
+    let mask = {
+        let mut inner = pcb.inner.get_mut();
+        let mask = inner.sig_mask;
+        if action.flags & SA_NODEFER == 0 {
+            inner.sig_mask |= sig_bit(sig);
+        }
+        inner.sig_mask = (inner.sig_mask | action.mask) & !UNBLOCKABLE;
+        mask
+    };
+    let frame = SignalFrame {
+        x: ctx.x,
+        sepc: ctx.sepc,
+        mask,
+    };
+    let sp = ctx.x[2].wrapping_sub(core::mem::size_of::<SignalFrame>()) & !0xf;
+    if copy_to_user(&pcb, sp, &frame).is_err() {
+        // no stack to run the handler on
+        return terminate(pcb, SIGSEGV);
+    }
+    if action.flags & SA_RESETHAND != 0 {
+        pcb.group().signals().actions[sig - 1] = SigAction::default();
+    }
+    ctx.x[1] = action.restorer;
+    ctx.x[2] = sp;
+    ctx.x[10] = sig;
+    ctx.x[11] = 0;
+    ctx.x[12] = sp;
+    ctx.sepc = action.handler;
+}
+
+/// Back from a handler, restore what `deliver` saved on the stack. Returns the saved `a0`,
+/// as that is where the result of the syscall goes.
+pub fn sigreturn() -> Result<isize, ()> {
+    let pcb = get_current_process();
+    let ctx = pcb.trap_ctx();
+    let mut frame = SignalFrame {
+        x: [0; 32],
+        sepc: 0,
+        mask: 0,
+    };
+    copy_from_user(&pcb, ctx.x[2], &mut frame)?;
+    ctx.x = frame.x;
+    // the syscall path steps over the ecall, the interrupted code was elsewhere
+    ctx.sepc = frame.sepc.wrapping_sub(4);
+    pcb.inner.get_mut().sig_mask = frame.mask & !UNBLOCKABLE;
+    Ok(frame.x[10] as isize)
+}
+
+pub fn action(sig: usize) -> SigAction {
+    get_current_process().group().signals().actions[sig - 1]
+}
+
+/// Change the action of the current process for `sig`, pending ones are dropped if it is
+/// ignored from now on. Those of `SIGKILL` and `SIGSTOP` are not to be changed.
+pub fn set_action(sig: usize, action: SigAction) {
+    let pcb = get_current_process();
+    let group = pcb.group();
+    group.signals().actions[sig - 1] = action;
+    if is_ignored(sig, &action) {
+        group.signals().pending &= !sig_bit(sig);
+        for thread in group.threads() {
+            thread.inner.get_mut().sig_pending &= !sig_bit(sig);
+        }
+    }
+}
+
+/// The signals the current thread blocks.
+pub fn mask() -> usize {
+    get_current_process().inner.get().sig_mask
+}
+
+/// `SIGKILL` and `SIGSTOP` cannot be blocked, they are left out of `mask`.
+pub fn set_mask(mask: usize) {
+    get_current_process().inner.get_mut().sig_mask = mask & !UNBLOCKABLE;
+}
@@ -0,0 +1,381 @@
+//! What the threads of a process share: the pid, the address space, the open files and the
+//! exit status, as well as its parent, process group and session.
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use super::{
+    handle_table::SyncHandles, process_control_block::PID, signal::ProcessSignals, usage::Usage,
+    ProcessControlBlock, WaitQueue,
+};
+use crate::{
+    file::{FdTable, File},
+    memory::{address::PhysAddr, memory_set::MemorySet, swap},
+    sync::{UPRefMut, UPSafeCell},
+};
+
+/// A change of state `waitpid` reports.
+#[derive(Clone, Copy)]
+pub enum WaitEvent {
+    Exited(i32),
+    Killed(usize),
+    Stopped(usize),
+    Continued,
+}
+
+/// Which changes `ThreadGroup::wait_child` waits for besides exiting, and whether it waits at
+/// all.
+#[derive(Default)]
+pub struct WaitOptions {
+    pub stopped: bool,
+    pub continued: bool,
+    pub nohang: bool,
+}
+
+pub struct ThreadGroup {
+    /// the tid of the first thread
+    pid: Arc<PID>,
+    pub(super) mem_set: Arc<UPSafeCell<MemorySet>>,
+    inner: UPSafeCell<ThreadGroupInner>,
+    sync_handles: UPSafeCell<SyncHandles>,
+    signals: UPSafeCell<ProcessSignals>,
+    files: UPSafeCell<FdTable>,
+    /// threads in `waitpid` for a child, woken when one exits, stops or continues
+    child_waiters: WaitQueue,
+    /// threads held while the process is stopped
+    stop_waiters: WaitQueue,
+}
+
+struct ThreadGroupInner {
+    /// where the user stacks of the threads start, it moves with `exec`
+    user_stack_base: usize,
+    /// the process which forked this one, none for those the kernel starts
+    parent: Weak<ThreadGroup>,
+    children: Vec<Arc<ThreadGroup>>,
+    /// threads which have not exited yet
+    threads: Vec<Arc<ProcessControlBlock>>,
+    /// slots of exited threads, see `trap_ctx_position` and `user_stack_position`
+    free_slots: Vec<usize>,
+    next_slot: usize,
+    exited: bool,
+    exit_code: i32,
+    /// the signal which killed it, 0 if it exited on its own
+    term_sig: usize,
+    /// stopped or continued since `waitpid` last looked
+    state_change: Option<WaitEvent>,
+    pgid: usize,
+    sid: usize,
+    /// usage of the exited threads
+    usage: Usage,
+    /// usage of the reaped children
+    children_usage: Usage,
+}
+
+impl ThreadGroup {
+    /// A process whose first thread has `pid` as tid and runs in slot 0 of `mem_set`.
+    pub(super) fn new(
+        pid: Arc<PID>,
+        mem_set: Arc<UPSafeCell<MemorySet>>,
+        user_stack_base: usize,
+    ) -> Self {
+        let inner = ThreadGroupInner {
+            user_stack_base,
+            parent: Weak::new(),
+            children: Vec::new(),
+            threads: Vec::new(),
+            free_slots: Vec::new(),
+            next_slot: 1,
+            exited: false,
+            exit_code: 0,
+            term_sig: 0,
+            state_change: None,
+            // like init on Linux, processes the kernel starts are in group and session 0
+            pgid: 0,
+            sid: 0,
+            usage: Usage::default(),
+            children_usage: Usage::default(),
+        };
+        Self {
+            pid,
+            mem_set,
+            inner: unsafe { UPSafeCell::new(inner) },
+            sync_handles: unsafe { UPSafeCell::new(SyncHandles::new()) },
+            signals: unsafe { UPSafeCell::new(ProcessSignals::new()) },
+            files: unsafe { UPSafeCell::new(FdTable::new()) },
+            child_waiters: WaitQueue::new(),
+            stop_waiters: WaitQueue::new(),
+        }
+    }
+    pub fn pid(&self) -> usize {
+        self.pid.0
+    }
+    /// A child of this process with pid `pid`, its only thread runs in `slot` of `mem_set`,
+    /// a copy of the one of this process. It inherits the open files, the signal actions,
+    /// the process group and the session.
+    pub(super) fn fork(
+        self: &Arc<Self>,
+        pid: Arc<PID>,
+        mut mem_set: MemorySet,
+        slot: usize,
+    ) -> Arc<Self> {
+        let (user_stack_base, next_slot, pgid, sid) = {
+            let inner = self.inner.get();
+            (
+                inner.user_stack_base,
+                inner.next_slot,
+                inner.pgid,
+                inner.sid,
+            )
+        };
+        // the other threads stay behind
+        let free_slots: Vec<usize> = (0..next_slot).filter(|&other| other != slot).collect();
+        for &other in free_slots.iter() {
+            mem_set.unmap_thread(user_stack_base, other);
+        }
+        let mem_set = Arc::new(unsafe { UPSafeCell::new(mem_set) });
+        swap::register(&mem_set);
+        let child = Self::new(pid, mem_set, user_stack_base);
+        {
+            let mut inner = child.inner.get_mut();
+            inner.parent = Arc::downgrade(self);
+            inner.free_slots = free_slots;
+            inner.next_slot = next_slot;
+            inner.pgid = pgid;
+            inner.sid = sid;
+        }
+        *child.signals.get_mut() = self.signals.get().fork();
+        *child.files.get_mut() = self.files.get().clone();
+        let child = Arc::new(child);
+        self.inner.get_mut().children.push(child.clone());
+        child
+    }
+    /// Run the image of `mem_set` from now on, the caller is the only thread left. Caught
+    /// signals go back to their default action and the synchronization objects are gone.
+    pub(super) fn exec(&self, mem_set: MemorySet, user_stack_base: usize) {
+        let mut old = core::mem::replace(&mut *self.mem_set.get_mut(), mem_set);
+        old.recycle_data_pages();
+        drop(old);
+        self.inner.get_mut().user_stack_base = user_stack_base;
+        self.signals.get_mut().exec();
+        let handles = core::mem::replace(&mut *self.sync_handles.get_mut(), SyncHandles::new());
+        drop(handles);
+    }
+    pub fn parent(&self) -> Option<Arc<ThreadGroup>> {
+        self.inner.get().parent.upgrade()
+    }
+    /// Whether process `pid` is a child of this one.
+    pub fn is_parent_of(&self, pid: usize) -> bool {
+        self.inner
+            .get()
+            .children
+            .iter()
+            .any(|child| child.pid() == pid)
+    }
+    /// Do not block while holding it, it keeps interrupts off.
+    pub fn sync_handles(&self) -> UPRefMut<'_, SyncHandles> {
+        self.sync_handles.get_mut()
+    }
+    pub(super) fn signals(&self) -> UPRefMut<'_, ProcessSignals> {
+        self.signals.get_mut()
+    }
+    /// Do not drop a file while holding it, closing one may send signals.
+    pub fn files(&self) -> UPRefMut<'_, FdTable> {
+        self.files.get_mut()
+    }
+    pub fn file(&self, fd: usize) -> Option<Arc<File>> {
+        self.files.get().get(fd)
+    }
+    /// Hold every thread on its way back to user mode until `resume`, `sig` stopped it.
+    pub(super) fn stop(&self, sig: usize) {
+        self.signals.get_mut().stopped = true;
+        self.inner.get_mut().state_change = Some(WaitEvent::Stopped(sig));
+        self.wake_parent();
+    }
+    pub(super) fn resume(&self) {
+        let stopped = core::mem::replace(&mut self.signals.get_mut().stopped, false);
+        if stopped {
+            self.inner.get_mut().state_change = Some(WaitEvent::Continued);
+            self.stop_waiters.wake_all();
+            self.wake_parent();
+        }
+    }
+    /// A killed thread does not wait.
+    pub(super) fn wait_while_stopped(&self) {
+        self.stop_waiters
+            .wait_event_killable(|| !self.signals.get().stopped);
+    }
+    pub(super) fn add_thread(&self, thread: Arc<ProcessControlBlock>) {
+        self.inner.get_mut().threads.push(thread);
+    }
+    /// The threads which have not exited yet.
+    pub(super) fn threads(&self) -> Vec<Arc<ProcessControlBlock>> {
+        self.inner.get().threads.clone()
+    }
+    /// Map the user stack and TrapContext of a new thread, returns its slot, stack top and
+    /// the TrapContext.
+    pub(super) fn alloc_thread(&self) -> (usize, usize, PhysAddr) {
+        let slot = {
+            let mut inner = self.inner.get_mut();
+            inner.free_slots.pop().unwrap_or_else(|| {
+                inner.next_slot += 1;
+                inner.next_slot - 1
+            })
+        };
+        let user_stack_base = self.inner.get().user_stack_base;
+        let mut mem_set = self.mem_set.get_mut();
+        let sp = mem_set.map_thread(user_stack_base, slot);
+        let trap_ctx = mem_set
+            .trap_ctx(slot)
+            .expect("TrapContext should be mapped");
+        (slot, sp, trap_ctx)
+    }
+    /// `thread` exited, its usage stays with the process. The last thread to leave ends the
+    /// process with `exit_code`, frees its memory and closes its files.
+    pub(super) fn leave(&self, thread: &Arc<ProcessControlBlock>, exit_code: i32) {
+        let usage = thread.usage();
+        let (last, user_stack_base) = {
+            let mut inner = self.inner.get_mut();
+            inner.threads.retain(|t| !Arc::ptr_eq(t, thread));
+            inner.usage += usage;
+            if inner.threads.is_empty() {
+                inner.exited = true;
+                inner.exit_code = exit_code;
+            } else if !thread.is_kernel_thread() {
+                inner.free_slots.push(thread.slot());
+            }
+            (inner.threads.is_empty(), inner.user_stack_base)
+        };
+        if !thread.is_kernel_thread() {
+            let mut mem_set = self.mem_set.get_mut();
+            match last {
+                true => mem_set.recycle_data_pages(),
+                false => mem_set.unmap_thread(user_stack_base, thread.slot()),
+            }
+        }
+        if last {
+            if self.sid() == self.pid() {
+                crate::tty::release(self.sid());
+            }
+            let files = self.files.get_mut().take_all();
+            drop(files);
+            // nobody reaps the children any more
+            let children = core::mem::take(&mut self.inner.get_mut().children);
+            for child in children {
+                child.inner.get_mut().parent = Weak::new();
+            }
+            self.wake_parent();
+        }
+    }
+    fn wake_parent(&self) {
+        if let Some(parent) = self.parent() {
+            parent.child_waiters.wake_all();
+        }
+    }
+    /// Whether the last thread left, even though the process may not be reaped yet.
+    pub fn has_exited(&self) -> bool {
+        self.inner.get().exited
+    }
+    /// Whether it exited and there is no parent left to reap it, either it did already or
+    /// the process was orphaned.
+    pub(super) fn is_reaped(&self) -> bool {
+        self.has_exited() && self.parent().is_none()
+    }
+    /// The process is being killed by `sig`, `waitpid` reports that rather than an exit code.
+    pub(super) fn killed_by(&self, sig: usize) {
+        let mut inner = self.inner.get_mut();
+        if !inner.exited && inner.term_sig == 0 {
+            inner.term_sig = sig;
+        }
+    }
+    /// Block until a child `wanted` picks exited, or until one stopped or continued if
+    /// `options` ask for that. Returns `None` if there is no such child, or with
+    /// `options.nohang` if nothing happened yet, or once the caller is being killed.
+    pub fn wait_child(
+        &self,
+        wanted: impl Fn(&ThreadGroup) -> bool,
+        options: WaitOptions,
+    ) -> Option<(Arc<ThreadGroup>, WaitEvent)> {
+        let mut found = None;
+        self.child_waiters.wait_event_killable(|| {
+            let children = self.children(&wanted);
+            found = children
+                .iter()
+                .find_map(|child| Some((child.clone(), child.take_event(&options)?)));
+            found.is_some() || options.nohang || children.is_empty()
+        });
+        found
+    }
+    /// The children `wanted` picks.
+    pub fn children(&self, wanted: impl Fn(&ThreadGroup) -> bool) -> Vec<Arc<ThreadGroup>> {
+        let children = self.inner.get().children.clone();
+        children.into_iter().filter(|child| wanted(child)).collect()
+    }
+    fn take_event(&self, options: &WaitOptions) -> Option<WaitEvent> {
+        let mut inner = self.inner.get_mut();
+        if inner.exited {
+            return Some(match inner.term_sig {
+                0 => WaitEvent::Exited(inner.exit_code),
+                sig => WaitEvent::Killed(sig),
+            });
+        }
+        let change = inner.state_change;
+        match change {
+            Some(WaitEvent::Stopped(_)) if options.stopped => inner.state_change.take(),
+            Some(WaitEvent::Continued) if options.continued => inner.state_change.take(),
+            _ => None,
+        }
+    }
+    pub fn pgid(&self) -> usize {
+        self.inner.get().pgid
+    }
+    pub fn sid(&self) -> usize {
+        self.inner.get().sid
+    }
+    pub fn set_pgid(&self, pgid: usize) {
+        self.inner.get_mut().pgid = pgid;
+    }
+    /// Lead a new session and a new process group in it, both named by the pid.
+    pub fn set_sid(&self) {
+        let mut inner = self.inner.get_mut();
+        inner.pgid = self.pid.0;
+        inner.sid = self.pid.0;
+    }
+    /// Usage of all threads so far, the exited ones included.
+    pub fn usage(&self) -> Usage {
+        let (mut usage, threads) = {
+            let inner = self.inner.get();
+            (inner.usage, inner.threads.clone())
+        };
+        for thread in threads.iter() {
+            usage += thread.usage();
+        }
+        usage
+    }
+    /// CPU time used by all threads so far in microseconds.
+    pub fn cpu_time_us(&self) -> usize {
+        let usage = self.usage();
+        usage.utime + usage.stime
+    }
+    pub fn children_usage(&self) -> Usage {
+        self.inner.get().children_usage
+    }
+    /// Forget `child`, which exited, its usage counts for the children from now on. Its
+    /// threads are dropped with it.
+    pub fn reap(&self, child: &ThreadGroup) {
+        let usage = {
+            let mut inner = child.inner.get_mut();
+            inner.parent = Weak::new();
+            let mut usage = core::mem::take(&mut inner.usage);
+            usage += core::mem::take(&mut inner.children_usage);
+            usage
+        };
+        {
+            let mut inner = self.inner.get_mut();
+            inner.children.retain(|other| other.pid() != child.pid());
+            inner.children_usage += usage;
+        }
+        super::reclaim();
+    }
+}
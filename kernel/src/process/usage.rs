@@ -0,0 +1,34 @@
+//! What a process used so far, for `times` and `getrusage`.
+
+use core::ops::AddAssign;
+
+#[derive(Clone, Copy, Default)]
+pub struct Usage {
+    /// microseconds spent in user mode
+    pub utime: usize,
+    /// microseconds spent in the kernel on behalf of the process
+    pub stime: usize,
+    /// resident pages at the peak
+    pub maxrss: usize,
+    /// page faults served without I/O
+    pub minflt: usize,
+    /// page faults which read the page back from swap
+    pub majflt: usize,
+    /// times the process blocked or yielded
+    pub nvcsw: usize,
+    /// times the process was preempted
+    pub nivcsw: usize,
+}
+
+/// Collects a reaped child, whose peak counts as it is and does not add up.
+impl AddAssign for Usage {
+    fn add_assign(&mut self, child: Self) {
+        self.utime += child.utime;
+        self.stime += child.stime;
+        self.maxrss = self.maxrss.max(child.maxrss);
+        self.minflt += child.minflt;
+        self.majflt += child.majflt;
+        self.nvcsw += child.nvcsw;
+        self.nivcsw += child.nivcsw;
+    }
+}
@@ -0,0 +1,114 @@
+//! Processes blocked on an event, woken by whoever makes it happen.
+
+use alloc::{collections::VecDeque, sync::Arc};
+
+use super::{block_current, get_current_process, wakeup, ProcessControlBlock, ProcessStatus};
+use crate::{
+    sync::{preempt::without_interrupts, UPSafeCell},
+    timer::{add_timer, cancel_timer},
+};
+
+/// A thread killed while parked is woken by the kill rather than the queue, it takes itself
+/// off once it runs.
+pub struct WaitQueue {
+    waiters: UPSafeCell<VecDeque<Arc<ProcessControlBlock>>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            waiters: unsafe { UPSafeCell::new(VecDeque::new()) },
+        }
+    }
+
+    /// Park the current process until it is woken.
+    ///
+    /// A wakeup between checking a condition and calling this is lost, use `wait_event`
+    /// unless interrupts are already off.
+    pub fn wait(&self) {
+        without_interrupts(|| {
+            let pcb = get_current_process();
+            self.waiters.get_mut().push_back(pcb.clone());
+            block_current();
+            self.remove(&pcb);
+        })
+    }
+
+    /// Like `wait`, but gives up once `get_time()` reaches `deadline`. Returns false if it
+    /// timed out rather than being woken.
+    pub fn wait_timeout(&self, deadline: usize) -> bool {
+        without_interrupts(|| {
+            let pcb = get_current_process();
+            self.waiters.get_mut().push_back(pcb.clone());
+            add_timer(deadline, pcb.clone());
+            block_current();
+            cancel_timer(&pcb);
+            // the timer or a kill leaves it queued
+            !self.remove(&pcb)
+        })
+    }
+
+    /// Take `pcb` off the queue without waking it, returns false if it was not queued.
+    pub fn remove(&self, pcb: &Arc<ProcessControlBlock>) -> bool {
+        let mut waiters = self.waiters.get_mut();
+        match waiters.iter().position(|waiter| Arc::ptr_eq(waiter, pcb)) {
+            Some(idx) => {
+                waiters.remove(idx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiters.get().is_empty()
+    }
+
+    /// Block until `cond` holds, it is checked again after every wakeup.
+    pub fn wait_event(&self, mut cond: impl FnMut() -> bool) {
+        without_interrupts(|| {
+            while !cond() {
+                self.wait();
+            }
+        })
+    }
+
+    /// Like `wait_event`, but gives up once the current thread is being killed. Returns
+    /// whether `cond` holds.
+    pub fn wait_event_killable(&self, mut cond: impl FnMut() -> bool) -> bool {
+        without_interrupts(|| loop {
+            if cond() {
+                return true;
+            }
+            if get_current_process().is_killed() {
+                return false;
+            }
+            self.wait();
+        })
+    }
+
+    /// Wake the longest waiting process, returns false if there was none.
+    pub fn wake_one(&self) -> bool {
+        loop {
+            let waiter = self.waiters.get_mut().pop_front();
+            match waiter {
+                // woken by a kill or its timer, it is still to take itself off
+                Some(pcb) if pcb.inner.get().status != ProcessStatus::Pending => continue,
+                Some(pcb) => {
+                    wakeup(&pcb);
+                    return true;
+                }
+                None => return false,
+            }
+        }
+    }
+
+    /// Wake every waiting process, returns how many there were.
+    pub fn wake_all(&self) -> usize {
+        let waiters = core::mem::take(&mut *self.waiters.get_mut());
+        for pcb in waiters.iter() {
+            wakeup(pcb);
+        }
+        waiters.len()
+    }
+}
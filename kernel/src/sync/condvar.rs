@@ -0,0 +1,50 @@
+//! Sleep until notified, with a `Mutex` released meanwhile.
+
+use super::{mutex::MutexGuard, preempt::without_interrupts};
+use crate::process::WaitQueue;
+
+pub struct Condvar {
+    waiters: WaitQueue,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Unlock `guard` and sleep until notified, the mutex is locked again on return. Check the
+    /// condition in a loop, somebody else may have changed it before the lock was retaken.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex;
+        self.sleep(guard);
+        mutex.lock()
+    }
+
+    /// Like `wait`, but gives up with `None` once the caller is being killed, the mutex is
+    /// not locked again then.
+    pub fn wait_killable<'a, T>(&self, guard: MutexGuard<'a, T>) -> Option<MutexGuard<'a, T>> {
+        let mutex = guard.mutex;
+        self.sleep(guard);
+        mutex.lock_killable()
+    }
+
+    fn sleep<T>(&self, guard: MutexGuard<'_, T>) {
+        // a notification must not slip in between unlocking and queueing up
+        without_interrupts(|| {
+            drop(guard);
+            self.waiters.wait();
+        });
+    }
+
+    /// Wake the longest waiting thread, returns false if there was none.
+    pub fn notify_one(&self) -> bool {
+        self.waiters.wake_one()
+    }
+
+    /// Wake every waiting thread, returns how many there were.
+    pub fn notify_all(&self) -> usize {
+        self.waiters.wake_all()
+    }
+}
@@ -0,0 +1,79 @@
+//! Wait queues keyed by a user word, the slow path of user space locks.
+
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use super::{preempt::without_interrupts, UPSafeCell};
+use crate::process::WaitQueue;
+
+/// Identifies a user word across the address spaces it is mapped in.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FutexKey {
+    /// address space and virtual address, the page may move while swapped out
+    Private(usize, usize),
+    /// physical address of shared memory, which processes sharing it meet on
+    Shared(usize),
+}
+
+static FUTEXES: UPSafeCell<BTreeMap<FutexKey, Arc<WaitQueue>>> =
+    unsafe { UPSafeCell::new(BTreeMap::new()) };
+
+pub enum FutexError {
+    /// the word no longer held the expected value
+    Again,
+    TimedOut,
+}
+
+/// Block on the word of `key` as long as it holds `val`, until woken or until `get_time()`
+/// reaches `deadline`. `locate` finds the word, `None` if it was swapped out meanwhile.
+pub fn wait(
+    key: FutexKey,
+    locate: impl FnOnce() -> Option<usize>,
+    val: u32,
+    deadline: Option<usize>,
+) -> Result<(), FutexError> {
+    // nothing may change the word between the check and queueing up
+    without_interrupts(|| {
+        let word = match locate() {
+            Some(pa) => unsafe { &*(pa as *const AtomicU32) },
+            None => return Err(FutexError::Again),
+        };
+        if word.load(Ordering::Relaxed) != val {
+            return Err(FutexError::Again);
+        }
+        let queue = FUTEXES
+            .get_mut()
+            .entry(key)
+            .or_insert_with(|| Arc::new(WaitQueue::new()))
+            .clone();
+        let woken = match deadline {
+            Some(deadline) => queue.wait_timeout(deadline),
+            None => {
+                queue.wait();
+                true
+            }
+        };
+        forget_if_idle(key, &queue);
+        woken.then_some(()).ok_or(FutexError::TimedOut)
+    })
+}
+
+/// Wake up to `n` waiters on the word of `key`, returns how many there were.
+pub fn wake(key: FutexKey, n: usize) -> usize {
+    let queue = match FUTEXES.get().get(&key) {
+        Some(queue) => queue.clone(),
+        None => return 0,
+    };
+    let woken = (0..n).take_while(|_| queue.wake_one()).count();
+    forget_if_idle(key, &queue);
+    woken
+}
+
+/// Drop the queue of `key` once nobody waits on it, unless it was replaced already.
+fn forget_if_idle(key: FutexKey, queue: &Arc<WaitQueue>) {
+    let mut futexes = FUTEXES.get_mut();
+    let same = futexes.get(&key).is_some_and(|q| Arc::ptr_eq(q, queue));
+    if same && queue.is_empty() {
+        futexes.remove(&key);
+    }
+}
@@ -1,2 +1,49 @@
+mod condvar;
+pub mod futex;
+mod mutex;
+pub mod preempt;
+mod semaphore;
 mod up_safe_cell;
+pub use self::condvar::Condvar;
+pub use self::mutex::{Mutex, MutexGuard};
+pub use self::semaphore::Semaphore;
 pub use self::up_safe_cell::*;
+
+use crate::{
+    info,
+    process::{kthread, suspend_current},
+};
+
+/// Two kernel threads hold a `Mutex` across yields in turn, a `Semaphore` lets them start and a
+/// `Condvar` tells the checker once both are done.
+#[allow(unused)]
+pub fn sync_test() {
+    static COUNTER: Mutex<usize> = Mutex::new(0);
+    static FINISHED: Mutex<usize> = Mutex::new(0);
+    static ALL_FINISHED: Condvar = Condvar::new();
+    static START: Semaphore = Semaphore::new(0);
+    for _ in 0..2 {
+        kthread::spawn(|| {
+            START.down();
+            for _ in 0..3 {
+                let mut counter = COUNTER.lock();
+                let seen = *counter;
+                // the other one has to sleep on the lock meanwhile
+                suspend_current();
+                *counter = seen + 1;
+            }
+            *FINISHED.lock() += 1;
+            ALL_FINISHED.notify_all();
+        });
+    }
+    kthread::spawn(|| {
+        START.up();
+        START.up();
+        let mut finished = FINISHED.lock();
+        while *finished < 2 {
+            finished = ALL_FINISHED.wait(finished);
+        }
+        assert_eq!(*COUNTER.lock(), 6);
+        info!("sync_test passed!");
+    });
+}
@@ -0,0 +1,101 @@
+//! A lock which puts the caller to sleep while another thread holds it. Unlike a
+//! `UPSafeCell` borrow it may be held across blocking, and taking it twice blocks instead of
+//! panicking.
+
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+};
+
+use super::UPSafeCell;
+use crate::process::{get_current_process, WaitQueue};
+
+pub struct Mutex<T> {
+    /// tid of the holder
+    owner: UPSafeCell<Option<usize>>,
+    waiters: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+pub struct MutexGuard<'a, T> {
+    pub(super) mutex: &'a Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            owner: unsafe { UPSafeCell::new(None) },
+            waiters: WaitQueue::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        let tid = get_current_process().tid();
+        self.waiters.wait_event(|| self.try_acquire(tid));
+        MutexGuard { mutex: self }
+    }
+
+    /// Like `lock`, but gives up with `None` once the caller is being killed.
+    pub fn lock_killable(&self) -> Option<MutexGuard<'_, T>> {
+        let tid = get_current_process().tid();
+        let locked = self.waiters.wait_event_killable(|| self.try_acquire(tid));
+        locked.then_some(MutexGuard { mutex: self })
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let tid = get_current_process().tid();
+        self.try_acquire(tid).then_some(MutexGuard { mutex: self })
+    }
+
+    fn try_acquire(&self, tid: usize) -> bool {
+        let mut owner = self.owner.get_mut();
+        match *owner {
+            Some(_) => false,
+            None => {
+                *owner = Some(tid);
+                true
+            }
+        }
+    }
+
+    /// The tid of the thread holding the lock.
+    pub fn owner(&self) -> Option<usize> {
+        *self.owner.get()
+    }
+
+    /// A guard for the lock whose guard was forgotten, so it stayed locked across syscalls.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held by the caller, with no other guard of it alive.
+    pub unsafe fn guard_unchecked(&self) -> MutexGuard<'_, T> {
+        MutexGuard { mutex: self }
+    }
+
+    fn unlock(&self) {
+        *self.owner.get_mut() = None;
+        self.waiters.wake_one();
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
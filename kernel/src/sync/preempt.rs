@@ -0,0 +1,75 @@
+//! Interrupts are allowed inside the kernel, except while shared state is borrowed.
+//!
+//! Every `UPSafeCell` borrow holds an `IrqGuard`, so an interrupt handler never finds a cell
+//! borrowed by the code it interrupted, and the kernel is only preempted with no borrow held.
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use riscv::register::sstatus;
+
+static PREEMPT_COUNT: AtomicUsize = AtomicUsize::new(0);
+static NEED_RESCHED: AtomicBool = AtomicBool::new(false);
+
+/// Disables interrupts until dropped, and restores them only if they were enabled before,
+/// so guards nest.
+pub struct IrqGuard {
+    was_enabled: bool,
+}
+
+impl IrqGuard {
+    pub fn new() -> Self {
+        let was_enabled = sstatus::read().sie();
+        unsafe { sstatus::clear_sie() };
+        preempt_disable();
+        Self { was_enabled }
+    }
+}
+
+impl Drop for IrqGuard {
+    fn drop(&mut self) {
+        preempt_enable();
+        if self.was_enabled {
+            unsafe { sstatus::set_sie() };
+        }
+    }
+}
+
+pub fn preempt_disable() {
+    PREEMPT_COUNT.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn preempt_enable() {
+    let prev = PREEMPT_COUNT.fetch_sub(1, Ordering::Relaxed);
+    assert!(prev > 0, "unbalanced preempt_enable");
+}
+
+pub fn preemptible() -> bool {
+    PREEMPT_COUNT.load(Ordering::Relaxed) == 0
+}
+
+/// Ask for a reschedule at the next safe point.
+pub fn set_need_resched() {
+    NEED_RESCHED.store(true, Ordering::Relaxed);
+}
+
+/// Drop a pending reschedule request, the next process starts with a fresh time slice.
+pub fn clear_need_resched() {
+    NEED_RESCHED.store(false, Ordering::Relaxed);
+}
+
+/// Consume a pending reschedule request if the kernel can be preempted now.
+pub fn take_need_resched() -> bool {
+    preemptible() && NEED_RESCHED.swap(false, Ordering::Relaxed)
+}
+
+/// Run `f` with interrupts disabled, without counting as a preemption-free section.
+///
+/// This is for context switches: the switched-to context restores its own interrupt state.
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    let was_enabled = sstatus::read().sie();
+    unsafe { sstatus::clear_sie() };
+    let ret = f();
+    if was_enabled {
+        unsafe { sstatus::set_sie() };
+    }
+    ret
+}
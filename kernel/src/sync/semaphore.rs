@@ -0,0 +1,44 @@
+//! A counting semaphore, `down` sleeps while the count is 0.
+
+use super::UPSafeCell;
+use crate::process::WaitQueue;
+
+pub struct Semaphore {
+    count: UPSafeCell<usize>,
+    waiters: WaitQueue,
+}
+
+impl Semaphore {
+    pub const fn new(count: usize) -> Self {
+        Self {
+            count: unsafe { UPSafeCell::new(count) },
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Take one, waiting until there is one to take.
+    pub fn down(&self) {
+        self.waiters.wait_event(|| self.try_down());
+    }
+
+    /// Like `down`, but gives up once the caller is being killed. Returns whether it took one.
+    pub fn down_killable(&self) -> bool {
+        self.waiters.wait_event_killable(|| self.try_down())
+    }
+
+    fn try_down(&self) -> bool {
+        let mut count = self.count.get_mut();
+        match *count {
+            0 => false,
+            _ => {
+                *count -= 1;
+                true
+            }
+        }
+    }
+
+    pub fn up(&self) {
+        *self.count.get_mut() += 1;
+        self.waiters.wake_one();
+    }
+}
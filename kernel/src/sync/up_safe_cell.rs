@@ -1,20 +1,70 @@
-use core::cell::{Ref, RefCell, RefMut};
+use core::{
+    cell::{Ref, RefCell, RefMut},
+    ops::{Deref, DerefMut},
+};
+
+use super::preempt::IrqGuard;
 
 pub struct UPSafeCell<T> {
     inner: RefCell<T>,
 }
 unsafe impl<T> Sync for UPSafeCell<T> {}
 
+/// A shared borrow, interrupts stay disabled while it is alive.
+pub struct UPRef<'a, T> {
+    inner: Ref<'a, T>,
+    _irq: IrqGuard,
+}
+
+/// An exclusive borrow, interrupts stay disabled while it is alive.
+pub struct UPRefMut<'a, T> {
+    inner: RefMut<'a, T>,
+    _irq: IrqGuard,
+}
+
 impl<T> UPSafeCell<T> {
     pub const unsafe fn new(val: T) -> Self {
         Self {
             inner: RefCell::new(val),
         }
     }
-    pub fn get(&self) -> Ref<'_, T> {
-        self.inner.borrow()
+    pub fn get(&self) -> UPRef<'_, T> {
+        let _irq = IrqGuard::new();
+        UPRef {
+            inner: self.inner.borrow(),
+            _irq,
+        }
+    }
+    pub fn get_mut(&self) -> UPRefMut<'_, T> {
+        let _irq = IrqGuard::new();
+        UPRefMut {
+            inner: self.inner.borrow_mut(),
+            _irq,
+        }
+    }
+    pub fn try_get_mut(&self) -> Option<UPRefMut<'_, T>> {
+        let _irq = IrqGuard::new();
+        let inner = self.inner.try_borrow_mut().ok()?;
+        Some(UPRefMut { inner, _irq })
     }
-    pub fn get_mut(&self) -> RefMut<'_, T> {
-        self.inner.borrow_mut()
+}
+
+impl<T> Deref for UPRef<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> Deref for UPRefMut<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> DerefMut for UPRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
     }
 }
@@ -1,33 +1,85 @@
 use alloc::vec::Vec;
 
+use super::{
+    copy_from_user, copy_to_user, read_path, user_writable, EBADF, EINTR, EIO, EMFILE, ENOENT,
+    ENOTTY, EPERM,
+};
 use crate::{
-    memory::{PTEFlags, VirtAddr},
-    print,
-    process::{get_current_process, ProcessControlBlock},
+    file::File,
+    memory::{PTEFlags, VirtAddr, PAGE_SIZE},
+    process::{get_current_process, processes, signal, ProcessControlBlock},
+    tty::{self, pty},
 };
 
-const FD_STDOUT: usize = 1;
+/// read up to `len` bytes from a file with `fd` into `buf`, waits until one is available
+///
+/// A terminal hands out a line at a time in canonical mode. A background process group of
+/// the session it belongs to gets `SIGTTIN` and `-EINTR` instead. The master side of a
+/// pseudo-terminal fails with `-EIO` once its slave side was closed.
+pub fn sys_read(fd: usize, buf: usize, len: usize) -> Result<isize, &'static str> {
+    let task = get_current_process();
+    let file = match task.group().file(fd) {
+        Some(file) => file,
+        None => return Ok(-EBADF),
+    };
+    if len == 0 {
+        return Ok(0);
+    }
+    // no more than a line fits in the input queue anyway
+    let len = len.min(PAGE_SIZE);
+    // what is taken from the input cannot be put back, so a bad buffer has to fail first
+    if !user_writable(&task, buf, len) {
+        return Err("Address out of range!");
+    }
+    let mut bytes = vec![0; len];
+    let read = match &*file {
+        File::PtyMaster(master) => match master.read(&mut bytes) {
+            Some(read) => read,
+            None => return Ok(-EIO),
+        },
+        file => {
+            let group = task.group();
+            if file.tty().is_background(group.pgid(), group.sid()) {
+                signal::send_to_group(group.pgid(), signal::SIGTTIN);
+                return Ok(-EINTR);
+            }
+            file.tty().read(&mut bytes)
+        }
+    };
+    for (i, &c) in bytes[..read].iter().enumerate() {
+        let pa = task
+            .translate((buf + i).into(), PTEFlags::W)
+            .map_err(|_| "Address out of range!")?;
+        unsafe { *(pa.0 as *mut u8) = c };
+    }
+    Ok(read as isize)
+}
 
 /// write buf of length `len` to a file with `fd`
+///
+/// What is written to the master side of a pseudo-terminal is taken as typed at its slave
+/// side, which fails with `-EIO` once the master side was closed.
 pub fn sys_write(fd: usize, buf: usize, len: usize) -> Result<isize, &'static str> {
     let task = get_current_process();
-    let contents = get_strs(buf, len, &task)?;
-    match fd {
-        FD_STDOUT => {
-            for ele in contents {
-                print!("{}", ele);
-            }
-            Ok(len as isize)
-        }
-        _ => Err("Unsupported fd in sys_write!"),
+    let file = match task.group().file(fd) {
+        Some(file) => file,
+        None => return Ok(-EBADF),
+    };
+    let contents = get_slices(buf, len, &task)?;
+    match &*file {
+        File::PtyMaster(master) => contents.iter().for_each(|bytes| master.write(bytes)),
+        File::PtySlave(slave) if slave.tty().is_hung_up() => return Ok(-EIO),
+        // copied, the pages may be swapped out while it waits for the master side to read
+        file => file.tty().write(&contents.concat()),
     }
+    Ok(len as isize)
 }
 
-fn get_strs(
+fn get_slices(
     mut buf: usize,
     mut len: usize,
     task: &ProcessControlBlock,
-) -> Result<Vec<&str>, &'static str> {
+) -> Result<Vec<&[u8]>, &'static str> {
     let mut ret = vec![];
     while len > 0 {
         // TODO: this is not safe, because we haven't check the permission.
@@ -36,8 +88,7 @@ fn get_strs(
             assert!(diff > 0);
             // linearly map physical space rather identical map.
             let slice = unsafe { core::slice::from_raw_parts(pa.0 as *const u8, diff) };
-            let s = core::str::from_utf8(slice).unwrap();
-            ret.push(s);
+            ret.push(slice);
             len -= diff;
             buf += diff;
         } else {
@@ -46,3 +97,157 @@ fn get_strs(
     }
     Ok(ret)
 }
+
+/// open the file at `path` and return its fd, the lowest free one
+///
+/// There is no file system, `/dev/console`, `/dev/ptmx` for a new pseudo-terminal and
+/// `/dev/pts/N` for its slave side are all there is. `dirfd`, `flags` and `mode` are not
+/// looked at as paths are absolute and every file is opened for reading and writing.
+pub fn sys_openat(_dirfd: usize, path: usize, _flags: usize, _mode: usize) -> Result<isize, ()> {
+    let task = get_current_process();
+    let path = match read_path(&task, path)? {
+        Some(path) => path,
+        None => return Ok(-ENOENT),
+    };
+    let file = match path.as_slice() {
+        b"/dev/console" => File::Console,
+        b"/dev/ptmx" => File::PtyMaster(pty::open_master()),
+        path => {
+            let index = path
+                .strip_prefix(b"/dev/pts/")
+                .and_then(|index| core::str::from_utf8(index).ok())
+                .and_then(|index| index.parse().ok());
+            match index {
+                Some(index) if pty::exists(index) => match pty::open_slave(index) {
+                    Some(slave) => File::PtySlave(slave),
+                    None => return Ok(-EIO),
+                },
+                _ => return Ok(-ENOENT),
+            }
+        }
+    };
+    let fd = task.group().files().insert(file);
+    Ok(fd.map_or(-EMFILE, |fd| fd as isize))
+}
+
+/// close file `fd`
+pub fn sys_close(fd: usize) -> isize {
+    let file = get_current_process().group().files().remove(fd);
+    match file {
+        Some(file) => {
+            // closing the last handle of it may send signals, the table is free by now
+            drop(file);
+            0
+        }
+        None => -EBADF,
+    }
+}
+
+const TCGETS: usize = 0x5401;
+const TCSETS: usize = 0x5402;
+const TCSETSW: usize = 0x5403;
+const TCSETSF: usize = 0x5404;
+const TIOCSCTTY: usize = 0x540e;
+const TIOCGPGRP: usize = 0x540f;
+const TIOCSPGRP: usize = 0x5410;
+const TIOCSTI: usize = 0x5412;
+const TIOCGWINSZ: usize = 0x5413;
+const TIOCSWINSZ: usize = 0x5414;
+const TIOCGPTN: usize = 0x8004_5430;
+const TIOCSPTLCK: usize = 0x4004_5431;
+
+/// control the terminal `fd` is, for the master side of a pseudo-terminal its slave side
+///
+/// `TCGETS` and `TCSETS` get and set its `Termios` at `arg`, `TCSETSF` drops the input not
+/// read yet as well, and there is no output queue for `TCSETSW` to wait for.
+/// `TIOCGWINSZ` and `TIOCSWINSZ` do the same for its `WinSize`, `TIOCSTI` has the byte at
+/// `arg` taken as if it was typed, on the controlling terminal of the caller only.
+///
+/// `TIOCSCTTY` makes it the controlling terminal of the session the caller leads,
+/// `TIOCGPGRP` and `TIOCSPGRP` get and set its foreground process group as an `i32` at `arg`.
+///
+/// Only for the master side, `TIOCGPTN` tells the number of the pair as a `u32` at `arg`,
+/// and `TIOCSPTLCK` locks or unlocks the slave side as the `i32` at `arg` says.
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> Result<isize, ()> {
+    let task = get_current_process();
+    let group = task.group();
+    let file = match group.file(fd) {
+        Some(file) => file,
+        None => return Ok(-EBADF),
+    };
+    let tty = file.tty();
+    match (&*file, cmd) {
+        (File::PtyMaster(master), TIOCGPTN) => {
+            copy_to_user(&task, arg, &(master.index() as u32))?;
+            return Ok(0);
+        }
+        (File::PtyMaster(master), TIOCSPTLCK) => {
+            let mut lock = 0i32;
+            copy_from_user(&task, arg, &mut lock)?;
+            master.set_locked(lock != 0);
+            return Ok(0);
+        }
+        _ => {}
+    }
+    match cmd {
+        TCGETS => {
+            copy_to_user(&task, arg, &tty.termios())?;
+            Ok(0)
+        }
+        TCSETS | TCSETSW | TCSETSF => {
+            let mut termios = tty.termios();
+            copy_from_user(&task, arg, &mut termios)?;
+            tty.set_termios(termios, cmd == TCSETSF);
+            Ok(0)
+        }
+        TIOCGWINSZ => {
+            copy_to_user(&task, arg, &tty.winsize())?;
+            Ok(0)
+        }
+        TIOCSWINSZ => {
+            let mut winsize = tty.winsize();
+            copy_from_user(&task, arg, &mut winsize)?;
+            tty.set_winsize(winsize);
+            Ok(0)
+        }
+        // or anyone could type into the shell of another session
+        TIOCSTI if tty.foreground(group.sid()).is_none() => Ok(-EPERM),
+        TIOCSTI => {
+            let mut byte = 0u8;
+            copy_from_user(&task, arg, &mut byte)?;
+            tty.receive(byte);
+            Ok(0)
+        }
+        TIOCSCTTY if group.sid() != group.pid() => Ok(-EPERM),
+        // a session has one controlling terminal at most
+        TIOCSCTTY if tty::controlled_elsewhere(tty, group.sid()) => Ok(-EPERM),
+        TIOCSCTTY => match tty.set_controlling(group.sid(), group.pgid()) {
+            true => Ok(0),
+            false => Ok(-EPERM),
+        },
+        TIOCGPGRP => match tty.foreground(group.sid()) {
+            Some(pgid) => {
+                copy_to_user(&task, arg, &(pgid as i32))?;
+                Ok(0)
+            }
+            None => Ok(-ENOTTY),
+        },
+        TIOCSPGRP => {
+            let mut pgid = 0i32;
+            copy_from_user(&task, arg, &mut pgid)?;
+            let pgid = pgid as usize;
+            // only a group of the same session may take it over
+            let in_session = processes()
+                .iter()
+                .any(|other| other.pgid() == pgid && other.sid() == group.sid());
+            if tty.foreground(group.sid()).is_none() {
+                Ok(-ENOTTY)
+            } else if !in_session || !tty.set_foreground(group.sid(), pgid) {
+                Ok(-EPERM)
+            } else {
+                Ok(0)
+            }
+        }
+        _ => Ok(-ENOTTY),
+    }
+}
@@ -0,0 +1,45 @@
+use crate::{
+    memory::{
+        memory_set::SegmentPermission,
+        shared_memory::{shm_get, shm_object, shm_remove},
+    },
+    process::get_current_process,
+};
+
+const SHM_RDONLY: usize = 0o10000;
+const IPC_RMID: usize = 0;
+
+/// get the id of the shared memory object identified by `key`
+pub fn sys_shmget(key: usize, size: usize, flags: usize) -> isize {
+    shm_get(key, size, flags).map_or(-1, |id| id as isize)
+}
+
+/// map the shared memory object `id` into current process, `addr` must be 0 for now
+pub fn sys_shmat(id: usize, addr: usize, flags: usize) -> isize {
+    if addr != 0 {
+        return -1;
+    }
+    let mut perm = SegmentPermission::R | SegmentPermission::U;
+    if flags & SHM_RDONLY == 0 {
+        perm |= SegmentPermission::W;
+    }
+    match shm_object(id) {
+        Some(shm) => get_current_process().attach_shm(shm, perm).0 as isize,
+        None => -1,
+    }
+}
+
+/// unmap the shared memory segment attached at `addr`
+pub fn sys_shmdt(addr: usize) -> isize {
+    match get_current_process().detach_shm(addr.into()) {
+        true => 0,
+        false => -1,
+    }
+}
+
+pub fn sys_shmctl(id: usize, cmd: usize, _buf: usize) -> isize {
+    match cmd {
+        IPC_RMID if shm_remove(id) => 0,
+        _ => -1,
+    }
+}
@@ -1,32 +1,150 @@
 mod fs;
+mod ipc;
 mod process;
+mod signal;
+mod sync;
+
+use self::{
+    fs::{sys_close, sys_ioctl, sys_openat, sys_read, sys_write},
+    ipc::*,
+    process::*,
+    signal::*,
+    sync::*,
+};
+use alloc::vec::Vec;
 
-use self::{fs::sys_write, process::*};
 use crate::{
-    fmt_str, memory::PTEFlags, process::get_current_process, timer::{MICRO_PER_SEC, get_time_us}
+    fmt_str,
+    memory::{frame_allocator::frame_stats, swap, PTEFlags, PAGE_SIZE},
+    process::{get_current_process, processes, ProcessControlBlock},
+    timer::{get_realtime_ns, get_time_ns, get_time_us, MICRO_PER_SEC, NANO_PER_SEC},
 };
 
 pub const MAX_MSG_LEN: usize = 32;
+const SYSCALL_IOCTL: usize = 29;
+const SYSCALL_OPENAT: usize = 56;
+const SYSCALL_CLOSE: usize = 57;
+const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_EXIT_GROUP: usize = 94;
+const SYSCALL_FUTEX: usize = 98;
+const SYSCALL_NANOSLEEP: usize = 101;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_KILL: usize = 129;
+const SYSCALL_SIGACTION: usize = 134;
+const SYSCALL_SIGPROCMASK: usize = 135;
+const SYSCALL_SIGRETURN: usize = 139;
+const SYSCALL_SET_PRIORITY: usize = 140;
+const SYSCALL_GETPRIORITY: usize = 141;
+/// not the Linux 140, which is the stride `set_priority`
+const SYSCALL_SETPRIORITY: usize = 142;
+const SYSCALL_TIMES: usize = 153;
+const SYSCALL_SETPGID: usize = 154;
+const SYSCALL_GETPGID: usize = 155;
+const SYSCALL_GETSID: usize = 156;
+const SYSCALL_SETSID: usize = 157;
+const SYSCALL_GETRUSAGE: usize = 165;
 const SYSCALL_GET_TIME: usize = 169;
+const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETTID: usize = 178;
+const SYSCALL_SYSINFO: usize = 179;
+const SYSCALL_SHMGET: usize = 194;
+const SYSCALL_SHMCTL: usize = 195;
+const SYSCALL_SHMAT: usize = 196;
+const SYSCALL_SHMDT: usize = 197;
+const SYSCALL_CLONE: usize = 220;
+const SYSCALL_EXECVE: usize = 221;
+const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_WAITTID: usize = 462;
+const SYSCALL_ENABLE_DEADLOCK_DETECT: usize = 469;
+const SYSCALL_MUTEX_CREATE: usize = 1010;
+const SYSCALL_MUTEX_LOCK: usize = 1011;
+const SYSCALL_MUTEX_UNLOCK: usize = 1012;
+const SYSCALL_SEMAPHORE_CREATE: usize = 1020;
+const SYSCALL_SEMAPHORE_UP: usize = 1021;
+const SYSCALL_SEMAPHORE_DOWN: usize = 1022;
+const SYSCALL_CONDVAR_CREATE: usize = 1030;
+const SYSCALL_CONDVAR_SIGNAL: usize = 1031;
+const SYSCALL_CONDVAR_WAIT: usize = 1032;
 // use self::fs::*;
 
+// errno values, negated by the syscalls which tell failures apart
+const EPERM: isize = 1;
+const ENOENT: isize = 2;
+const ESRCH: isize = 3;
+const EINTR: isize = 4;
+const EIO: isize = 5;
+const EBADF: isize = 9;
+const ECHILD: isize = 10;
+const EAGAIN: isize = 11;
+const ENOMEM: isize = 12;
+const EINVAL: isize = 22;
+const EMFILE: isize = 24;
+const ENOTTY: isize = 25;
+const EDEADLK: isize = 35;
+const ETIMEDOUT: isize = 110;
+
 /// handle syscall exception with `syscall_id` and other arguments
 pub fn syscall(
     syscall_id: usize,
-    args: [usize; 3],
+    args: [usize; 4],
     error: &mut [u8; MAX_MSG_LEN],
 ) -> Result<isize, ()> {
     match syscall_id {
+        SYSCALL_IOCTL => sys_ioctl(args[0], args[1], args[2]),
+        SYSCALL_OPENAT => sys_openat(args[0], args[1], args[2], args[3]),
+        SYSCALL_CLOSE => Ok(sys_close(args[0])),
+        SYSCALL_READ => sys_read(args[0], args[1], args[2]).or_else(|msg| {
+            fmt_str!(error, "{}", msg).unwrap();
+            Err(())
+        }),
         SYSCALL_WRITE => sys_write(args[0], args[1], args[2]).or_else(|msg| {
             fmt_str!(error, "{}", msg).unwrap();
             Err(())
         }),
         SYSCALL_EXIT => sys_exit(args[0] as i32),
+        SYSCALL_EXIT_GROUP => sys_exit_group(args[0] as i32),
+        SYSCALL_FUTEX => sys_futex(args[0], args[1], args[2], args[3]),
+        SYSCALL_NANOSLEEP => sys_nanosleep(args[0], args[1]),
+        SYSCALL_CLOCK_GETTIME => sys_clock_gettime(args[0], args[1]),
         SYSCALL_YIELD => Ok(sys_yield()),
+        SYSCALL_KILL => Ok(sys_kill(args[0] as isize, args[1])),
+        SYSCALL_SIGACTION => sys_sigaction(args[0], args[1], args[2]),
+        SYSCALL_SIGPROCMASK => sys_sigprocmask(args[0], args[1], args[2]),
+        SYSCALL_SIGRETURN => sys_sigreturn(),
+        SYSCALL_SET_PRIORITY => Ok(sys_set_priority(args[0] as isize)),
+        SYSCALL_SETPRIORITY => Ok(sys_setpriority(args[0], args[1], args[2] as isize)),
+        SYSCALL_GETPRIORITY => Ok(sys_getpriority(args[0], args[1])),
+        SYSCALL_TIMES => sys_times(args[0]),
+        SYSCALL_SETPGID => Ok(sys_setpgid(args[0], args[1])),
+        SYSCALL_GETPGID => Ok(sys_getpgid(args[0])),
+        SYSCALL_GETSID => Ok(sys_getsid(args[0])),
+        SYSCALL_SETSID => Ok(sys_setsid()),
+        SYSCALL_GETRUSAGE => sys_getrusage(args[0] as isize, args[1]),
         SYSCALL_GET_TIME => sys_get_time(args[0], args[1]),
+        SYSCALL_GETPID => Ok(sys_getpid()),
+        SYSCALL_GETTID => Ok(sys_gettid()),
+        SYSCALL_SYSINFO => sys_sysinfo(args[0]),
+        SYSCALL_SHMGET => Ok(sys_shmget(args[0], args[1], args[2])),
+        SYSCALL_SHMCTL => Ok(sys_shmctl(args[0], args[1], args[2])),
+        SYSCALL_SHMAT => Ok(sys_shmat(args[0], args[1], args[2])),
+        SYSCALL_SHMDT => Ok(sys_shmdt(args[0])),
+        SYSCALL_CLONE => Ok(sys_clone(args[0], args[1])),
+        SYSCALL_EXECVE => sys_execve(args[0]),
+        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1], args[2]),
+        SYSCALL_WAITTID => Ok(sys_waittid(args[0])),
+        SYSCALL_ENABLE_DEADLOCK_DETECT => Ok(sys_enable_deadlock_detect(args[0])),
+        SYSCALL_MUTEX_CREATE => Ok(sys_mutex_create()),
+        SYSCALL_MUTEX_LOCK => Ok(sys_mutex_lock(args[0])),
+        SYSCALL_MUTEX_UNLOCK => Ok(sys_mutex_unlock(args[0])),
+        SYSCALL_SEMAPHORE_CREATE => Ok(sys_semaphore_create(args[0])),
+        SYSCALL_SEMAPHORE_UP => Ok(sys_semaphore_up(args[0])),
+        SYSCALL_SEMAPHORE_DOWN => Ok(sys_semaphore_down(args[0])),
+        SYSCALL_CONDVAR_CREATE => Ok(sys_condvar_create()),
+        SYSCALL_CONDVAR_SIGNAL => Ok(sys_condvar_signal(args[0])),
+        SYSCALL_CONDVAR_WAIT => Ok(sys_condvar_wait(args[0], args[1])),
         _ => {
             fmt_str!(error, "Unsupported syscall_id: {:#x}", syscall_id).unwrap();
             Err(())
@@ -38,7 +156,7 @@ fn sys_get_time(va: usize, _tz: usize) -> Result<isize, ()> {
     let pa = task.translate(va.into(), PTEFlags::W)?;
     // TODO: this is not safe, because we haven't check the permission.
     let ts = pa.0 as *mut TimeVal;
-    let t = get_time_us();
+    let t = get_realtime_ns() / (NANO_PER_SEC / MICRO_PER_SEC);
     unsafe {
         (*ts).sec = t / MICRO_PER_SEC;
         (*ts).usec = t % MICRO_PER_SEC;
@@ -47,8 +165,139 @@ fn sys_get_time(va: usize, _tz: usize) -> Result<isize, ()> {
     Ok(0)
 }
 
+const CLOCK_REALTIME: usize = 0;
+const CLOCK_MONOTONIC: usize = 1;
+const CLOCK_PROCESS_CPUTIME_ID: usize = 2;
+
+fn sys_clock_gettime(clock_id: usize, va: usize) -> Result<isize, ()> {
+    let task = get_current_process();
+    let t = match clock_id {
+        CLOCK_REALTIME => get_realtime_ns(),
+        CLOCK_MONOTONIC => get_time_ns(),
+        CLOCK_PROCESS_CPUTIME_ID => task.group().cpu_time_us() * (NANO_PER_SEC / MICRO_PER_SEC),
+        _ => return Ok(-EINVAL),
+    };
+    let ts = TimeSpec {
+        sec: t / NANO_PER_SEC,
+        nsec: t % NANO_PER_SEC,
+    };
+    copy_to_user(&task, va, &ts)?;
+    Ok(0)
+}
+
+/// Linux layout, sizes are in bytes as `mem_unit` is 1 and there are no load averages.
+#[repr(C)]
+#[derive(Default)]
+pub struct Sysinfo {
+    pub uptime: usize,
+    pub loads: [usize; 3],
+    /// memory the kernel hands out to processes and itself, its image not included
+    pub totalram: usize,
+    pub freeram: usize,
+    pub sharedram: usize,
+    pub bufferram: usize,
+    pub totalswap: usize,
+    pub freeswap: usize,
+    pub procs: u16,
+    pub pad: u16,
+    pub totalhigh: usize,
+    pub freehigh: usize,
+    pub mem_unit: u32,
+}
+
+fn sys_sysinfo(va: usize) -> Result<isize, ()> {
+    let (frames, swap) = (frame_stats(), swap::stats());
+    let info = Sysinfo {
+        uptime: get_time_us() / MICRO_PER_SEC,
+        totalram: frames.total * PAGE_SIZE,
+        freeram: frames.free * PAGE_SIZE,
+        totalswap: swap.total * PAGE_SIZE,
+        freeswap: swap.free * PAGE_SIZE,
+        procs: processes().len() as u16,
+        mem_unit: 1,
+        ..Default::default()
+    };
+    copy_to_user(&get_current_process(), va, &info)?;
+    Ok(0)
+}
+
+/// Copy `val` out to `va` of `task`, page by page as it may straddle a page boundary.
+pub(crate) fn copy_to_user<T>(task: &ProcessControlBlock, va: usize, val: &T) -> Result<(), ()> {
+    let bytes = unsafe {
+        core::slice::from_raw_parts(val as *const T as *const u8, core::mem::size_of::<T>())
+    };
+    let mut done = 0;
+    while done < bytes.len() {
+        let addr = va + done;
+        let pa = task.translate(addr.into(), PTEFlags::W)?;
+        let len = (PAGE_SIZE - addr % PAGE_SIZE).min(bytes.len() - done);
+        unsafe { core::ptr::copy_nonoverlapping(bytes[done..].as_ptr(), pa.0 as *mut u8, len) };
+        done += len;
+    }
+    Ok(())
+}
+
+/// Whether `len` bytes at `va` of `task` may be written, pages swapped out or not touched yet
+/// are brought in on the way.
+pub(crate) fn user_writable(task: &ProcessControlBlock, va: usize, len: usize) -> bool {
+    let last = match len.checked_sub(1).and_then(|len| va.checked_add(len)) {
+        Some(last) => last,
+        None => return len == 0,
+    };
+    (va / PAGE_SIZE..=last / PAGE_SIZE).all(|page| {
+        let va = (page * PAGE_SIZE).into();
+        task.translate(va, PTEFlags::W).is_ok()
+    })
+}
+
+/// Paths are no longer than that.
+const PATH_MAX: usize = 64;
+
+/// The NUL-terminated path at `va`, `None` if it is too long.
+fn read_path(task: &ProcessControlBlock, va: usize) -> Result<Option<Vec<u8>>, ()> {
+    let mut path = Vec::new();
+    while path.len() < PATH_MAX {
+        let mut byte = 0u8;
+        copy_from_user(task, va + path.len(), &mut byte)?;
+        if byte == 0 {
+            return Ok(Some(path));
+        }
+        path.push(byte);
+    }
+    Ok(None)
+}
+
+/// Copy what is at `va` of `task` into `val`, the counterpart of `copy_to_user`.
+pub(crate) fn copy_from_user<T>(
+    task: &ProcessControlBlock,
+    va: usize,
+    val: &mut T,
+) -> Result<(), ()> {
+    let bytes = unsafe {
+        core::slice::from_raw_parts_mut(val as *mut T as *mut u8, core::mem::size_of::<T>())
+    };
+    let mut done = 0;
+    while done < bytes.len() {
+        let addr = va + done;
+        let pa = task.translate(addr.into(), PTEFlags::R)?;
+        let len = (PAGE_SIZE - addr % PAGE_SIZE).min(bytes.len() - done);
+        unsafe {
+            core::ptr::copy_nonoverlapping(pa.0 as *const u8, bytes[done..].as_mut_ptr(), len)
+        };
+        done += len;
+    }
+    Ok(())
+}
+
 #[repr(C)]
+#[derive(Default)]
 pub struct TimeVal {
     pub sec: usize,
     pub usec: usize,
 }
+
+#[repr(C)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
+}
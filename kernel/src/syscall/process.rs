@@ -1,12 +1,358 @@
-use crate::{info, process};
+use super::{
+    copy_from_user, copy_to_user, read_path, TimeSpec, TimeVal, ECHILD, EINVAL, ENOENT, ENOMEM,
+    EPERM, ESRCH,
+};
+use crate::{
+    info,
+    memory::PAGE_SIZE,
+    process::{
+        self, find_process, find_thread, get_current_process, processes, signal::SIGCHLD,
+        ProcessControlBlock, ThreadGroup, WaitEvent, WaitOptions,
+    },
+    sync::preempt::without_interrupts,
+    timer::{
+        add_timer, cancel_timer, get_time, get_time_us, ns_to_ticks, MICRO_PER_SEC, NANO_PER_SEC,
+    },
+};
+use alloc::sync::Arc;
 
-/// task exits and submit an exit code
+/// the calling thread exits with an exit code, which is the one of the process if it was the
+/// last thread
 pub fn sys_exit(exit_code: i32) -> ! {
+    process::exit_current(exit_code)
+}
+
+/// every thread of the process exits and submit an exit code
+pub fn sys_exit_group(exit_code: i32) -> ! {
     info!("[kernel] Application exited with code {}", exit_code);
-    process::exit_current()
+    process::exit_group_current(exit_code)
+}
+
+pub fn sys_getpid() -> isize {
+    get_current_process().pid() as isize
+}
+
+pub fn sys_gettid() -> isize {
+    get_current_process().tid() as isize
+}
+
+const CLONE_VM: usize = 0x100;
+const CLONE_FILES: usize = 0x400;
+const CLONE_THREAD: usize = 0x10000;
+
+/// start a thread of the calling process on the user stack at `stack`, a fresh one if it is 0,
+/// or fork it
+///
+/// The child returns 0 from the syscall, the parent its tid. A thread takes
+/// `CLONE_VM | CLONE_THREAD` and `CLONE_FILES` is implied, the threads share the fd table.
+/// `SIGCHLD` alone forks, as `fork` does on Linux, and the parent gets the pid of the child.
+pub fn sys_clone(flags: usize, stack: usize) -> isize {
+    let thread = CLONE_VM | CLONE_THREAD;
+    if flags == SIGCHLD {
+        return match process::fork_current() {
+            Some(child) => child.pid() as isize,
+            None => -ENOMEM,
+        };
+    }
+    if flags & thread != thread || flags & !(thread | CLONE_FILES) != 0 {
+        return -1;
+    }
+    process::clone_current(stack).tid() as isize
+}
+
+/// run the app named `path` in place of the calling process, the other threads exit
+///
+/// There is no file system, apps are looked up by name. Arguments and the environment are
+/// not passed on. Returns only if it fails, with `-ENOENT` if there is no such app.
+pub fn sys_execve(path: usize) -> Result<isize, ()> {
+    let path = match read_path(&get_current_process(), path)? {
+        Some(path) => path,
+        None => return Ok(-ENOENT),
+    };
+    let name = core::str::from_utf8(&path).map_err(|_| ())?;
+    match process::exec_current(name) {
+        true => Ok(0),
+        false => Ok(-ENOENT),
+    }
+}
+
+/// wait for thread `tid` of the calling process to exit, returns its exit code
+pub fn sys_waittid(tid: usize) -> isize {
+    let task = get_current_process();
+    match find_thread(tid) {
+        Some(thread) if thread.pid() == task.pid() && thread.tid() != task.tid() => {
+            thread.wait_exit() as isize
+        }
+        _ => -1,
+    }
 }
 
 pub fn sys_yield() -> isize {
     process::suspend_current();
     0
 }
+
+/// sleep for the duration at `req`, `rem` is zeroed since sleeps are never interrupted
+pub fn sys_nanosleep(req: usize, rem: usize) -> Result<isize, ()> {
+    let task = get_current_process();
+    let mut ts = TimeSpec { sec: 0, nsec: 0 };
+    copy_from_user(&task, req, &mut ts)?;
+    if ts.nsec >= NANO_PER_SEC {
+        return Ok(-EINVAL);
+    }
+    let deadline = get_time() + ns_to_ticks(ts.sec * NANO_PER_SEC + ts.nsec);
+    // the timer must not fire before the task is marked pending
+    without_interrupts(|| {
+        add_timer(deadline, task.clone());
+        process::block_current();
+        // a kill wakes it early
+        cancel_timer(&task);
+    });
+    if rem != 0 {
+        copy_to_user(&task, rem, &TimeSpec { sec: 0, nsec: 0 })?;
+    }
+    Ok(0)
+}
+
+const WNOHANG: usize = 1;
+const WUNTRACED: usize = 2;
+const WCONTINUED: usize = 8;
+
+/// the status `waitpid` stores, laid out as on Linux
+fn wait_status(event: WaitEvent) -> i32 {
+    match event {
+        WaitEvent::Exited(code) => (code & 0xff) << 8,
+        WaitEvent::Killed(sig) => sig as i32,
+        WaitEvent::Stopped(sig) => ((sig as i32) << 8) | 0x7f,
+        WaitEvent::Continued => 0xffff,
+    }
+}
+
+/// wait for child `pid` to exit and store its status at `status` unless it is null, returns
+/// the pid of the child
+///
+/// `-1` waits for any child, 0 for any in the process group of the caller and `-pgid` for
+/// any in process group `pgid`. With `WUNTRACED` and `WCONTINUED` it also returns once the
+/// child stopped or continued, with `WNOHANG` it returns 0 right away if nothing happened
+/// yet. Returns `-ECHILD` if there is no such child.
+pub fn sys_waitpid(pid: isize, status: usize, options: usize) -> Result<isize, ()> {
+    if options & !(WNOHANG | WUNTRACED | WCONTINUED) != 0 {
+        return Ok(-EINVAL);
+    }
+    let task = get_current_process();
+    let group = task.group();
+    let pgid = group.pgid();
+    let wanted = |child: &ThreadGroup| match pid {
+        -1 => true,
+        0 => child.pgid() == pgid,
+        pid if pid < 0 => child.pgid() == pid.unsigned_abs(),
+        pid => child.pid() == pid as usize,
+    };
+    let options = WaitOptions {
+        stopped: options & WUNTRACED != 0,
+        continued: options & WCONTINUED != 0,
+        nohang: options & WNOHANG != 0,
+    };
+    let (child, event) = match group.wait_child(wanted, options) {
+        Some(found) => found,
+        None if group.children(wanted).is_empty() => return Ok(-ECHILD),
+        None => return Ok(0),
+    };
+    if let WaitEvent::Exited(_) | WaitEvent::Killed(_) = event {
+        group.reap(&child);
+    }
+    if status != 0 {
+        copy_to_user(&task, status, &wait_status(event))?;
+    }
+    Ok(child.pid() as isize)
+}
+
+/// the process `pid` names, the caller if it is 0
+fn process_or_current(pid: usize) -> Option<Arc<ProcessControlBlock>> {
+    match pid {
+        0 => Some(get_current_process()),
+        pid => find_process(pid),
+    }
+}
+
+/// move process `pid` into process group `pgid`, a new one named by its pid if `pgid` is the
+/// pid or 0, either may be 0 for the caller
+///
+/// Only the caller and its children can be moved, within the session of the caller and
+/// unless they lead a session.
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    let task = get_current_process();
+    let target = match process_or_current(pid) {
+        Some(target) if target.pid() == task.pid() || task.group().is_parent_of(target.pid()) => {
+            target
+        }
+        _ => return -ESRCH,
+    };
+    let group = target.group();
+    let pgid = match pgid {
+        0 => group.pid(),
+        pgid => pgid,
+    };
+    let joinable = pgid == group.pid()
+        || processes()
+            .iter()
+            .any(|other| other.pgid() == pgid && other.sid() == group.sid());
+    if group.sid() != task.group().sid() || group.sid() == group.pid() || !joinable {
+        return -EPERM;
+    }
+    group.set_pgid(pgid);
+    0
+}
+
+/// the process group of process `pid`, or of the caller if it is 0
+pub fn sys_getpgid(pid: usize) -> isize {
+    match process_or_current(pid) {
+        Some(target) => target.group().pgid() as isize,
+        None => -ESRCH,
+    }
+}
+
+/// the session of process `pid`, or of the caller if it is 0
+pub fn sys_getsid(pid: usize) -> isize {
+    match process_or_current(pid) {
+        Some(target) => target.group().sid() as isize,
+        None => -ESRCH,
+    }
+}
+
+/// lead a new session, without a controlling terminal, and a new process group in it,
+/// returns the new session id
+///
+/// A process group leader may not, the group would be split across sessions.
+pub fn sys_setsid() -> isize {
+    let task = get_current_process();
+    let pid = task.pid();
+    if processes().iter().any(|group| group.pgid() == pid) {
+        return -EPERM;
+    }
+    task.group().set_sid();
+    pid as isize
+}
+
+/// set the priority of the current process, returns it or -1 if it is below 2
+pub fn sys_set_priority(prio: isize) -> isize {
+    match usize::try_from(prio) {
+        Ok(prio) if get_current_process().set_priority(prio) => prio as isize,
+        _ => -1,
+    }
+}
+
+const PRIO_PROCESS: usize = 0;
+
+/// process `who` of `setpriority`/`getpriority`, the caller if it is 0
+fn priority_target(which: usize, who: usize) -> Option<Arc<ProcessControlBlock>> {
+    match (which, who) {
+        (PRIO_PROCESS, 0) => Some(get_current_process()),
+        (PRIO_PROCESS, pid) => find_process(pid),
+        _ => None,
+    }
+}
+
+/// set the nice value of a process, only `PRIO_PROCESS` is supported
+pub fn sys_setpriority(which: usize, who: usize, nice: isize) -> isize {
+    match priority_target(which, who) {
+        Some(pcb) => {
+            pcb.set_nice(nice);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// the nice value of a process as `20 - nice`, which leaves -1 for errors
+pub fn sys_getpriority(which: usize, who: usize) -> isize {
+    match priority_target(which, who) {
+        Some(pcb) => 20 - pcb.nice(),
+        None => -1,
+    }
+}
+
+/// clock ticks per second of `times`
+const CLOCK_TICKS_PER_SEC: usize = 100;
+
+#[repr(C)]
+pub struct Tms {
+    pub utime: usize,
+    pub stime: usize,
+    pub cutime: usize,
+    pub cstime: usize,
+}
+
+fn us_to_clock_ticks(us: usize) -> usize {
+    us / (MICRO_PER_SEC / CLOCK_TICKS_PER_SEC)
+}
+
+/// fill `buf` with the CPU time of all threads of the current process and its reaped children
+/// in clock ticks, returns the ticks since boot
+pub fn sys_times(buf: usize) -> Result<isize, ()> {
+    let task = get_current_process();
+    let (usage, children) = (task.group().usage(), task.group().children_usage());
+    let tms = Tms {
+        utime: us_to_clock_ticks(usage.utime),
+        stime: us_to_clock_ticks(usage.stime),
+        cutime: us_to_clock_ticks(children.utime),
+        cstime: us_to_clock_ticks(children.stime),
+    };
+    copy_to_user(&task, buf, &tms)?;
+    Ok(us_to_clock_ticks(get_time_us()) as isize)
+}
+
+const RUSAGE_SELF: isize = 0;
+const RUSAGE_CHILDREN: isize = -1;
+
+/// Linux layout, the fields which are not tracked stay 0.
+#[repr(C)]
+#[derive(Default)]
+pub struct Rusage {
+    pub utime: TimeVal,
+    pub stime: TimeVal,
+    /// in kilobytes
+    pub maxrss: usize,
+    pub ixrss: usize,
+    pub idrss: usize,
+    pub isrss: usize,
+    pub minflt: usize,
+    pub majflt: usize,
+    pub nswap: usize,
+    pub inblock: usize,
+    pub oublock: usize,
+    pub msgsnd: usize,
+    pub msgrcv: usize,
+    pub nsignals: usize,
+    pub nvcsw: usize,
+    pub nivcsw: usize,
+}
+
+fn us_to_timeval(us: usize) -> TimeVal {
+    TimeVal {
+        sec: us / MICRO_PER_SEC,
+        usec: us % MICRO_PER_SEC,
+    }
+}
+
+/// fill `buf` with the usage of the current process or of its reaped children
+pub fn sys_getrusage(who: isize, buf: usize) -> Result<isize, ()> {
+    let task = get_current_process();
+    let usage = match who {
+        RUSAGE_SELF => task.group().usage(),
+        RUSAGE_CHILDREN => task.group().children_usage(),
+        _ => return Ok(-1),
+    };
+    let rusage = Rusage {
+        utime: us_to_timeval(usage.utime),
+        stime: us_to_timeval(usage.stime),
+        maxrss: usage.maxrss * PAGE_SIZE / 1024,
+        minflt: usage.minflt,
+        majflt: usage.majflt,
+        nvcsw: usage.nvcsw,
+        nivcsw: usage.nivcsw,
+        ..Default::default()
+    };
+    copy_to_user(&task, buf, &rusage)?;
+    Ok(0)
+}
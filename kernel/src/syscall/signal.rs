@@ -0,0 +1,111 @@
+use alloc::vec::Vec;
+
+use super::{copy_from_user, copy_to_user, EINVAL, EPERM, ESRCH};
+use crate::process::{
+    find_process, get_current_process, processes,
+    signal::{self, SigAction, SIGKILL, SIGSTOP},
+};
+
+/// send signal `sig` to process `pid`, a `sig` of 0 only checks that it exists
+///
+/// A `pid` of 0 sends it to the process group of the caller, -1 to every other process and
+/// below that to process group `-pid`.
+pub fn sys_kill(pid: isize, sig: usize) -> isize {
+    if sig != 0 && !signal::is_valid(sig) {
+        return -EINVAL;
+    }
+    let pgid = match pid {
+        0 => get_current_process().group().pgid(),
+        -1 => return kill_all(sig),
+        pid if pid < 0 => pid.unsigned_abs(),
+        pid => return kill_one(pid as usize, sig),
+    };
+    let found = match sig {
+        0 => processes().iter().any(|group| group.pgid() == pgid),
+        sig => signal::send_to_group(pgid, sig),
+    };
+    match found {
+        true => 0,
+        false => -ESRCH,
+    }
+}
+
+fn kill_one(pid: usize, sig: usize) -> isize {
+    let target = match find_process(pid) {
+        Some(target) => target,
+        None => return -ESRCH,
+    };
+    if target.is_kernel_thread() {
+        return -EPERM;
+    }
+    if sig != 0 {
+        signal::send(target.group(), sig);
+    }
+    0
+}
+
+fn kill_all(sig: usize) -> isize {
+    let current = get_current_process().pid();
+    let others = processes()
+        .into_iter()
+        .filter(|group| group.pid() != current)
+        .collect::<Vec<_>>();
+    if sig != 0 {
+        for group in others.iter() {
+            signal::send(group, sig);
+        }
+    }
+    match others.is_empty() {
+        true => -ESRCH,
+        false => 0,
+    }
+}
+
+/// set the action for `sig` from `act` and store the old one at `oldact`, either may be null
+pub fn sys_sigaction(sig: usize, act: usize, oldact: usize) -> Result<isize, ()> {
+    if !signal::is_valid(sig) || (act != 0 && (sig == SIGKILL || sig == SIGSTOP)) {
+        return Ok(-EINVAL);
+    }
+    let task = get_current_process();
+    let mut action = SigAction::default();
+    if act != 0 {
+        copy_from_user(&task, act, &mut action)?;
+    }
+    if oldact != 0 {
+        copy_to_user(&task, oldact, &signal::action(sig))?;
+    }
+    if act != 0 {
+        signal::set_action(sig, action);
+    }
+    Ok(0)
+}
+
+const SIG_BLOCK: usize = 0;
+const SIG_UNBLOCK: usize = 1;
+const SIG_SETMASK: usize = 2;
+
+/// change the signals the calling thread blocks by `set` as `how` says, unless it is null,
+/// and store the old mask at `oldset` unless that is
+pub fn sys_sigprocmask(how: usize, set: usize, oldset: usize) -> Result<isize, ()> {
+    let task = get_current_process();
+    let old = signal::mask();
+    if set != 0 {
+        let mut mask = 0usize;
+        copy_from_user(&task, set, &mut mask)?;
+        match how {
+            SIG_BLOCK => signal::set_mask(old | mask),
+            SIG_UNBLOCK => signal::set_mask(old & !mask),
+            SIG_SETMASK => signal::set_mask(mask),
+            _ => return Ok(-EINVAL),
+        }
+    }
+    if oldset != 0 {
+        copy_to_user(&task, oldset, &old)?;
+    }
+    Ok(0)
+}
+
+/// return from a signal handler to where the thread was before, only for the restorer
+pub fn sys_sigreturn() -> Result<isize, ()> {
+    signal::sigreturn()
+}
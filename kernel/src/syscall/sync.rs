@@ -0,0 +1,233 @@
+use alloc::sync::Arc;
+
+use super::{TimeSpec, EAGAIN, EDEADLK, EINTR, EINVAL, EPERM, ETIMEDOUT};
+use crate::{
+    memory::PTEFlags,
+    process::{deadlock::Resource, get_current_process, ProcessControlBlock},
+    sync::{
+        futex::{self, FutexError},
+        Condvar, Mutex, Semaphore,
+    },
+    timer::{get_time, ns_to_ticks, NANO_PER_SEC},
+};
+
+const FUTEX_WAIT: usize = 0;
+const FUTEX_WAKE: usize = 1;
+/// the futex is not shared with other processes, which changes nothing here as only shared
+/// memory is keyed across processes anyway
+const FUTEX_PRIVATE_FLAG: usize = 128;
+
+/// `FUTEX_WAIT` blocks while the word at `uaddr` holds `val`, for at most the relative
+/// `timeout` unless it is null. `FUTEX_WAKE` wakes up to `val` waiters and returns how many.
+pub fn sys_futex(uaddr: usize, op: usize, val: usize, timeout: usize) -> Result<isize, ()> {
+    if uaddr % 4 != 0 {
+        return Ok(-EINVAL);
+    }
+    let task = get_current_process();
+    let pa = task.translate(uaddr.into(), PTEFlags::R)?;
+    let key = task.futex_key(uaddr.into(), pa);
+    match op & !FUTEX_PRIVATE_FLAG {
+        FUTEX_WAIT => {
+            let deadline = match timeout {
+                0 => None,
+                _ => {
+                    let pa = task.translate(timeout.into(), PTEFlags::R)?;
+                    let timeout = unsafe { &*(pa.0 as *const TimeSpec) };
+                    let ns = timeout
+                        .sec
+                        .checked_mul(NANO_PER_SEC)
+                        .and_then(|ns| ns.checked_add(timeout.nsec));
+                    match ns {
+                        Some(ns) if timeout.nsec < NANO_PER_SEC => {
+                            Some(get_time().saturating_add(ns_to_ticks(ns)))
+                        }
+                        _ => return Ok(-EINVAL),
+                    }
+                }
+            };
+            // the word may have been swapped out again since it was faulted in
+            let locate = || {
+                task.translate_resident(uaddr.into(), PTEFlags::R)
+                    .ok()
+                    .map(|pa| pa.0)
+            };
+            match futex::wait(key, locate, val as u32, deadline) {
+                Ok(()) => Ok(0),
+                Err(FutexError::Again) => Ok(-EAGAIN),
+                Err(FutexError::TimedOut) => Ok(-ETIMEDOUT),
+            }
+        }
+        FUTEX_WAKE => Ok(futex::wake(key, val) as isize),
+        _ => Ok(-EINVAL),
+    }
+}
+
+// Locks held across syscalls have their guard forgotten, and a guard is made up again to
+// unlock them. Handles are looked up first, as the handle table must not stay borrowed
+// while blocking.
+
+/// create a mutex of the calling process, returns its id
+pub fn sys_mutex_create() -> isize {
+    let task = get_current_process();
+    let mut handles = task.group().sync_handles();
+    let id = handles.mutexes.insert(Arc::new(Mutex::new(())));
+    handles.deadlock.add_resource(Resource::Mutex(id), 1);
+    id as isize
+}
+
+/// Note that the calling thread waits for `res`, `-EDEADLK` if detection is on and it might
+/// wait forever.
+fn request(task: &ProcessControlBlock, res: Resource) -> Result<(), isize> {
+    let mut handles = task.group().sync_handles();
+    match handles.deadlock.request(task.tid(), res) {
+        true => Ok(()),
+        false => Err(-EDEADLK),
+    }
+}
+
+fn grant(task: &ProcessControlBlock, res: Resource) {
+    task.group().sync_handles().deadlock.grant(task.tid(), res);
+}
+
+fn release(task: &ProcessControlBlock, res: Resource) {
+    task.group()
+        .sync_handles()
+        .deadlock
+        .release(task.tid(), res);
+}
+
+pub fn sys_mutex_lock(id: usize) -> isize {
+    let task = get_current_process();
+    let mutex = match task.group().sync_handles().mutexes.get(id) {
+        Some(mutex) => mutex,
+        None => return -EINVAL,
+    };
+    if let Err(err) = request(&task, Resource::Mutex(id)) {
+        return err;
+    }
+    match mutex.lock_killable() {
+        Some(guard) => core::mem::forget(guard),
+        None => return -EINTR,
+    }
+    grant(&task, Resource::Mutex(id));
+    0
+}
+
+/// unlock a mutex held by the calling thread
+pub fn sys_mutex_unlock(id: usize) -> isize {
+    let task = get_current_process();
+    let mutex = task.group().sync_handles().mutexes.get(id);
+    match mutex {
+        Some(mutex) if mutex.owner() == Some(task.tid()) => {
+            release(&task, Resource::Mutex(id));
+            drop(unsafe { mutex.guard_unchecked() });
+            0
+        }
+        Some(_) => -EPERM,
+        None => -EINVAL,
+    }
+}
+
+/// create a semaphore of the calling process with `count` to take, returns its id
+pub fn sys_semaphore_create(count: usize) -> isize {
+    let task = get_current_process();
+    let mut handles = task.group().sync_handles();
+    let id = handles.semaphores.insert(Arc::new(Semaphore::new(count)));
+    handles
+        .deadlock
+        .add_resource(Resource::Semaphore(id), count);
+    id as isize
+}
+
+pub fn sys_semaphore_up(id: usize) -> isize {
+    let task = get_current_process();
+    let semaphore = task.group().sync_handles().semaphores.get(id);
+    match semaphore {
+        Some(semaphore) => {
+            release(&task, Resource::Semaphore(id));
+            semaphore.up();
+            0
+        }
+        None => -EINVAL,
+    }
+}
+
+pub fn sys_semaphore_down(id: usize) -> isize {
+    let task = get_current_process();
+    let semaphore = match task.group().sync_handles().semaphores.get(id) {
+        Some(semaphore) => semaphore,
+        None => return -EINVAL,
+    };
+    if let Err(err) = request(&task, Resource::Semaphore(id)) {
+        return err;
+    }
+    if !semaphore.down_killable() {
+        return -EINTR;
+    }
+    grant(&task, Resource::Semaphore(id));
+    0
+}
+
+/// create a condition variable of the calling process, returns its id
+pub fn sys_condvar_create() -> isize {
+    let condvar = Arc::new(Condvar::new());
+    get_current_process()
+        .group()
+        .sync_handles()
+        .condvars
+        .insert(condvar) as isize
+}
+
+/// wake one thread waiting on the condition variable
+pub fn sys_condvar_signal(id: usize) -> isize {
+    let condvar = get_current_process()
+        .group()
+        .sync_handles()
+        .condvars
+        .get(id);
+    match condvar {
+        Some(condvar) => {
+            condvar.notify_one();
+            0
+        }
+        None => -EINVAL,
+    }
+}
+
+/// unlock mutex `mutex_id`, which the calling thread must hold, and sleep on the condition
+/// variable until signaled, the mutex is locked again on return
+pub fn sys_condvar_wait(id: usize, mutex_id: usize) -> isize {
+    let task = get_current_process();
+    let (condvar, mutex) = {
+        let handles = task.group().sync_handles();
+        (handles.condvars.get(id), handles.mutexes.get(mutex_id))
+    };
+    match (condvar, mutex) {
+        (Some(condvar), Some(mutex)) if mutex.owner() == Some(task.tid()) => {
+            release(&task, Resource::Mutex(mutex_id));
+            let guard = unsafe { mutex.guard_unchecked() };
+            match condvar.wait_killable(guard) {
+                Some(guard) => core::mem::forget(guard),
+                None => return -EINTR,
+            }
+            // it held the mutex before, so getting it back is not checked
+            grant(&task, Resource::Mutex(mutex_id));
+            0
+        }
+        (Some(_), Some(_)) => -EPERM,
+        _ => -EINVAL,
+    }
+}
+
+/// turn deadlock detection for the mutexes and semaphores of the calling process on with 1,
+/// or off with 0
+pub fn sys_enable_deadlock_detect(enabled: usize) -> isize {
+    let enabled = match enabled {
+        0 => false,
+        1 => true,
+        _ => return -EINVAL,
+    };
+    let task = get_current_process();
+    task.group().sync_handles().deadlock.set_enabled(enabled);
+    0
+}
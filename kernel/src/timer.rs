@@ -1,18 +1,144 @@
+use alloc::{collections::BinaryHeap, sync::Arc};
+use core::{
+    cmp::Ordering as CmpOrdering,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 use riscv::register::time;
 
 pub fn get_time() -> usize {
     time::read()
 }
 
-use crate::{configs::CLOCK_FREQ, sbi::set_timer};
+use crate::{
+    device_tree::machine,
+    process::{wakeup, ProcessControlBlock},
+    sbi::set_timer,
+    sync::UPSafeCell,
+};
 const TICKS_PER_SEC: usize = 100;
 
+/// End of the time slice of the running process, in `time` register ticks.
+static SLICE_END: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+fn tick_len() -> usize {
+    machine().timebase_frequency / TICKS_PER_SEC
+}
+
+/// Give the process about to run `us` microseconds.
+pub fn start_time_slice(us: usize) {
+    let ticks = us * (machine().timebase_frequency / MICRO_PER_SEC);
+    SLICE_END.store(get_time() + ticks, Ordering::Relaxed);
+    set_next_trigger();
+}
+
+pub fn time_slice_expired() -> bool {
+    get_time() >= SLICE_END.load(Ordering::Relaxed)
+}
+
+/// Program the nearer of the end of the time slice and the earliest sleeper.
 pub fn set_next_trigger() {
-    set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
+    let now = get_time();
+    // an expired slice which is not given up yet is checked again a tick later
+    let slice_end = match SLICE_END.load(Ordering::Relaxed) {
+        end if end <= now => now + tick_len(),
+        end => end,
+    };
+    let next = TIMERS
+        .get()
+        .peek()
+        .map_or(slice_end, |timer| timer.deadline.min(slice_end));
+    set_timer(next);
+}
+
+/// Program only the earliest sleeper, if any, while there is nothing to schedule.
+pub fn set_idle_trigger() {
+    SLICE_END.store(usize::MAX, Ordering::Relaxed);
+    let next = TIMERS
+        .get()
+        .peek()
+        .map_or(usize::MAX, |timer| timer.deadline);
+    set_timer(next);
+}
+
+lazy_static::lazy_static! {
+    static ref TIMERS: UPSafeCell<BinaryHeap<Timer>> = unsafe {
+        UPSafeCell::new(BinaryHeap::new())
+    };
+}
+
+/// A process sleeping until `deadline`, in `time` register ticks.
+struct Timer {
+    deadline: usize,
+    process: Arc<ProcessControlBlock>,
+}
+
+// `BinaryHeap` is a max-heap, so the earliest deadline has to compare greatest
+impl Ord for Timer {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        other.deadline.cmp(&self.deadline)
+    }
+}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Timer {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for Timer {}
+
+/// Wake `process` up once `get_time()` reaches `deadline`.
+pub fn add_timer(deadline: usize, process: Arc<ProcessControlBlock>) {
+    TIMERS.get_mut().push(Timer { deadline, process });
+    set_next_trigger();
+}
+
+/// Drop the timers of `process`, once it was woken by something else.
+pub fn cancel_timer(process: &Arc<ProcessControlBlock>) {
+    TIMERS
+        .get_mut()
+        .retain(|timer| !Arc::ptr_eq(&timer.process, process));
+}
+
+/// Wake up every sleeper whose deadline has passed.
+pub fn check_timers() {
+    let now = get_time();
+    let mut timers = TIMERS.get_mut();
+    while timers.peek().is_some_and(|timer| timer.deadline <= now) {
+        wakeup(&timers.pop().unwrap().process);
+    }
+}
+
+/// Convert a duration in nanoseconds to `time` register ticks.
+pub fn ns_to_ticks(ns: usize) -> usize {
+    (ns as u128 * machine().timebase_frequency as u128 / NANO_PER_SEC as u128) as usize
 }
 
 pub const MICRO_PER_SEC: usize = 1_000_000;
+pub const NANO_PER_SEC: usize = 1_000_000_000;
 
 pub fn get_time_us() -> usize {
-    time::read() / (CLOCK_FREQ / MICRO_PER_SEC)
+    time::read() / (machine().timebase_frequency / MICRO_PER_SEC)
+}
+
+/// Time since boot.
+pub fn get_time_ns() -> usize {
+    (time::read() as u128 * NANO_PER_SEC as u128 / machine().timebase_frequency as u128) as usize
+}
+
+/// Wall clock time at boot, in nanoseconds since the unix epoch.
+static BOOT_EPOCH_NS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn set_realtime_ns(now: usize) {
+    BOOT_EPOCH_NS.store(now.saturating_sub(get_time_ns()), Ordering::Relaxed);
+}
+
+pub fn get_realtime_ns() -> usize {
+    BOOT_EPOCH_NS.load(Ordering::Relaxed) + get_time_ns()
 }
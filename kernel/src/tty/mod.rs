@@ -0,0 +1,446 @@
+//! Terminals. A `Tty` puts a line discipline between a device and the processes which read
+//! and write it: in canonical mode input is edited and echoed as it is typed and handed out
+//! a line at a time, and control characters become signals for the foreground process group
+//! of the session it is the controlling terminal of.
+//!
+//! The console is one, the slave sides of the pseudo-terminals are the others. The bytes of
+//! the console are taken from the UART by a kernel thread, as signals may not be sent from
+//! the interrupt handler: they can end the very process it interrupted.
+
+pub mod pty;
+mod termios;
+
+use alloc::{collections::VecDeque, sync::Weak, vec::Vec};
+use core::ptr;
+
+use self::pty::Pty;
+use self::termios::*;
+use crate::{
+    console,
+    drivers::uart,
+    process::{
+        kthread,
+        signal::{self, SIGHUP, SIGINT, SIGQUIT, SIGTSTP, SIGWINCH},
+        WaitQueue,
+    },
+    sync::UPSafeCell,
+};
+
+/// Bytes a line being edited, and the input not read yet, may take.
+const MAX_INPUT: usize = 4096;
+
+const BS: u8 = 0x08;
+const DEL: u8 = 0x7f;
+
+pub struct Tty {
+    inner: UPSafeCell<TtyInner>,
+    /// woken when there is input to read
+    readers: WaitQueue,
+    /// where output and echo go
+    output: Output,
+}
+
+enum Output {
+    Console,
+    /// queued for the master side to read
+    Pty(Weak<Pty>),
+}
+
+struct TtyInner {
+    /// the session it is the controlling terminal of
+    session: Option<usize>,
+    /// the process group of that session which may read and gets the signals
+    foreground: usize,
+    termios: Termios,
+    winsize: WinSize,
+    /// the line being edited in canonical mode
+    line: Vec<u8>,
+    /// ready to be read
+    input: VecDeque<u8>,
+    /// in canonical mode, how long the lines in `input` are, a read does not go past the end
+    /// of the first one. The end of file is a line of length 0.
+    line_ends: VecDeque<usize>,
+    /// nothing is on the other side any more, reads find the end of file once the input is
+    /// used up
+    hung_up: bool,
+}
+
+static CONSOLE: Tty = Tty::new(Output::Console);
+
+pub fn console() -> &'static Tty {
+    &CONSOLE
+}
+
+pub fn init() {
+    kthread::spawn(input_loop);
+}
+
+fn input_loop() {
+    loop {
+        uart::wait_readable();
+        while let Some(byte) = uart::getchar() {
+            CONSOLE.receive(byte);
+        }
+    }
+}
+
+/// The leader of session `sid` exited, its controlling terminal is free for another one.
+pub fn release(sid: usize) {
+    CONSOLE.release(sid);
+    for pty in pty::all() {
+        pty.tty().release(sid);
+    }
+}
+
+/// Whether a terminal other than `tty` is the controlling one of session `sid`.
+pub fn controlled_elsewhere(tty: &Tty, sid: usize) -> bool {
+    let controls = |other: &Tty| !ptr::eq(other, tty) && other.foreground(sid).is_some();
+    controls(&CONSOLE) || pty::all().iter().any(|pty| controls(pty.tty()))
+}
+
+impl Tty {
+    const fn new(output: Output) -> Self {
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(TtyInner {
+                    session: None,
+                    foreground: 0,
+                    termios: Termios::new(),
+                    winsize: WinSize::new(),
+                    line: Vec::new(),
+                    input: VecDeque::new(),
+                    line_ends: VecDeque::new(),
+                    hung_up: false,
+                })
+            },
+            readers: WaitQueue::new(),
+            output,
+        }
+    }
+
+    /// Run `byte` through the line discipline, as if it was typed.
+    pub fn receive(&self, byte: u8) {
+        let mut echo = Vec::new();
+        let (sig, foreground) = {
+            let mut inner = self.inner.get_mut();
+            let sig = inner.receive(byte, &mut echo);
+            (sig, inner.session.map(|_| inner.foreground))
+        };
+        self.post(&echo, false);
+        self.readers.wake_all();
+        if let (Some(sig), Some(pgid)) = (sig, foreground) {
+            signal::send_to_group(pgid, sig);
+        }
+    }
+
+    /// Block until there is input and move it to `buf`, in canonical mode no more than one
+    /// line. Returns 0 at the end of file, or right away in raw mode with a `VMIN` of 0, or
+    /// once it was hung up and the input is used up, or once the caller is being killed.
+    pub fn read(&self, buf: &mut [u8]) -> usize {
+        let mut read = 0;
+        self.readers
+            .wait_event_killable(|| match self.inner.get_mut().take_input(buf) {
+                Some(len) => {
+                    read = len;
+                    true
+                }
+                None => false,
+            });
+        read
+    }
+
+    /// Send `bytes` to the device, `\n` as `\r\n` if `OPOST` and `ONLCR` are set. Blocks
+    /// while the master side of a pseudo-terminal has too much to read.
+    pub fn write(&self, bytes: &[u8]) {
+        self.post(bytes, true);
+    }
+
+    /// `write`, only echo does not `wait` for room.
+    fn post(&self, bytes: &[u8], wait: bool) {
+        let oflag = self.inner.get().termios.oflag;
+        if oflag & (OPOST | ONLCR) != OPOST | ONLCR {
+            return self.output(bytes, wait);
+        }
+        for chunk in bytes.split_inclusive(|&byte| byte == b'\n') {
+            match chunk.strip_suffix(b"\n") {
+                Some(line) => {
+                    self.output(line, wait);
+                    self.output(b"\r\n", wait);
+                }
+                None => self.output(chunk, wait),
+            }
+        }
+    }
+
+    fn output(&self, bytes: &[u8], wait: bool) {
+        match &self.output {
+            Output::Console => console::write(bytes),
+            Output::Pty(pty) => match (pty.upgrade(), wait) {
+                (Some(pty), true) => pty.output(bytes),
+                (Some(pty), false) => pty.echo(bytes),
+                (None, _) => {}
+            },
+        }
+    }
+
+    /// The other side went away: the foreground process group gets `SIGHUP`, and readers
+    /// the end of file.
+    pub fn hangup(&self) {
+        let foreground = {
+            let mut inner = self.inner.get_mut();
+            inner.hung_up = true;
+            inner.session.map(|_| inner.foreground)
+        };
+        self.readers.wake_all();
+        if let Some(pgid) = foreground {
+            signal::send_to_group(pgid, SIGHUP);
+        }
+    }
+
+    pub fn is_hung_up(&self) -> bool {
+        self.inner.get().hung_up
+    }
+
+    pub fn termios(&self) -> Termios {
+        self.inner.get().termios
+    }
+
+    /// Change the attributes, `flush` drops the input not read yet first.
+    ///
+    /// Leaving canonical mode makes the line being edited readable, entering it makes what
+    /// was not read yet one line.
+    pub fn set_termios(&self, termios: Termios, flush: bool) {
+        {
+            let mut inner = self.inner.get_mut();
+            if flush {
+                inner.flush_input();
+            }
+            let was_canonical = inner.termios.local(ICANON);
+            let inner = &mut *inner;
+            match (was_canonical, termios.local(ICANON)) {
+                (true, false) => {
+                    inner.input.extend(inner.line.drain(..));
+                    inner.line_ends.clear();
+                }
+                (false, true) if !inner.input.is_empty() => {
+                    inner.line_ends.clear();
+                    inner.line_ends.push_back(inner.input.len());
+                }
+                _ => {}
+            }
+            inner.termios = termios;
+        }
+        self.readers.wake_all();
+    }
+
+    pub fn winsize(&self) -> WinSize {
+        self.inner.get().winsize
+    }
+
+    /// A change of the size is told to the foreground process group with `SIGWINCH`.
+    pub fn set_winsize(&self, winsize: WinSize) {
+        let foreground = {
+            let mut inner = self.inner.get_mut();
+            let changed = inner.winsize != winsize;
+            inner.winsize = winsize;
+            inner.session.filter(|_| changed).map(|_| inner.foreground)
+        };
+        if let Some(pgid) = foreground {
+            signal::send_to_group(pgid, SIGWINCH);
+        }
+    }
+
+    /// Whether process group `pgid` of session `sid` is not allowed to read, as it is in the
+    /// background of the session the terminal belongs to.
+    pub fn is_background(&self, pgid: usize, sid: usize) -> bool {
+        let inner = self.inner.get();
+        inner.session == Some(sid) && inner.foreground != pgid
+    }
+
+    /// Make the terminal the controlling one of session `sid`, with `pgid` in the foreground.
+    /// Fails if it belongs to another session.
+    pub fn set_controlling(&self, sid: usize, pgid: usize) -> bool {
+        let mut inner = self.inner.get_mut();
+        match inner.session {
+            Some(session) if session != sid => false,
+            _ => {
+                inner.session = Some(sid);
+                inner.foreground = pgid;
+                true
+            }
+        }
+    }
+
+    /// The foreground process group, if the terminal is the controlling one of session `sid`.
+    pub fn foreground(&self, sid: usize) -> Option<usize> {
+        let inner = self.inner.get();
+        inner
+            .session
+            .filter(|&session| session == sid)
+            .map(|_| inner.foreground)
+    }
+
+    /// Put `pgid` in the foreground, if the terminal is the controlling one of session `sid`.
+    pub fn set_foreground(&self, sid: usize, pgid: usize) -> bool {
+        let mut inner = self.inner.get_mut();
+        match inner.session {
+            Some(session) if session == sid => {
+                inner.foreground = pgid;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn release(&self, sid: usize) {
+        let mut inner = self.inner.get_mut();
+        if inner.session == Some(sid) {
+            inner.session = None;
+        }
+    }
+}
+
+impl TtyInner {
+    /// The line discipline, what is to be echoed goes to `echo`. Returns the signal `byte`
+    /// stands for, if any.
+    fn receive(&mut self, byte: u8, echo: &mut Vec<u8>) -> Option<usize> {
+        let termios = self.termios;
+        let byte = match byte {
+            b'\r' if termios.iflag & IGNCR != 0 => return None,
+            b'\r' if termios.iflag & ICRNL != 0 => b'\n',
+            b'\n' if termios.iflag & INLCR != 0 => b'\r',
+            byte => byte,
+        };
+        if termios.local(ISIG) {
+            let sig = [(VINTR, SIGINT), (VQUIT, SIGQUIT), (VSUSP, SIGTSTP)]
+                .into_iter()
+                .find(|&(index, _)| termios.is_char(byte, index))
+                .map(|(_, sig)| sig);
+            if sig.is_some() {
+                if !termios.local(NOFLSH) {
+                    self.flush_input();
+                }
+                self.echo(byte, echo);
+                return sig;
+            }
+        }
+        if !termios.local(ICANON) {
+            if self.input.len() < MAX_INPUT {
+                self.input.push_back(byte);
+                self.echo(byte, echo);
+            }
+            return None;
+        }
+        if termios.is_char(byte, VERASE) {
+            self.erase(1, termios.local(ECHO | ECHOE), byte, echo);
+        } else if termios.is_char(byte, VWERASE) {
+            let word = |line: &[u8]| {
+                let spaces = line
+                    .iter()
+                    .rev()
+                    .take_while(|b| b.is_ascii_whitespace())
+                    .count();
+                let rest = line.iter().rev().skip(spaces);
+                spaces + rest.take_while(|b| !b.is_ascii_whitespace()).count()
+            };
+            self.erase(word(&self.line), termios.local(ECHO | ECHOE), byte, echo);
+        } else if termios.is_char(byte, VKILL) {
+            self.erase(self.line.len(), termios.local(ECHO | ECHOKE), byte, echo);
+            if !termios.local(ECHOKE) && termios.local(ECHO | ECHOK) {
+                echo.push(b'\n');
+            }
+        } else if termios.is_char(byte, VEOF) {
+            self.end_line();
+        } else if byte == b'\n' || termios.is_char(byte, VEOL) {
+            self.line.push(byte);
+            if byte == b'\n' && termios.local(ECHONL) && !termios.local(ECHO) {
+                echo.push(b'\n');
+            }
+            self.echo(byte, echo);
+            self.end_line();
+        } else if self.line.len() < MAX_INPUT - 1 {
+            // room is left for the newline
+            self.line.push(byte);
+            self.echo(byte, echo);
+        }
+        None
+    }
+
+    /// Echo `byte` if `ECHO` is set, a control character as `^X` with `ECHOCTL`.
+    fn echo(&self, byte: u8, echo: &mut Vec<u8>) {
+        if !self.termios.local(ECHO) {
+            return;
+        }
+        if self.shows_as_caret(byte) {
+            echo.extend_from_slice(&[b'^', byte ^ 0x40]);
+        } else {
+            echo.push(byte);
+        }
+    }
+
+    fn shows_as_caret(&self, byte: u8) -> bool {
+        let control = (byte < b' ' && byte != b'\t' && byte != b'\n') || byte == DEL;
+        control && self.termios.local(ECHOCTL)
+    }
+
+    /// Take `count` bytes off the line being edited, and off the screen if `visual`.
+    /// Otherwise `erase_char`, the character which asked for that, is echoed.
+    fn erase(&mut self, count: usize, visual: bool, erase_char: u8, echo: &mut Vec<u8>) {
+        let count = count.min(self.line.len());
+        if count == 0 {
+            return;
+        }
+        if !visual {
+            self.line.truncate(self.line.len() - count);
+            return self.echo(erase_char, echo);
+        }
+        for _ in 0..count {
+            let byte = self.line.pop().unwrap();
+            let width = if self.shows_as_caret(byte) { 2 } else { 1 };
+            for _ in 0..width {
+                echo.extend_from_slice(&[BS, b' ', BS]);
+            }
+        }
+    }
+
+    /// Hand the line being edited to the readers.
+    fn end_line(&mut self) {
+        self.line_ends.push_back(self.line.len());
+        self.input.extend(self.line.drain(..));
+    }
+
+    fn flush_input(&mut self) {
+        self.line.clear();
+        self.input.clear();
+        self.line_ends.clear();
+    }
+
+    /// Move what is ready to `buf`, `None` if the reader has to wait.
+    fn take_input(&mut self, buf: &mut [u8]) -> Option<usize> {
+        if buf.is_empty() {
+            return Some(0);
+        }
+        if self.hung_up && self.input.is_empty() {
+            return Some(0);
+        }
+        let len = if self.termios.local(ICANON) {
+            let line = self.line_ends.front_mut()?;
+            let len = (*line).min(buf.len());
+            *line -= len;
+            if *line == 0 {
+                self.line_ends.pop_front();
+            }
+            len
+        } else {
+            let min = (self.termios.cc[VMIN] as usize).min(buf.len());
+            if self.input.len() < min {
+                return None;
+            }
+            self.input.len().min(buf.len())
+        };
+        for (dst, src) in buf.iter_mut().zip(self.input.drain(..len)) {
+            *dst = src;
+        }
+        Some(len)
+    }
+}
@@ -0,0 +1,212 @@
+//! Pseudo-terminals. The slave side is a `Tty` like the console, only what it outputs is
+//! queued for the master side to read, and what is written to the master side is taken as
+//! typed at it. Once `MAX_INPUT` bytes are queued writers of the slave side wait for the
+//! master side to read, echo which does not fit is dropped.
+//!
+//! Opening `/dev/ptmx` makes a new pair, numbered with the lowest number no other pair has.
+//! Its slave side is `/dev/pts/N` and may be opened once `TIOCSPTLCK` unlocked it.
+
+use alloc::{
+    collections::VecDeque,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use super::{Output, Tty, MAX_INPUT};
+use crate::{process::WaitQueue, sync::UPSafeCell};
+
+pub struct Pty {
+    index: usize,
+    tty: Tty,
+    inner: UPSafeCell<PtyInner>,
+    /// woken when there is output to read, or when the last slave was closed
+    master_readers: WaitQueue,
+    /// woken when the master side read some output, or was closed
+    slave_writers: WaitQueue,
+}
+
+struct PtyInner {
+    /// output of the slave side, not read yet
+    output: VecDeque<u8>,
+    locked: bool,
+    slaves: usize,
+    /// every slave which was opened was closed, reads of the master side fail until one is
+    /// opened again
+    slaves_closed: bool,
+}
+
+/// Numbered by their index, a pair which is gone leaves its number free.
+static PTYS: UPSafeCell<Vec<Weak<Pty>>> = unsafe { UPSafeCell::new(Vec::new()) };
+
+/// Make a new pair, locked, and open its master side.
+pub fn open_master() -> Master {
+    let mut ptys = PTYS.get_mut();
+    let index = ptys
+        .iter()
+        .position(|pty| pty.strong_count() == 0)
+        .unwrap_or(ptys.len());
+    let pty = Arc::new_cyclic(|pty| Pty {
+        index,
+        tty: Tty::new(Output::Pty(pty.clone())),
+        inner: unsafe {
+            UPSafeCell::new(PtyInner {
+                output: VecDeque::new(),
+                locked: true,
+                slaves: 0,
+                slaves_closed: false,
+            })
+        },
+        master_readers: WaitQueue::new(),
+        slave_writers: WaitQueue::new(),
+    });
+    match ptys.get_mut(index) {
+        Some(slot) => *slot = Arc::downgrade(&pty),
+        None => ptys.push(Arc::downgrade(&pty)),
+    }
+    Master(pty)
+}
+
+/// Open the slave side of pair `index`, fails if there is none, it is still locked or its
+/// master side was closed.
+pub fn open_slave(index: usize) -> Option<Slave> {
+    let pty = PTYS.get().get(index)?.upgrade()?;
+    {
+        let mut inner = pty.inner.get_mut();
+        if inner.locked || pty.tty.is_hung_up() {
+            return None;
+        }
+        inner.slaves += 1;
+        inner.slaves_closed = false;
+    }
+    Some(Slave(pty))
+}
+
+pub fn exists(index: usize) -> bool {
+    PTYS.get()
+        .get(index)
+        .map_or(false, |pty| pty.strong_count() > 0)
+}
+
+/// Every pair which is still open.
+pub(super) fn all() -> Vec<Arc<Pty>> {
+    let ptys = PTYS.get();
+    ptys.iter().filter_map(|pty| pty.upgrade()).collect()
+}
+
+impl Pty {
+    pub(super) fn tty(&self) -> &Tty {
+        &self.tty
+    }
+
+    /// Queue what the slave side outputs, blocking while the queue is full. It is dropped
+    /// once the master side was closed, or what is left once the caller is being killed.
+    pub(super) fn output(&self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let mut queued = 0;
+            let killed = !self.slave_writers.wait_event_killable(|| {
+                if self.tty.is_hung_up() {
+                    queued = bytes.len();
+                    return true;
+                }
+                let mut inner = self.inner.get_mut();
+                queued = MAX_INPUT
+                    .saturating_sub(inner.output.len())
+                    .min(bytes.len());
+                inner.output.extend(&bytes[..queued]);
+                queued > 0
+            });
+            if killed {
+                return;
+            }
+            bytes = &bytes[queued..];
+            self.master_readers.wake_all();
+        }
+    }
+
+    /// Queue echo of the slave side, what does not fit is dropped. The writer of the master
+    /// side may be the one to read it, it must not block.
+    pub(super) fn echo(&self, bytes: &[u8]) {
+        {
+            let mut inner = self.inner.get_mut();
+            let room = MAX_INPUT.saturating_sub(inner.output.len());
+            inner.output.extend(bytes.iter().take(room));
+        }
+        self.master_readers.wake_all();
+    }
+}
+
+/// The master side as a file holds it, there is one for each pair. The slave side is hung up
+/// once it is closed.
+pub struct Master(Arc<Pty>);
+
+impl Master {
+    pub fn index(&self) -> usize {
+        self.0.index
+    }
+
+    pub fn tty(&self) -> &Tty {
+        &self.0.tty
+    }
+
+    pub fn set_locked(&self, locked: bool) {
+        self.0.inner.get_mut().locked = locked;
+    }
+
+    /// Block until the slave side output something and move it to `buf`. Returns `None` once
+    /// every slave was closed and there is nothing left, or once the caller is being killed.
+    pub fn read(&self, buf: &mut [u8]) -> Option<usize> {
+        let mut read = None;
+        self.0.master_readers.wait_event_killable(|| {
+            let mut inner = self.0.inner.get_mut();
+            if !inner.output.is_empty() {
+                let len = inner.output.len().min(buf.len());
+                for (dst, src) in buf.iter_mut().zip(inner.output.drain(..len)) {
+                    *dst = src;
+                }
+                read = Some(len);
+                true
+            } else {
+                inner.slaves_closed
+            }
+        });
+        if read.is_some() {
+            self.0.slave_writers.wake_all();
+        }
+        read
+    }
+
+    /// Have the slave side take `bytes` as typed.
+    pub fn write(&self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0.tty.receive(byte);
+        }
+    }
+}
+
+impl Drop for Master {
+    fn drop(&mut self) {
+        self.0.tty.hangup();
+        self.0.slave_writers.wake_all();
+    }
+}
+
+/// The slave side as a file holds it.
+pub struct Slave(Arc<Pty>);
+
+impl Slave {
+    pub fn tty(&self) -> &Tty {
+        &self.0.tty
+    }
+}
+
+impl Drop for Slave {
+    fn drop(&mut self) {
+        let mut inner = self.0.inner.get_mut();
+        inner.slaves -= 1;
+        if inner.slaves == 0 {
+            inner.slaves_closed = true;
+            drop(inner);
+            self.0.master_readers.wake_all();
+        }
+    }
+}
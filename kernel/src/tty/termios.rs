@@ -0,0 +1,96 @@
+//! Terminal attributes as `TCGETS` and `TCSETS` take them, laid out as `struct termios` and
+//! `struct winsize` are on Linux.
+
+pub const NCCS: usize = 19;
+
+// input modes
+pub const INLCR: u32 = 0o100;
+pub const IGNCR: u32 = 0o200;
+pub const ICRNL: u32 = 0o400;
+
+// output modes
+pub const OPOST: u32 = 0o1;
+pub const ONLCR: u32 = 0o4;
+
+// control modes, only reported
+const CS8: u32 = 0o60;
+const CREAD: u32 = 0o200;
+
+// local modes
+pub const ISIG: u32 = 0o1;
+pub const ICANON: u32 = 0o2;
+pub const ECHO: u32 = 0o10;
+pub const ECHOE: u32 = 0o20;
+pub const ECHOK: u32 = 0o40;
+pub const ECHONL: u32 = 0o100;
+pub const NOFLSH: u32 = 0o200;
+pub const ECHOCTL: u32 = 0o1000;
+pub const ECHOKE: u32 = 0o4000;
+
+// indices of the control characters, a character of 0 is disabled
+pub const VINTR: usize = 0;
+pub const VQUIT: usize = 1;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const VMIN: usize = 6;
+pub const VSUSP: usize = 10;
+pub const VEOL: usize = 11;
+pub const VWERASE: usize = 14;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    pub cflag: u32,
+    pub lflag: u32,
+    /// the line discipline, there is only the one
+    pub line: u8,
+    pub cc: [u8; NCCS],
+}
+
+impl Termios {
+    /// Canonical mode with echo and signals, `\r` read as `\n` and `\n` written as `\r\n`.
+    pub const fn new() -> Self {
+        Self {
+            iflag: ICRNL,
+            oflag: OPOST | ONLCR,
+            cflag: CS8 | CREAD,
+            lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL | ECHOKE,
+            line: 0,
+            // ^C ^\ DEL ^U ^D, VTIME 0, VMIN 1, ^Z and ^W, the others are not used
+            cc: [
+                0x03, 0x1c, 0x7f, 0x15, 0x04, 0, 1, 0, 0, 0, 0x1a, 0, 0, 0, 0x17, 0, 0, 0, 0,
+            ],
+        }
+    }
+    /// Whether every flag of `flags` is set in the local modes.
+    pub fn local(&self, flags: u32) -> bool {
+        self.lflag & flags == flags
+    }
+    /// Whether `byte` is the control character at `index`.
+    pub fn is_char(&self, byte: u8, index: usize) -> bool {
+        self.cc[index] != 0 && self.cc[index] == byte
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq)]
+pub struct WinSize {
+    pub row: u16,
+    pub col: u16,
+    pub xpixel: u16,
+    pub ypixel: u16,
+}
+
+impl WinSize {
+    pub const fn new() -> Self {
+        Self {
+            row: 24,
+            col: 80,
+            xpixel: 0,
+            ypixel: 0,
+        }
+    }
+}
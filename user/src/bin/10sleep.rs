@@ -1,7 +1,7 @@
 #![no_std]
 #![no_main]
 
-use user_lib::{get_time, syscall::sys_yield};
+use user_lib::{get_time, sleep};
 
 #[macro_use]
 extern crate user_lib;
@@ -11,9 +11,8 @@ fn main() -> i32 {
     let current_timer = get_time();
     let wait_for = current_timer + 3000;
     println!("Before sleep.");
-    while get_time() < wait_for {
-        sys_yield();
-    }
+    sleep(3000);
+    assert!(get_time() >= wait_for, "woke up too early");
     println!("Test sleep OK!");
     0
 }
@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{shmat, shmctl, shmdt, shmget, yield_, IPC_CREAT, IPC_RMID};
+
+const SHM_KEY: usize = 0x5348;
+const SHM_SIZE: usize = 4096 * 2;
+const DATA_LEN: usize = 1024;
+
+#[no_mangle]
+fn main() -> i32 {
+    let id = shmget(SHM_KEY, SHM_SIZE, IPC_CREAT);
+    assert!(id > 0, "shmget failed");
+    let addr = shmat(id as usize, 0);
+    assert!(addr > 0, "shmat failed");
+    println!("producer attached shm {} at {:#x}", id, addr);
+    let base = addr as *mut usize;
+    unsafe {
+        for i in 0..DATA_LEN {
+            base.add(i + 1).write_volatile(i * i);
+        }
+        // publish, then wait for the consumer to acknowledge
+        base.write_volatile(1);
+        while base.read_volatile() != 2 {
+            yield_();
+        }
+    }
+    assert_eq!(shmdt(addr as usize), 0);
+    assert_eq!(shmctl(id as usize, IPC_RMID), 0);
+    println!("Test shm producer OK!");
+    0
+}
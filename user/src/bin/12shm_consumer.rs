@@ -0,0 +1,38 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{shmat, shmdt, shmget, yield_};
+
+const SHM_KEY: usize = 0x5348;
+const DATA_LEN: usize = 1024;
+
+#[no_mangle]
+fn main() -> i32 {
+    let id = loop {
+        match shmget(SHM_KEY, 0, 0) {
+            -1 => {
+                yield_();
+            }
+            id => break id,
+        }
+    };
+    let addr = shmat(id as usize, 0);
+    assert!(addr > 0, "shmat failed");
+    println!("consumer attached shm {} at {:#x}", id, addr);
+    let base = addr as *mut usize;
+    unsafe {
+        while base.read_volatile() != 1 {
+            yield_();
+        }
+        for i in 0..DATA_LEN {
+            assert_eq!(base.add(i + 1).read_volatile(), i * i);
+        }
+        base.write_volatile(2);
+    }
+    assert_eq!(shmdt(addr as usize), 0);
+    println!("Test shm consumer OK!");
+    0
+}
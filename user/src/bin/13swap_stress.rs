@@ -0,0 +1,54 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::addr_of_mut;
+use user_lib::{getrusage, syscall::Rusage, syscall::Sysinfo, sysinfo, RUSAGE_SELF};
+
+const PAGE_SIZE: usize = 4096;
+/// Touched past the free memory, these at least have to go to swap.
+const EXTRA_PAGES: usize = 2048;
+/// Room for up to 256 MiB of free memory, pages of it are only backed once touched.
+const MAX_PAGES: usize = 65536 + EXTRA_PAGES;
+
+static mut BUF: [u8; MAX_PAGES * PAGE_SIZE] = [0; MAX_PAGES * PAGE_SIZE];
+
+fn pattern(page: usize) -> usize {
+    page.wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ 0x5a5a
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut info = Sysinfo::default();
+    assert_eq!(sysinfo(&mut info), 0);
+    assert!(info.totalswap > 0, "no swap device");
+    // more than the memory free right now, the RAM QEMU was given is found at boot
+    let pages = info.freeram / PAGE_SIZE + EXTRA_PAGES;
+    assert!(pages <= MAX_PAGES, "too much free memory to run out of");
+    println!("Touching {} KiB of memory...", pages * PAGE_SIZE / 1024);
+    let base = unsafe { addr_of_mut!(BUF) as *mut u8 };
+    for page in 0..pages {
+        unsafe {
+            let p = base.add(page * PAGE_SIZE) as *mut usize;
+            p.write_volatile(pattern(page));
+            p.add(PAGE_SIZE / 8 - 1).write_volatile(!pattern(page));
+        }
+        if page % 4096 == 0 {
+            println!("written {} pages", page);
+        }
+    }
+    for page in 0..pages {
+        unsafe {
+            let p = base.add(page * PAGE_SIZE) as *mut usize;
+            assert_eq!(p.read_volatile(), pattern(page));
+            assert_eq!(p.add(PAGE_SIZE / 8 - 1).read_volatile(), !pattern(page));
+        }
+    }
+    let mut usage = Rusage::default();
+    assert_eq!(getrusage(RUSAGE_SELF, &mut usage), 0);
+    assert!(usage.majflt > 0, "nothing was read back from swap");
+    println!("Test swap OK!");
+    0
+}
@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    clock_gettime, sync::EINVAL, syscall::TimeSpec, CLOCK_MONOTONIC, CLOCK_PROCESS_CPUTIME_ID,
+    CLOCK_REALTIME,
+};
+
+/// 2020-01-01T00:00:00Z
+const EPOCH_2020: usize = 1_577_836_800;
+
+fn now(clock_id: usize) -> TimeSpec {
+    let mut ts = TimeSpec::default();
+    assert_eq!(clock_gettime(clock_id, &mut ts), 0);
+    ts
+}
+
+fn nanos(ts: TimeSpec) -> usize {
+    ts.sec * 1_000_000_000 + ts.nsec
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let real = now(CLOCK_REALTIME);
+    println!("realtime: {}.{:09}", real.sec, real.nsec);
+    assert!(real.sec > EPOCH_2020, "wall clock is not set");
+
+    let mono = nanos(now(CLOCK_MONOTONIC));
+    let cpu = nanos(now(CLOCK_PROCESS_CPUTIME_ID));
+    let mut x: usize = 1;
+    for i in 0..1_000_000 {
+        x = x.wrapping_mul(i) ^ i;
+    }
+    core::hint::black_box(x);
+    assert!(nanos(now(CLOCK_MONOTONIC)) > mono);
+    assert!(nanos(now(CLOCK_PROCESS_CPUTIME_ID)) > cpu);
+
+    let mut ts = TimeSpec::default();
+    assert_eq!(clock_gettime(100, &mut ts), -EINVAL);
+    println!("Test clock OK!");
+    0
+}
@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, getpid, sleep, sync::ECHILD, wait, waitpid, wexitstatus, wifexited};
+
+/// Start a child which exits with `code` after `ms`, returns its pid.
+fn spawn(code: i32, ms: usize) -> usize {
+    let pid = fork();
+    assert!(pid >= 0, "fork failed");
+    if pid == 0 {
+        sleep(ms);
+        exit(code);
+        unreachable!();
+    }
+    pid as usize
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut status = -1;
+    // nothing to wait for before the first fork
+    assert_eq!(wait(&mut status), -ECHILD);
+
+    // blocks until it exited if it has not yet
+    let child = spawn(3, 10);
+    assert_eq!(waitpid(child, &mut status), child as isize);
+    assert!(wifexited(status));
+    assert_eq!(wexitstatus(status), 3);
+    // reaped, it is no child any more
+    assert_eq!(waitpid(child, &mut status), -ECHILD);
+    // only children can be waited for
+    assert_eq!(waitpid(getpid() as usize, &mut status), -ECHILD);
+    assert_eq!(waitpid(4096, &mut status), -ECHILD);
+
+    // any child, in the order they exit
+    let slow = spawn(5, 30);
+    let fast = spawn(4, 0);
+    assert_eq!(wait(&mut status), fast as isize);
+    assert_eq!(wexitstatus(status), 4);
+    assert_eq!(wait(&mut status), slow as isize);
+    assert_eq!(wexitstatus(status), 5);
+    assert_eq!(wait(&mut status), -ECHILD);
+    println!("Test waitpid OK!");
+    0
+}
@@ -0,0 +1,33 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{getpriority, set_priority, setpriority, yield_, PRIO_PROCESS};
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(set_priority(1), -1);
+    assert_eq!(set_priority(-5), -1);
+    assert_eq!(set_priority(32), 32);
+    yield_();
+    assert_eq!(set_priority(16), 16);
+    println!("Test set_priority OK!");
+
+    assert_eq!(getpriority(PRIO_PROCESS, 0), Some(0));
+    assert_eq!(setpriority(PRIO_PROCESS, 0, 10), 0);
+    yield_();
+    assert_eq!(getpriority(PRIO_PROCESS, 0), Some(10));
+    // out of range values are clamped
+    assert_eq!(setpriority(PRIO_PROCESS, 0, 100), 0);
+    assert_eq!(getpriority(PRIO_PROCESS, 0), Some(19));
+    assert_eq!(setpriority(PRIO_PROCESS, 0, -100), 0);
+    assert_eq!(getpriority(PRIO_PROCESS, 0), Some(-20));
+    assert_eq!(setpriority(PRIO_PROCESS, 0, 0), 0);
+    assert_eq!(setpriority(PRIO_PROCESS, 4096, 0), -1);
+    assert_eq!(setpriority(1, 0, 0), -1);
+    assert_eq!(getpriority(PRIO_PROCESS, 4096), None);
+    println!("Test priority OK!");
+    0
+}
@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    clock_gettime, setpriority, shmat, shmctl, shmdt, shmget, sleep, syscall::TimeSpec,
+    CLOCK_MONOTONIC, CLOCK_PROCESS_CPUTIME_ID, IPC_CREAT, IPC_RMID, PRIO_PROCESS,
+};
+
+/// Shared with 18nice_slow: the start of the window in monotonic nanoseconds, then the CPU
+/// time both of them got within the window.
+const SHM_KEY: usize = 0x4e49;
+const START: usize = 0;
+const FAST_CPU: usize = 1;
+const SLOW_CPU: usize = 2;
+const WINDOW_NS: usize = 2_000_000_000;
+
+fn now(clock_id: usize) -> usize {
+    let mut ts = TimeSpec::default();
+    assert_eq!(clock_gettime(clock_id, &mut ts), 0);
+    ts.sec * 1_000_000_000 + ts.nsec
+}
+
+/// CPU time spent spinning through the window.
+fn spin(start: usize) -> usize {
+    while now(CLOCK_MONOTONIC) < start {
+        sleep(1);
+    }
+    let cpu = now(CLOCK_PROCESS_CPUTIME_ID);
+    while now(CLOCK_MONOTONIC) < start + WINDOW_NS {}
+    now(CLOCK_PROCESS_CPUTIME_ID) - cpu
+}
+
+/// Nice 0 against nice 5 weighs about 3:1 under the cfs policy, the others ignore nice values.
+#[no_mangle]
+fn main() -> i32 {
+    let id = shmget(SHM_KEY, 4096, IPC_CREAT);
+    assert!(id > 0, "shmget failed");
+    let addr = shmat(id as usize, 0);
+    assert!(addr > 0, "shmat failed");
+    let base = addr as *mut usize;
+    assert_eq!(setpriority(PRIO_PROCESS, 0, 0), 0);
+    let start = now(CLOCK_MONOTONIC) + 100_000_000;
+    unsafe {
+        base.add(START).write_volatile(start);
+        base.add(FAST_CPU).write_volatile(spin(start));
+        while base.add(SLOW_CPU).read_volatile() == 0 {
+            sleep(10);
+        }
+        let (fast, slow) = (
+            base.add(FAST_CPU).read_volatile(),
+            base.add(SLOW_CPU).read_volatile(),
+        );
+        println!("nice 0 ran {}us, nice 5 ran {}us", fast / 1000, slow / 1000);
+        if cfg!(feature = "sched-cfs") {
+            assert!(
+                fast > slow * 2,
+                "nice 0 should get about 3 times the CPU of nice 5"
+            );
+        }
+    }
+    assert_eq!(shmdt(addr as usize), 0);
+    assert_eq!(shmctl(id as usize, IPC_RMID), 0);
+    println!("Test nice fast OK!");
+    0
+}
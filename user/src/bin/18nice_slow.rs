@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    clock_gettime, setpriority, shmat, shmdt, shmget, sleep, syscall::TimeSpec, CLOCK_MONOTONIC,
+    CLOCK_PROCESS_CPUTIME_ID, PRIO_PROCESS,
+};
+
+/// See 17nice_fast.
+const SHM_KEY: usize = 0x4e49;
+const START: usize = 0;
+const SLOW_CPU: usize = 2;
+const WINDOW_NS: usize = 2_000_000_000;
+
+fn now(clock_id: usize) -> usize {
+    let mut ts = TimeSpec::default();
+    assert_eq!(clock_gettime(clock_id, &mut ts), 0);
+    ts.sec * 1_000_000_000 + ts.nsec
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(setpriority(PRIO_PROCESS, 0, 5), 0);
+    let id = loop {
+        match shmget(SHM_KEY, 0, 0) {
+            -1 => {
+                sleep(1);
+            }
+            id => break id,
+        }
+    };
+    let addr = shmat(id as usize, 0);
+    assert!(addr > 0, "shmat failed");
+    let base = addr as *mut usize;
+    unsafe {
+        let start = loop {
+            match base.add(START).read_volatile() {
+                0 => {
+                    sleep(1);
+                }
+                start => break start,
+            }
+        };
+        while now(CLOCK_MONOTONIC) < start {
+            sleep(1);
+        }
+        let cpu = now(CLOCK_PROCESS_CPUTIME_ID);
+        while now(CLOCK_MONOTONIC) < start + WINDOW_NS {}
+        base.add(SLOW_CPU)
+            .write_volatile(now(CLOCK_PROCESS_CPUTIME_ID) - cpu);
+    }
+    assert_eq!(shmdt(addr as usize), 0);
+    println!("Test nice slow OK!");
+    0
+}
@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exec, fork, getrusage, signal::SIGSEGV, sleep, syscall::Rusage, syscall::Tms, times, waitpid,
+    wifsignaled, wtermsig, RUSAGE_CHILDREN, RUSAGE_SELF,
+};
+
+fn usage(who: isize) -> Rusage {
+    let mut usage = Rusage::default();
+    assert_eq!(getrusage(who, &mut usage), 0);
+    usage
+}
+
+fn us(tv: user_lib::syscall::TimeVal) -> usize {
+    tv.sec * 1_000_000 + tv.usec
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let before = usage(RUSAGE_SELF);
+    let mut x: usize = 1;
+    for i in 0..1_000_000 {
+        x = x.wrapping_mul(i) ^ i;
+    }
+    core::hint::black_box(x);
+    sleep(10);
+    let after = usage(RUSAGE_SELF);
+    println!(
+        "user {}us, system {}us, maxrss {}KB, {} voluntary / {} involuntary switches, {} faults",
+        us(after.utime),
+        us(after.stime),
+        after.maxrss,
+        after.nvcsw,
+        after.nivcsw,
+        after.minflt + after.majflt
+    );
+    assert!(us(after.utime) > us(before.utime));
+    assert!(after.nvcsw > before.nvcsw, "sleeping is a voluntary switch");
+    assert!(after.maxrss > 0);
+
+    let mut tms = Tms::default();
+    assert!(times(&mut tms) > 0);
+    assert!(tms.utime + tms.stime <= (us(after.utime) + us(after.stime)) / 10_000 + 1);
+
+    // reaping a child makes its usage count for the children, store_fault is killed by SIGSEGV
+    let child = fork();
+    if child == 0 {
+        exec("store_fault\0");
+        println!("exec store_fault failed");
+        return -4;
+    }
+    assert!(child > 0, "fork failed");
+    let mut status = 0;
+    assert_eq!(waitpid(child as usize, &mut status), child);
+    assert!(wifsignaled(status));
+    assert_eq!(wtermsig(status), SIGSEGV);
+    let children = usage(RUSAGE_CHILDREN);
+    assert!(us(children.utime) + us(children.stime) > 0);
+    assert_eq!(getrusage(1, &mut Rusage::default()), -1);
+    println!("Test rusage OK!");
+    0
+}
@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{getpid, gettid, thread, yield_};
+
+const THREADS: usize = 4;
+const ROUNDS: usize = 100;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+static PID: AtomicUsize = AtomicUsize::new(0);
+
+fn worker(id: usize) -> i32 {
+    // same process, thread of its own
+    assert_eq!(getpid() as usize, PID.load(Ordering::Relaxed));
+    assert_ne!(gettid(), getpid());
+    for _ in 0..ROUNDS {
+        COUNTER.fetch_add(1, Ordering::Relaxed);
+        yield_();
+    }
+    id as i32 + 10
+}
+
+fn early_exit(_: usize) -> i32 {
+    thread::exit(7)
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    PID.store(getpid() as usize, Ordering::Relaxed);
+    assert_eq!(gettid(), getpid());
+    let handles: [_; THREADS] = core::array::from_fn(|id| thread::spawn(worker, id).unwrap());
+    // a thread may exit on its own while the others run on
+    let early = thread::spawn(early_exit, 0).unwrap();
+    assert_eq!(early.join(), 7);
+    for (id, handle) in handles.into_iter().enumerate() {
+        assert_eq!(handle.join(), id as i32 + 10);
+    }
+    assert_eq!(COUNTER.load(Ordering::Relaxed), THREADS * ROUNDS);
+    // slots of exited threads are reused
+    let again = thread::spawn(worker, 0).unwrap();
+    assert_eq!(again.join(), 10);
+    println!("Test threads OK!");
+    0
+}
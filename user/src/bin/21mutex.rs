@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::AtomicU32;
+use user_lib::{
+    sync::{futex_wait, futex_wake, Mutex, EAGAIN, EINVAL, ETIMEDOUT},
+    syscall::TimeSpec,
+    thread, yield_,
+};
+
+const THREADS: usize = 8;
+const ROUNDS: usize = 1000;
+
+/// not atomic, only the lock keeps the increments from getting lost
+static COUNTER: Mutex<usize> = Mutex::new(0);
+
+fn worker(_: usize) -> i32 {
+    for i in 0..ROUNDS {
+        let mut counter = COUNTER.lock();
+        let seen = *counter;
+        // invite the others in while the lock is held
+        if i % 16 == 0 {
+            yield_();
+        }
+        *counter = seen + 1;
+    }
+    0
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let word = AtomicU32::new(1);
+    assert_eq!(futex_wait(&word, 0, None), -EAGAIN);
+    assert_eq!(futex_wake(&word, 1), 0);
+    let short = TimeSpec {
+        sec: 0,
+        nsec: 1_000_000,
+    };
+    assert_eq!(futex_wait(&word, 1, Some(&short)), -ETIMEDOUT);
+    let huge = TimeSpec {
+        sec: usize::MAX,
+        nsec: 0,
+    };
+    assert_eq!(futex_wait(&word, 1, Some(&huge)), -EINVAL);
+
+    let handles: [_; THREADS] = core::array::from_fn(|id| thread::spawn(worker, id).unwrap());
+    for handle in handles {
+        assert_eq!(handle.join(), 0);
+    }
+    assert_eq!(*COUNTER.lock(), THREADS * ROUNDS);
+    assert!(COUNTER.try_lock().is_some());
+    println!("Test mutex OK!");
+    0
+}
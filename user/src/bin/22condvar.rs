@@ -0,0 +1,86 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    get_time,
+    sync::{Condvar, Mutex},
+    thread,
+};
+
+const ITEMS: usize = 500;
+const CAPACITY: usize = 4;
+const CONSUMERS: usize = 3;
+
+/// a bounded ring between one producer and a few consumers
+struct Queue {
+    buf: [usize; CAPACITY],
+    head: usize,
+    len: usize,
+    /// consumers stop once this reaches `ITEMS`
+    taken: usize,
+}
+
+static QUEUE: Mutex<Queue> = Mutex::new(Queue {
+    buf: [0; CAPACITY],
+    head: 0,
+    len: 0,
+    taken: 0,
+});
+static NOT_EMPTY: Condvar = Condvar::new();
+static NOT_FULL: Condvar = Condvar::new();
+
+fn producer(_: usize) -> i32 {
+    for item in 1..=ITEMS {
+        let mut queue = QUEUE.lock();
+        while queue.len == CAPACITY {
+            queue = NOT_FULL.wait(queue);
+        }
+        let tail = (queue.head + queue.len) % CAPACITY;
+        queue.buf[tail] = item;
+        queue.len += 1;
+        NOT_EMPTY.notify_one();
+    }
+    0
+}
+
+/// exits with the sum of the items it took, mod 1 << 30 to stay positive
+fn consumer(_: usize) -> i32 {
+    let mut sum = 0;
+    loop {
+        let mut queue = QUEUE.lock();
+        while queue.len == 0 && queue.taken < ITEMS {
+            queue = NOT_EMPTY.wait(queue);
+        }
+        if queue.taken == ITEMS {
+            // wake the others to let them see it too
+            NOT_EMPTY.notify_all();
+            return (sum % (1 << 30)) as i32;
+        }
+        sum += queue.buf[queue.head];
+        queue.head = (queue.head + 1) % CAPACITY;
+        queue.len -= 1;
+        queue.taken += 1;
+        NOT_FULL.notify_one();
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let consumers: [_; CONSUMERS] = core::array::from_fn(|id| thread::spawn(consumer, id).unwrap());
+    let producer = thread::spawn(producer, 0).unwrap();
+    assert_eq!(producer.join(), 0);
+    let sum: usize = consumers.map(|handle| handle.join() as usize).iter().sum();
+    assert_eq!(sum, ITEMS * (ITEMS + 1) / 2);
+
+    // nobody notifies, so only the timeout ends the wait
+    let start = get_time();
+    let (queue, timed_out) = NOT_EMPTY.wait_timeout(QUEUE.lock(), 50);
+    assert!(timed_out);
+    assert!(get_time() - start >= 50);
+    drop(queue);
+    println!("Test condvar OK!");
+    0
+}
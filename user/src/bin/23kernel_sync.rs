@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use user_lib::{
+    condvar_create, condvar_signal, condvar_wait, mutex_create, mutex_lock, mutex_unlock,
+    semaphore_create, semaphore_down, semaphore_up,
+    sync::{EINVAL, EPERM},
+    thread, yield_,
+};
+
+const THREADS: usize = 4;
+const ROUNDS: usize = 200;
+
+/// ids of the kernel objects, created before the threads
+static MUTEX: AtomicUsize = AtomicUsize::new(0);
+static SEMAPHORE: AtomicUsize = AtomicUsize::new(0);
+static CONDVAR: AtomicUsize = AtomicUsize::new(0);
+
+/// load and store apart, so only the mutex keeps increments from getting lost
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+static READY: AtomicBool = AtomicBool::new(false);
+
+fn adder(_: usize) -> i32 {
+    let mutex = MUTEX.load(Ordering::Relaxed);
+    for i in 0..ROUNDS {
+        assert_eq!(mutex_lock(mutex), 0);
+        let seen = COUNTER.load(Ordering::Relaxed);
+        if i % 8 == 0 {
+            yield_();
+        }
+        COUNTER.store(seen + 1, Ordering::Relaxed);
+        assert_eq!(mutex_unlock(mutex), 0);
+    }
+    semaphore_up(SEMAPHORE.load(Ordering::Relaxed));
+    0
+}
+
+fn waiter(_: usize) -> i32 {
+    let (mutex, condvar) = (
+        MUTEX.load(Ordering::Relaxed),
+        CONDVAR.load(Ordering::Relaxed),
+    );
+    mutex_lock(mutex);
+    while !READY.load(Ordering::Relaxed) {
+        assert_eq!(condvar_wait(condvar, mutex), 0);
+    }
+    mutex_unlock(mutex);
+    0
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    MUTEX.store(mutex_create() as usize, Ordering::Relaxed);
+    SEMAPHORE.store(semaphore_create(0) as usize, Ordering::Relaxed);
+    CONDVAR.store(condvar_create() as usize, Ordering::Relaxed);
+    let mutex = MUTEX.load(Ordering::Relaxed);
+    assert_eq!(mutex_unlock(mutex), -EPERM);
+    assert_eq!(mutex_lock(4096), -EINVAL);
+
+    for id in 0..THREADS {
+        thread::spawn(adder, id).unwrap();
+    }
+    // one up by every adder once it is done
+    for _ in 0..THREADS {
+        assert_eq!(semaphore_down(SEMAPHORE.load(Ordering::Relaxed)), 0);
+    }
+    assert_eq!(COUNTER.load(Ordering::Relaxed), THREADS * ROUNDS);
+
+    let waiter = thread::spawn(waiter, 0).unwrap();
+    yield_();
+    mutex_lock(mutex);
+    READY.store(true, Ordering::Relaxed);
+    condvar_signal(CONDVAR.load(Ordering::Relaxed));
+    mutex_unlock(mutex);
+    assert_eq!(waiter.join(), 0);
+    println!("Test kernel sync OK!");
+    0
+}
@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    enable_deadlock_detect, mutex_create, mutex_lock, mutex_unlock, semaphore_create,
+    semaphore_down, sync::EDEADLK, thread, yield_,
+};
+
+static MUTEXES: [AtomicUsize; 2] = [AtomicUsize::new(0), AtomicUsize::new(0)];
+/// how many of the two sides hold their first mutex
+static HOLDING: AtomicUsize = AtomicUsize::new(0);
+
+/// Take one mutex, then the other one, which the other side holds. Returns 1 if it was
+/// refused, after backing off.
+fn take_both(side: usize) -> i32 {
+    let first = MUTEXES[side].load(Ordering::Relaxed);
+    let second = MUTEXES[1 - side].load(Ordering::Relaxed);
+    assert_eq!(mutex_lock(first), 0);
+    HOLDING.fetch_add(1, Ordering::Relaxed);
+    while HOLDING.load(Ordering::Relaxed) < 2 {
+        yield_();
+    }
+    match mutex_lock(second) {
+        0 => {
+            mutex_unlock(second);
+            mutex_unlock(first);
+            0
+        }
+        err => {
+            assert_eq!(err, -EDEADLK);
+            mutex_unlock(first);
+            1
+        }
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(enable_deadlock_detect(true), 0);
+    for mutex in MUTEXES.iter() {
+        mutex.store(mutex_create() as usize, Ordering::Relaxed);
+    }
+
+    // taking a mutex twice waits for itself
+    let mutex = MUTEXES[0].load(Ordering::Relaxed);
+    assert_eq!(mutex_lock(mutex), 0);
+    assert_eq!(mutex_lock(mutex), -EDEADLK);
+    assert_eq!(mutex_unlock(mutex), 0);
+
+    // whichever side asks second is refused, the other one then gets both
+    let other = thread::spawn(take_both, 1).unwrap();
+    let refused = take_both(0) + other.join();
+    assert_eq!(refused, 1);
+
+    // nothing holds what an empty semaphore waits for
+    let semaphore = semaphore_create(0) as usize;
+    assert_eq!(semaphore_down(semaphore), -EDEADLK);
+    println!("Test deadlock OK!");
+    0
+}
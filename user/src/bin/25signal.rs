@@ -0,0 +1,97 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    getpid,
+    signal::{
+        kill, sig_bit, sigaction, sigprocmask, SA_RESETHAND, SIGKILL, SIGSTOP, SIGTERM, SIGUSR1,
+        SIGUSR2, SIG_BLOCK, SIG_DFL, SIG_SETMASK, SIG_UNBLOCK,
+    },
+    sync::{EINVAL, ESRCH},
+    syscall::SigAction,
+    thread,
+};
+
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+static LAST: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn on_signal(sig: usize) {
+    LAST.store(sig, Ordering::Relaxed);
+    HANDLED.fetch_add(1, Ordering::Relaxed);
+}
+
+fn handled() -> usize {
+    HANDLED.load(Ordering::Relaxed)
+}
+
+/// Spin in user mode until a handler ran, the signal comes in on a timer interrupt.
+fn spin(until: usize) -> i32 {
+    while handled() < until {}
+    0
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let pid = getpid() as usize;
+    // the handler runs before kill returns, and the caller goes on where it was
+    assert_eq!(
+        sigaction(SIGUSR1, Some(&SigAction::new(on_signal)), None),
+        0
+    );
+    let x = core::hint::black_box(42usize);
+    assert_eq!(kill(pid, SIGUSR1), 0);
+    assert_eq!(handled(), 1);
+    assert_eq!(LAST.load(Ordering::Relaxed), SIGUSR1);
+    assert_eq!(x, 42);
+
+    // blocked signals stay pending until unblocked
+    let mut old = usize::MAX;
+    assert_eq!(
+        sigprocmask(SIG_BLOCK, Some(sig_bit(SIGUSR1)), Some(&mut old)),
+        0
+    );
+    assert_eq!(old, 0);
+    assert_eq!(kill(pid, SIGUSR1), 0);
+    assert_eq!(handled(), 1);
+    assert_eq!(sigprocmask(SIG_UNBLOCK, Some(sig_bit(SIGUSR1)), None), 0);
+    assert_eq!(handled(), 2);
+
+    // SIGKILL and SIGSTOP can be neither caught nor blocked
+    assert_eq!(
+        sigaction(SIGKILL, Some(&SigAction::ignore()), None),
+        -EINVAL
+    );
+    assert_eq!(sigprocmask(SIG_SETMASK, Some(usize::MAX), None), 0);
+    assert_eq!(sigprocmask(SIG_SETMASK, Some(0), Some(&mut old)), 0);
+    assert_eq!(old & (sig_bit(SIGKILL) | sig_bit(SIGSTOP)), 0);
+
+    // ignored signals are dropped, even those which would terminate
+    assert_eq!(sigaction(SIGTERM, Some(&SigAction::ignore()), None), 0);
+    assert_eq!(kill(pid, SIGTERM), 0);
+
+    // a one-shot handler
+    let once = SigAction::new(on_signal).with_flags(SA_RESETHAND);
+    assert_eq!(sigaction(SIGUSR2, Some(&once), None), 0);
+    assert_eq!(kill(pid, SIGUSR2), 0);
+    assert_eq!(LAST.load(Ordering::Relaxed), SIGUSR2);
+    let mut act = SigAction::default();
+    assert_eq!(sigaction(SIGUSR2, None, Some(&mut act)), 0);
+    assert_eq!(act.handler, SIG_DFL);
+
+    // a signal to the process goes to a thread which does not block it
+    let worker = thread::spawn(spin, 4).unwrap();
+    assert_eq!(sigprocmask(SIG_BLOCK, Some(sig_bit(SIGUSR1)), None), 0);
+    assert_eq!(kill(pid, SIGUSR1), 0);
+    assert_eq!(worker.join(), 0);
+    assert_eq!(handled(), 4);
+
+    assert_eq!(kill(pid, 0), 0);
+    assert_eq!(kill(4096, SIGUSR1), -ESRCH);
+    assert_eq!(kill(pid, 65), -EINVAL);
+    println!("Test signal OK!");
+    0
+}
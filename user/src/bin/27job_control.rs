@@ -0,0 +1,91 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exec, fork, getpgid, getpid, getsid, setpgid, setsid, shmat, shmctl, shmdt, shmget,
+    signal::{kill, killpg, SIGCONT, SIGSTOP, SIGTERM},
+    sleep,
+    sync::{ENOTTY, EPERM, ESRCH},
+    tty::{set_controlling_tty, tcgetpgrp, tcsetpgrp},
+    waitpid, waitpid_options, wifcontinued, wifsignaled, wifstopped, wstopsig, wtermsig, yield_,
+    IPC_CREAT, IPC_RMID, WCONTINUED, WNOHANG, WUNTRACED,
+};
+
+/// shared with job_target
+const SHM_KEY: usize = 0x4a43;
+
+#[no_mangle]
+fn main() -> i32 {
+    let id = shmget(SHM_KEY, 4096, IPC_CREAT);
+    assert!(id > 0, "shmget failed");
+    let id = id as usize;
+    let base = shmat(id, 0) as *mut usize;
+    let progress = || unsafe { base.add(2).read_volatile() };
+    let target = fork();
+    if target == 0 {
+        exec("job_target\0");
+        println!("exec job_target failed");
+        return -4;
+    }
+    let target = target as usize;
+    unsafe {
+        while base.read_volatile() != 1 {
+            yield_();
+        }
+        assert_eq!(base.add(1).read_volatile(), target);
+    }
+
+    let mut status = 0;
+    assert_eq!(waitpid_options(target, &mut status, WNOHANG), 0);
+
+    // a stopped process makes no progress until it is continued
+    assert_eq!(kill(target, SIGSTOP), 0);
+    assert_eq!(
+        waitpid_options(target, &mut status, WUNTRACED),
+        target as isize
+    );
+    assert!(wifstopped(status));
+    assert_eq!(wstopsig(status), SIGSTOP);
+    let stopped_at = progress();
+    sleep(20);
+    assert_eq!(progress(), stopped_at);
+    assert_eq!(kill(target, SIGCONT), 0);
+    assert_eq!(
+        waitpid_options(target, &mut status, WCONTINUED),
+        target as isize
+    );
+    assert!(wifcontinued(status));
+    while progress() == stopped_at {
+        yield_();
+    }
+
+    // in a process group of its own, which is killed as a whole
+    assert_eq!(setpgid(target, 0), 0);
+    assert_eq!(getpgid(target), target as isize);
+    assert_eq!(killpg(target, SIGTERM), 0);
+    assert_eq!(waitpid(target, &mut status), target as isize);
+    assert!(wifsignaled(status));
+    assert_eq!(wtermsig(status), SIGTERM);
+    assert_eq!(killpg(target, SIGTERM), -ESRCH);
+    assert_eq!(shmdt(base as usize), 0);
+    assert_eq!(shmctl(id, IPC_RMID), 0);
+
+    // a session of its own, with the console as its controlling terminal
+    let pid = getpid() as usize;
+    assert_eq!(getsid(0), 0);
+    assert_eq!(setsid(), pid as isize);
+    assert_eq!(getsid(0), pid as isize);
+    assert_eq!(getpgid(0), pid as isize);
+    assert_eq!(setsid(), -EPERM);
+    assert_eq!(setpgid(0, 0), -EPERM);
+    assert_eq!(tcgetpgrp(0), -ENOTTY);
+    assert_eq!(set_controlling_tty(0), 0);
+    assert_eq!(tcgetpgrp(0), pid as isize);
+    assert_eq!(tcsetpgrp(0, 4096), -EPERM);
+    assert_eq!(tcsetpgrp(0, pid), 0);
+    println!("Test job control OK!");
+    0
+}
@@ -0,0 +1,122 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    getpid, open, read, setsid,
+    sync::{EBADF, EPERM},
+    tty::{
+        get_winsize, posix_openpt, ptsname_r, set_controlling_tty, set_winsize, simulate_input,
+        tcgetattr, tcsetattr, unlockpt, Termios, WinSize, ECHO, ICANON, ICRNL, ISIG, TCSAFLUSH,
+        TCSANOW, VEOF, VERASE, VMIN,
+    },
+    O_NOCTTY, O_RDWR,
+};
+
+const STDIN: usize = 0;
+
+/// Open a new pseudo-terminal for the test to run on, so the console is left alone. Its
+/// slave side becomes the controlling terminal of a new session, only there can input be
+/// simulated. Returns the fd of the slave side.
+fn open_tty() -> usize {
+    let master = posix_openpt(O_RDWR | O_NOCTTY);
+    assert!(master >= 0);
+    let mut name = [0u8; 16];
+    let len = ptsname_r(master as usize, &mut name);
+    assert!(len > 0);
+    assert_eq!(unlockpt(master as usize), 0);
+    let path = core::str::from_utf8(&name[..=len as usize]).unwrap();
+    let slave = open(path, O_RDWR);
+    assert!(slave >= 0);
+    assert_eq!(setsid(), getpid());
+    assert_eq!(set_controlling_tty(slave as usize), 0);
+    slave as usize
+}
+
+/// Have the terminal take `bytes` as if they were typed.
+fn type_in(tty: usize, bytes: &[u8]) {
+    for &byte in bytes {
+        assert_eq!(simulate_input(tty, byte), 0);
+    }
+}
+
+fn read_input(tty: usize, buf: &mut [u8]) -> &[u8] {
+    let len = read(tty, buf);
+    assert!(len >= 0);
+    &buf[..len as usize]
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    // not the controlling terminal of the caller
+    assert_eq!(simulate_input(STDIN, b'x'), -EPERM);
+    let tty = open_tty();
+    let mut saved = Termios::default();
+    assert_eq!(tcgetattr(tty, &mut saved), 0);
+    assert_eq!(saved.lflag & (ISIG | ICANON | ECHO), ISIG | ICANON | ECHO);
+    assert_eq!(saved.iflag & ICRNL, ICRNL);
+    assert_eq!(saved.cc[VERASE], 0x7f);
+    assert_eq!(saved.cc[VEOF], 0x04);
+
+    // canonical mode, without echo as nobody reads the master side
+    let mut quiet = saved;
+    quiet.lflag &= !ECHO;
+    assert_eq!(tcsetattr(tty, TCSANOW, &quiet), 0);
+    let mut buf = [0u8; 16];
+    type_in(tty, b"ab\x7fc\r");
+    assert_eq!(read_input(tty, &mut buf), b"ac\n");
+    type_in(tty, b"one two\x17three\n");
+    assert_eq!(read_input(tty, &mut buf), b"one three\n");
+    type_in(tty, b"junk\x15ok\n");
+    assert_eq!(read_input(tty, &mut buf), b"ok\n");
+
+    // a read ends with the line, and the rest of one too long for it is left for the next
+    type_in(tty, b"1\n2\n");
+    assert_eq!(read_input(tty, &mut buf), b"1\n");
+    assert_eq!(read_input(tty, &mut buf), b"2\n");
+    type_in(tty, b"long\n");
+    assert_eq!(read_input(tty, &mut buf[..2]), b"lo");
+    assert_eq!(read_input(tty, &mut buf), b"ng\n");
+
+    // ^D ends a line without a newline, at the start of one it is the end of file
+    type_in(tty, b"eof\x04");
+    assert_eq!(read_input(tty, &mut buf), b"eof");
+    type_in(tty, b"\x04");
+    assert_eq!(read_input(tty, &mut buf), b"");
+
+    // raw mode hands out bytes as they come, control characters included
+    let mut raw = quiet;
+    raw.make_raw();
+    raw.cc[VMIN] = 0;
+    assert_eq!(tcsetattr(tty, TCSANOW, &raw), 0);
+    assert_eq!(read_input(tty, &mut buf), b"");
+    type_in(tty, b"x\x7f\x03\r");
+    assert_eq!(read_input(tty, &mut buf), b"x\x7f\x03\r");
+    type_in(tty, b"stale");
+    assert_eq!(tcsetattr(tty, TCSAFLUSH, &raw), 0);
+    assert_eq!(read_input(tty, &mut buf), b"");
+
+    // leaving canonical mode makes the line being edited readable
+    assert_eq!(tcsetattr(tty, TCSANOW, &quiet), 0);
+    type_in(tty, b"half");
+    assert_eq!(tcsetattr(tty, TCSANOW, &raw), 0);
+    assert_eq!(read_input(tty, &mut buf), b"half");
+
+    let mut size = WinSize::default();
+    assert_eq!(get_winsize(tty, &mut size), 0);
+    assert_eq!((size.row, size.col), (24, 80));
+    let wide = WinSize {
+        row: 50,
+        col: 132,
+        ..size
+    };
+    assert_eq!(set_winsize(tty, &wide), 0);
+    let mut now = WinSize::default();
+    assert_eq!(get_winsize(tty, &mut now), 0);
+    assert_eq!(now, wide);
+    assert_eq!(tcgetattr(9, &mut saved), -EBADF);
+    println!("Test termios OK!");
+    0
+}
@@ -0,0 +1,119 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    close, fork, getpid, open, read, setsid,
+    signal::{sigaction, SIGHUP, SIGINT},
+    sleep,
+    sync::{EBADF, EIO, ENOENT},
+    syscall::SigAction,
+    tty::{grantpt, posix_openpt, ptsname_r, set_controlling_tty, tcgetpgrp, unlockpt},
+    waitpid, waitpid_options, write, O_NOCTTY, O_RDWR, WNOHANG,
+};
+
+/// More than the master side queues, `MAX_INPUT` in the kernel.
+const FLOOD: usize = 5000;
+
+static INTERRUPTED: AtomicUsize = AtomicUsize::new(0);
+static HUNG_UP: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn on_sigint(_sig: usize) {
+    INTERRUPTED.fetch_add(1, Ordering::Relaxed);
+}
+
+extern "C" fn on_sighup(_sig: usize) {
+    HUNG_UP.fetch_add(1, Ordering::Relaxed);
+}
+
+fn read_from(fd: usize, buf: &mut [u8]) -> &[u8] {
+    let len = read(fd, buf);
+    assert!(len >= 0);
+    &buf[..len as usize]
+}
+
+/// Open a new pair, returns the fds of its master and slave sides.
+fn open_pair() -> (usize, usize) {
+    let master = posix_openpt(O_RDWR | O_NOCTTY);
+    assert!(master >= 0);
+    let mut name = [0u8; 16];
+    let len = ptsname_r(master as usize, &mut name);
+    assert!(len > 0);
+    let path = core::str::from_utf8(&name[..=len as usize]).unwrap();
+    assert!(path.starts_with("/dev/pts/"));
+    // the slave side stays locked until it is unlocked
+    assert_eq!(open(path, O_RDWR), -EIO);
+    assert_eq!(grantpt(master as usize), 0);
+    assert_eq!(unlockpt(master as usize), 0);
+    let slave = open(path, O_RDWR);
+    assert!(slave >= 0);
+    (master as usize, slave as usize)
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(open("/dev/pts/99\0", O_RDWR), -ENOENT);
+    let (master, slave) = open_pair();
+    let mut buf = [0u8; 16];
+
+    // what is written to the master side is typed at the slave side, and echoed back
+    assert_eq!(write(master, b"hi\r"), 3);
+    assert_eq!(read_from(slave, &mut buf), b"hi\n");
+    assert_eq!(read_from(master, &mut buf), b"hi\r\n");
+    assert_eq!(write(slave, b"out\n"), 4);
+    assert_eq!(read_from(master, &mut buf), b"out\r\n");
+
+    // as the controlling terminal, ^C interrupts the foreground process group
+    let pid = getpid() as usize;
+    assert_eq!(setsid(), pid as isize);
+    assert_eq!(set_controlling_tty(slave), 0);
+    assert_eq!(tcgetpgrp(master), pid as isize);
+    assert_eq!(sigaction(SIGINT, Some(&SigAction::new(on_sigint)), None), 0);
+    assert_eq!(write(master, b"junk\x03"), 5);
+    assert_eq!(INTERRUPTED.load(Ordering::Relaxed), 1);
+    assert_eq!(read_from(master, &mut buf), b"junk^C");
+    assert_eq!(write(master, b"ok\n"), 3);
+    assert_eq!(read_from(slave, &mut buf), b"ok\n");
+    assert_eq!(read_from(master, &mut buf), b"ok\r\n");
+
+    // once the slave side is closed the master side reads fail, and closing the master side
+    // hangs up the session it controls
+    assert_eq!(close(slave), 0);
+    assert_eq!(read(master, &mut buf), -EIO);
+    assert_eq!(sigaction(SIGHUP, Some(&SigAction::new(on_sighup)), None), 0);
+    assert_eq!(close(master), 0);
+    assert_eq!(HUNG_UP.load(Ordering::Relaxed), 1);
+
+    // a slave side which was hung up reads the end of file and cannot be written to
+    let (master, slave) = open_pair();
+    assert_eq!(close(master), 0);
+    assert_eq!(read(slave, &mut buf), 0);
+    assert_eq!(write(slave, b"lost"), -EIO);
+    assert_eq!(close(slave), 0);
+    assert_eq!(close(slave), -EBADF);
+
+    // a writer of the slave side waits for the master side to read what is queued
+    let (master, slave) = open_pair();
+    let child = fork();
+    if child == 0 {
+        assert_eq!(write(slave, &[b'x'; FLOOD]), FLOOD as isize);
+        return 0;
+    }
+    let mut status = 0;
+    sleep(20);
+    assert_eq!(waitpid_options(child as usize, &mut status, WNOHANG), 0);
+    let mut total = 0;
+    while total < FLOOD {
+        total += read_from(master, &mut buf).len();
+    }
+    assert_eq!(total, FLOOD);
+    assert_eq!(waitpid(child as usize, &mut status), child);
+    assert_eq!(status, 0);
+    assert_eq!(close(slave), 0);
+    assert_eq!(close(master), 0);
+    println!("Test pty OK!");
+    0
+}
@@ -0,0 +1,115 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exec, fork, open, read,
+    tty::{posix_openpt, ptsname_r, unlockpt},
+    waitpid, wexitstatus, wifexited, write, O_NOCTTY, O_RDWR,
+};
+
+/// What the master side read and nobody looked for yet.
+struct Screen {
+    master: usize,
+    buf: [u8; 1024],
+    len: usize,
+}
+
+impl Screen {
+    /// Read until the shell printed `text`, and forget what came before it.
+    fn expect(&mut self, text: &str) {
+        let text = text.as_bytes();
+        loop {
+            if let Some(at) = self.buf[..self.len]
+                .windows(text.len())
+                .position(|window| window == text)
+            {
+                let end = at + text.len();
+                self.buf.copy_within(end..self.len, 0);
+                self.len -= end;
+                return;
+            }
+            if self.len == self.buf.len() {
+                // keep what may be the start of `text`
+                let keep = text.len() - 1;
+                self.buf.copy_within(self.len - keep.., 0);
+                self.len = keep;
+            }
+            let len = read(self.master, &mut self.buf[self.len..]);
+            assert!(len > 0, "the shell is gone");
+            self.len += len as usize;
+        }
+    }
+
+    /// Type `bytes` at the shell.
+    fn type_in(&self, bytes: &[u8]) {
+        assert_eq!(write(self.master, bytes), bytes.len() as isize);
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let master = posix_openpt(O_RDWR | O_NOCTTY);
+    assert!(master >= 0);
+    let master = master as usize;
+    let mut name = [0u8; 16];
+    let len = ptsname_r(master, &mut name);
+    assert!(len > 0);
+    assert_eq!(unlockpt(master), 0);
+    let shell = fork();
+    if shell == 0 {
+        // the slave side as stdin and stdout, the lowest fds free
+        let path = core::str::from_utf8(&name[..=len as usize]).unwrap();
+        close(0);
+        close(1);
+        assert_eq!(open(path, O_RDWR), 0);
+        assert_eq!(open(path, O_RDWR), 1);
+        exec("user_shell\0");
+        return -4;
+    }
+    let mut screen = Screen {
+        master,
+        buf: [0; 1024],
+        len: 0,
+    };
+    screen.expect("$ ");
+
+    // a job in the foreground prints to the terminal
+    screen.type_in(b"hello_world\r");
+    screen.expect("Hello, world!\r\n");
+    screen.expect("exited with code 0");
+    screen.expect("$ ");
+
+    // ^Z stops it, fg continues it in the foreground
+    screen.type_in(b"sleep\r");
+    screen.expect("Before sleep.");
+    screen.type_in(b"\x1a");
+    screen.expect("[1] Stopped sleep");
+    screen.expect("$ ");
+    screen.type_in(b"jobs\r");
+    screen.expect("[1] Stopped sleep");
+    screen.expect("$ ");
+    screen.type_in(b"fg\r");
+    screen.expect("Test sleep OK!");
+    screen.expect("exited with code 0");
+    screen.expect("$ ");
+
+    // ^C kills it, not the shell
+    screen.type_in(b"sleep\r");
+    screen.expect("Before sleep.");
+    screen.type_in(b"\x03");
+    screen.expect("killed by signal 2");
+    screen.expect("$ ");
+
+    // ^D at the start of a line ends the shell
+    screen.type_in(b"\x04");
+    screen.expect("exit");
+    let mut status = 0;
+    assert_eq!(waitpid(shell as usize, &mut status), shell);
+    assert!(wifexited(status));
+    assert_eq!(wexitstatus(status), 0);
+    println!("Test shell on a pty OK!");
+    0
+}
@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{get_time, getpid, shmat, shmget, yield_};
+
+/// shared with 27job_control, which runs this one, stops, continues and at last kills it
+const SHM_KEY: usize = 0x4a43;
+/// give up on being killed after this many ms, so a broken controller does not hang the tests
+const TIMEOUT_MS: isize = 10_000;
+
+#[no_mangle]
+fn main() -> i32 {
+    let id = shmget(SHM_KEY, 0, 0);
+    assert!(id > 0, "shmget failed");
+    let addr = shmat(id as usize, 0);
+    assert!(addr > 0, "shmat failed");
+    let base = addr as *mut usize;
+    println!("job target {} waits to be killed", getpid());
+    let deadline = get_time() + TIMEOUT_MS;
+    unsafe {
+        base.add(1).write_volatile(getpid() as usize);
+        base.write_volatile(1);
+        // progress for the controller to watch
+        while get_time() < deadline {
+            base.add(2).write_volatile(base.add(2).read_volatile() + 1);
+            yield_();
+        }
+    }
+    println!("job target {} was not killed", getpid());
+    -1
+}
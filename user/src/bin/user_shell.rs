@@ -1,59 +1,237 @@
 #![no_std]
 #![no_main]
 
-extern crate alloc;
-
 #[macro_use]
 extern crate user_lib;
 
-const BS: u8 = 0x08u8;
-const LF: u8 = 0x0au8;
-const CR: u8 = 0x0du8;
-const DL: u8 = 0x7fu8;
+const STDIN: usize = 0;
+
+use user_lib::signal::{
+    killpg, sigaction, SIGCONT, SIGINT, SIGKILL, SIGQUIT, SIGTSTP, SIGTTIN, SIGTTOU,
+};
+use user_lib::syscall::SigAction;
+use user_lib::tty::{set_controlling_tty, tcsetpgrp};
+use user_lib::{exec, fork, getpid, read, setpgid, setsid};
+use user_lib::{waitpid_options, wexitstatus, wifsignaled, wifstopped, wtermsig};
+use user_lib::{WNOHANG, WUNTRACED};
+
+/// Typed at the terminal, these are for the foreground job rather than the shell.
+const JOB_SIGNALS: [usize; 5] = [SIGINT, SIGQUIT, SIGTSTP, SIGTTIN, SIGTTOU];
+
+/// Longest command line, the terminal has to fit it in its line buffer as well.
+const LINE_MAX: usize = 256;
+/// Jobs the shell keeps track of, there is no heap to grow the list.
+const MAX_JOBS: usize = 8;
+
+/// A command which was stopped or put in the background, in a process group of its own.
+#[derive(Clone, Copy)]
+struct Job {
+    pgid: usize,
+    line: [u8; LINE_MAX],
+    len: usize,
+    stopped: bool,
+}
+
+impl Job {
+    fn new(pgid: usize, line: &str) -> Self {
+        let mut job = Job {
+            pgid,
+            line: [0; LINE_MAX],
+            len: line.len(),
+            stopped: false,
+        };
+        job.line[..line.len()].copy_from_slice(line.as_bytes());
+        job
+    }
+
+    fn line(&self) -> &str {
+        core::str::from_utf8(&self.line[..self.len]).unwrap_or("")
+    }
+}
+
+/// The jobs in the order they were stopped or put in the background, numbered from 1.
+struct Jobs {
+    list: [Job; MAX_JOBS],
+    len: usize,
+}
+
+impl Jobs {
+    fn new() -> Self {
+        Jobs {
+            list: [Job::new(0, ""); MAX_JOBS],
+            len: 0,
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.list[..self.len].iter()
+    }
+
+    /// Add `job` as the last one and return its number, `None` if the list is full.
+    fn push(&mut self, job: Job) -> Option<usize> {
+        if self.len == MAX_JOBS {
+            return None;
+        }
+        self.list[self.len] = job;
+        self.len += 1;
+        Some(self.len)
+    }
+
+    fn remove(&mut self, index: usize) -> Job {
+        let job = self.list[index];
+        self.list.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+        job
+    }
+}
+
+fn set_job_signals(action: &SigAction) {
+    for sig in JOB_SIGNALS {
+        sigaction(sig, Some(action), None);
+    }
+}
+
+/// Hand the terminal to `job` and wait until it exits or stops, a stopped job is added to
+/// `jobs`.
+fn wait_foreground(jobs: &mut Jobs, mut job: Job) {
+    let pgid = job.pgid;
+    tcsetpgrp(0, pgid);
+    let mut status: i32 = 0;
+    let exit_pid = waitpid_options(pgid, &mut status, WUNTRACED);
+    assert_eq!(pgid as isize, exit_pid);
+    tcsetpgrp(0, getpid() as usize);
+    if wifstopped(status) {
+        job.stopped = true;
+        match jobs.push(job) {
+            Some(n) => println!("[{}] Stopped {}", n, job.line()),
+            None => {
+                // nothing could bring it back, do not leave it behind stopped
+                println!("Shell: too many jobs, killing {}", job.line());
+                killpg(pgid, SIGKILL);
+                killpg(pgid, SIGCONT);
+                waitpid_options(pgid, &mut status, 0);
+            }
+        }
+    } else if wifsignaled(status) {
+        println!(
+            "Shell: Process {} killed by signal {}",
+            pgid,
+            wtermsig(status)
+        );
+    } else {
+        println!(
+            "Shell: Process {} exited with code {}",
+            pgid,
+            wexitstatus(status)
+        );
+    }
+}
+
+/// Report and forget background jobs which are done.
+fn reap_jobs(jobs: &mut Jobs) {
+    let mut i = 0;
+    while i < jobs.len {
+        let mut status: i32 = 0;
+        let job = &jobs.list[i];
+        if !job.stopped && waitpid_options(job.pgid, &mut status, WNOHANG) > 0 {
+            println!("[{}] Done {}", i + 1, job.line());
+            jobs.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// The job `arg` names by number, the last one if there is no `arg`.
+fn take_job(jobs: &mut Jobs, arg: Option<&str>) -> Option<Job> {
+    let index = match arg {
+        Some(arg) => arg
+            .trim_start_matches('%')
+            .parse::<usize>()
+            .ok()?
+            .checked_sub(1)?,
+        None => jobs.len.checked_sub(1)?,
+    };
+    (index < jobs.len).then(|| jobs.remove(index))
+}
 
-use alloc::string::String;
-use user_lib::getchar;
-use user_lib::{exec, fork, waitpid, yield_};
+/// Run the `jobs`, `fg` and `bg` builtins, returns false if `line` is none of them.
+fn builtin(jobs: &mut Jobs, line: &str) -> bool {
+    let mut args = line.split_whitespace();
+    match args.next() {
+        Some("jobs") => {
+            for (i, job) in jobs.iter().enumerate() {
+                let state = if job.stopped { "Stopped" } else { "Running" };
+                println!("[{}] {} {}", i + 1, state, job.line());
+            }
+        }
+        Some("fg") => match take_job(jobs, args.next()) {
+            Some(job) => {
+                println!("{}", job.line());
+                // the terminal first, or it stops again as soon as it reads
+                tcsetpgrp(0, job.pgid);
+                killpg(job.pgid, SIGCONT);
+                wait_foreground(jobs, job);
+            }
+            None => println!("fg: no such job"),
+        },
+        Some("bg") => match take_job(jobs, args.next()) {
+            Some(mut job) => {
+                killpg(job.pgid, SIGCONT);
+                job.stopped = false;
+                // it was just taken out, there is room for it
+                let n = jobs.push(job).unwrap();
+                println!("[{}] {} &", n, job.line());
+            }
+            None => println!("bg: no such job"),
+        },
+        _ => return false,
+    }
+    true
+}
 
 #[no_mangle]
 pub fn main() -> i32 {
     println!("Rust user shell");
-    let mut line: String = String::new();
-    println!("$ ");
+    // a session of its own, with the console to give to the job in the foreground
+    setsid();
+    set_controlling_tty(0);
+    set_job_signals(&SigAction::ignore());
+    let mut jobs = Jobs::new();
+    let mut buf = [0u8; LINE_MAX];
+    // the line with the NUL `exec` wants
+    let mut path = [0u8; LINE_MAX + 1];
+    print!("$ ");
     loop {
-        let c = getchar();
-        match c {
-            LF | CR => {
-                println!("");
-                if !line.is_empty() {
-                    line.push('\0');
-                    let pid = fork();
-                    if pid == 0 {
-                        if exec(line.as_str()) == -1 {
-                            println!("Error when executing!");
-                            return -4;
-                        }
-                        unreachable!();
-                    } else {
-                        let mut exit_code: i32 = 0;
-                        let exit_pid = waitpid(pid as usize, &mut exit_code);
-                        assert_eq!(pid, exit_pid);
-                        println!("Shell: Process {} exited with code {}", pid, exit_code);
-                    }
-                    line.clear();
-                }
-                print!("$ ");
-            }
-            BS | DL => {
-                if !line.is_empty() {
-                    print!("{} {}", BS as char, BS as char);
-                    line.pop();
+        // the terminal edits and echoes the line, it comes in once it is complete
+        let len = read(STDIN, &mut buf);
+        if len <= 0 {
+            println!("exit");
+            return 0;
+        }
+        let line = core::str::from_utf8(&buf[..len as usize])
+            .unwrap_or("")
+            .trim();
+        if !line.is_empty() && !builtin(&mut jobs, line) {
+            path[..line.len()].copy_from_slice(line.as_bytes());
+            path[line.len()] = 0;
+            let path = core::str::from_utf8(&path[..=line.len()]).unwrap();
+            let pid = fork();
+            if pid == 0 {
+                setpgid(0, 0);
+                set_job_signals(&SigAction::default());
+                if exec(path) < 0 {
+                    println!("Error when executing!");
+                    return -4;
                 }
-            }
-            _ => {
-                print!("{}", c as char);
-                line.push(c as char);
+                unreachable!();
+            } else {
+                // in the child as well, whichever runs first
+                setpgid(pid as usize, pid as usize);
+                wait_foreground(&mut jobs, Job::new(pid as usize, line));
             }
         }
+        reap_jobs(&mut jobs);
+        print!("$ ");
     }
 }
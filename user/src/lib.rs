@@ -2,7 +2,11 @@
 #![feature(linkage)]
 
 pub mod console;
+pub mod signal;
+pub mod sync;
 pub mod syscall;
+pub mod thread;
+pub mod tty;
 
 mod alloc;
 
@@ -39,18 +43,103 @@ pub fn getchar() -> u8 {
 pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
+
+pub const O_RDONLY: usize = 0;
+pub const O_WRONLY: usize = 1;
+pub const O_RDWR: usize = 2;
+pub const O_NOCTTY: usize = 0o400;
+
+/// Open the device at `path`, which has to end with a NUL like the one `exec` takes. Returns
+/// the lowest free fd.
+pub fn open(path: &str, flags: usize) -> isize {
+    sys_openat(path, flags)
+}
+
+pub fn close(fd: usize) -> isize {
+    sys_close(fd)
+}
+
+/// Exit every thread of the process, see `thread::exit` for the caller alone.
 pub fn exit(exit_code: i32) -> isize {
-    sys_exit(exit_code)
+    sys_exit_group(exit_code)
+}
+
+pub fn getpid() -> isize {
+    sys_getpid()
+}
+
+pub fn gettid() -> isize {
+    sys_gettid()
 }
 
 pub fn yield_() -> isize {
     sys_yield()
 }
 
+/// Weight under the stride scheduler, at least 2.
+pub fn set_priority(prio: isize) -> isize {
+    sys_set_priority(prio)
+}
+
+pub const PRIO_PROCESS: usize = 0;
+
+/// Set the nice value of process `who`, or of the caller if it is 0.
+pub fn setpriority(which: usize, who: usize, nice: isize) -> isize {
+    sys_setpriority(which, who, nice)
+}
+
+/// The nice value of process `who`, or `None` if there is no such process.
+pub fn getpriority(which: usize, who: usize) -> Option<isize> {
+    match sys_getpriority(which, who) {
+        -1 => None,
+        prio => Some(20 - prio),
+    }
+}
+
 pub fn get_time() -> isize {
     sys_get_time()
 }
 
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+pub const CLOCK_PROCESS_CPUTIME_ID: usize = 2;
+
+pub fn clock_gettime(clock_id: usize, ts: &mut TimeSpec) -> isize {
+    sys_clock_gettime(clock_id, ts)
+}
+
+pub fn nanosleep(req: &TimeSpec, rem: &mut TimeSpec) -> isize {
+    sys_nanosleep(req, rem)
+}
+
+pub fn sleep(ms: usize) -> isize {
+    let req = TimeSpec {
+        sec: ms / 1000,
+        nsec: ms % 1000 * 1_000_000,
+    };
+    nanosleep(&req, &mut TimeSpec::default())
+}
+
+pub const CLOCK_TICKS_PER_SEC: usize = 100;
+
+/// Fill `tms` with the CPU time of this process and its reaped children, returns the clock
+/// ticks since boot.
+pub fn times(tms: &mut Tms) -> isize {
+    sys_times(tms)
+}
+
+pub const RUSAGE_SELF: isize = 0;
+pub const RUSAGE_CHILDREN: isize = -1;
+
+pub fn getrusage(who: isize, usage: &mut Rusage) -> isize {
+    sys_getrusage(who, usage)
+}
+
+/// Memory and swap sizes and the number of processes.
+pub fn sysinfo(info: &mut Sysinfo) -> isize {
+    sys_sysinfo(info)
+}
+
 pub fn fork() -> isize {
     sys_fork()
 }
@@ -59,22 +148,138 @@ pub fn exec(path: &str) -> isize {
     sys_exec(path)
 }
 
-pub fn wait(exit_code: &mut i32) -> isize {
-    loop {
-        match sys_waitpid(-1, exit_code as *mut _) {
-            -2 => {yield_();}
-            exit_pid => return exit_pid,
-        }
-    }
+/// The kernel blocks the caller until any child exits, `-ECHILD` if there are none.
+pub fn wait(status: &mut i32) -> isize {
+    sys_waitpid(-1, status as *mut _, 0)
 }
 
-pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
-    loop {
-        match sys_waitpid(pid as isize, exit_code as *mut _) {
-            -2 => {yield_();}
-            exit_pid => return exit_pid,
-        }
-    }    
+/// Wait for process `pid` to exit, `status` tells how, see `wifexited` and the like.
+pub fn waitpid(pid: usize, status: &mut i32) -> isize {
+    sys_waitpid(pid as isize, status as *mut _, 0)
+}
+
+pub const WNOHANG: usize = 1;
+pub const WUNTRACED: usize = 2;
+pub const WCONTINUED: usize = 8;
+
+/// Like `waitpid`, `WUNTRACED` and `WCONTINUED` have it report stops and continues too, and
+/// `WNOHANG` returns 0 rather than blocking.
+pub fn waitpid_options(pid: usize, status: &mut i32, options: usize) -> isize {
+    sys_waitpid(pid as isize, status as *mut _, options)
+}
+
+pub fn wifexited(status: i32) -> bool {
+    status & 0x7f == 0
+}
+
+pub fn wexitstatus(status: i32) -> i32 {
+    (status >> 8) & 0xff
+}
+
+pub fn wifsignaled(status: i32) -> bool {
+    !wifexited(status) && !wifstopped(status) && !wifcontinued(status)
+}
+
+pub fn wtermsig(status: i32) -> usize {
+    (status & 0x7f) as usize
+}
+
+pub fn wifstopped(status: i32) -> bool {
+    status & 0xff == 0x7f
+}
+
+pub fn wstopsig(status: i32) -> usize {
+    wexitstatus(status) as usize
+}
+
+pub fn wifcontinued(status: i32) -> bool {
+    status == 0xffff
+}
+
+/// Move process `pid` into process group `pgid`, 0 stands for the caller and its pid.
+pub fn setpgid(pid: usize, pgid: usize) -> isize {
+    sys_setpgid(pid, pgid)
+}
+
+pub fn getpgid(pid: usize) -> isize {
+    sys_getpgid(pid)
+}
+
+pub fn getsid(pid: usize) -> isize {
+    sys_getsid(pid)
+}
+
+/// Lead a new session and process group, returns the session id.
+pub fn setsid() -> isize {
+    sys_setsid()
+}
+
+pub const IPC_PRIVATE: usize = 0;
+pub const IPC_CREAT: usize = 0o1000;
+pub const IPC_EXCL: usize = 0o2000;
+pub const IPC_RMID: usize = 0;
+pub const SHM_RDONLY: usize = 0o10000;
+
+pub fn shmget(key: usize, size: usize, flags: usize) -> isize {
+    sys_shmget(key, size, flags)
+}
+
+pub fn shmat(id: usize, flags: usize) -> isize {
+    sys_shmat(id, flags)
+}
+
+pub fn shmdt(addr: usize) -> isize {
+    sys_shmdt(addr)
+}
+
+pub fn shmctl(id: usize, cmd: usize) -> isize {
+    sys_shmctl(id, cmd)
+}
+
+// Locks kept by the kernel, every call is a syscall. `sync` has the ones which only enter
+// the kernel under contention.
+
+pub fn mutex_create() -> isize {
+    sys_mutex_create()
+}
+
+pub fn mutex_lock(id: usize) -> isize {
+    sys_mutex_lock(id)
+}
+
+pub fn mutex_unlock(id: usize) -> isize {
+    sys_mutex_unlock(id)
+}
+
+pub fn semaphore_create(count: usize) -> isize {
+    sys_semaphore_create(count)
+}
+
+pub fn semaphore_up(id: usize) -> isize {
+    sys_semaphore_up(id)
+}
+
+pub fn semaphore_down(id: usize) -> isize {
+    sys_semaphore_down(id)
+}
+
+pub fn condvar_create() -> isize {
+    sys_condvar_create()
+}
+
+pub fn condvar_signal(id: usize) -> isize {
+    sys_condvar_signal(id)
+}
+
+/// Unlock mutex `mutex_id` while sleeping until signaled, it is locked again on return.
+pub fn condvar_wait(id: usize, mutex_id: usize) -> isize {
+    sys_condvar_wait(id, mutex_id)
+}
+
+/// With detection on, taking a mutex or semaphore fails with `-EDEADLK` instead of blocking
+/// when some thread of the process might never get what it waits for.
+pub fn enable_deadlock_detect(enabled: bool) -> isize {
+    sys_enable_deadlock_detect(enabled as usize)
 }
 
 mod panic {
@@ -95,6 +300,6 @@ mod panic {
         } else {
             println!("\x1b[31m[Panic] {}\x1b[0m", msg);
         }
-        syscall::sys_exit(-1)
+        syscall::sys_exit_group(-1)
     }
 }
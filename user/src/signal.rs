@@ -0,0 +1,87 @@
+//! Signals. A handler runs on the stack of the thread it interrupted, the kernel saves the
+//! registers below it and `__sigreturn` has them restored once the handler returns.
+
+use crate::syscall::{__sigreturn, sys_kill, sys_sigaction, sys_sigprocmask, SigAction};
+
+pub const SIGHUP: usize = 1;
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL: usize = 4;
+pub const SIGTRAP: usize = 5;
+pub const SIGABRT: usize = 6;
+pub const SIGBUS: usize = 7;
+pub const SIGFPE: usize = 8;
+pub const SIGKILL: usize = 9;
+pub const SIGUSR1: usize = 10;
+pub const SIGSEGV: usize = 11;
+pub const SIGUSR2: usize = 12;
+pub const SIGPIPE: usize = 13;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGSTOP: usize = 19;
+pub const SIGTSTP: usize = 20;
+pub const SIGTTIN: usize = 21;
+pub const SIGTTOU: usize = 22;
+pub const SIGURG: usize = 23;
+pub const SIGWINCH: usize = 28;
+
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+/// do not block the signal while its handler runs
+pub const SA_NODEFER: usize = 0x4000_0000;
+/// back to `SIG_DFL` once the handler was called
+pub const SA_RESETHAND: usize = 0x8000_0000;
+
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+/// The bit of `sig` in a signal mask.
+pub const fn sig_bit(sig: usize) -> usize {
+    1 << (sig - 1)
+}
+
+impl SigAction {
+    /// Call `handler` with the signal number.
+    pub fn new(handler: extern "C" fn(usize)) -> Self {
+        Self {
+            handler: handler as usize,
+            flags: 0,
+            restorer: __sigreturn as usize,
+            mask: 0,
+        }
+    }
+    pub fn ignore() -> Self {
+        Self {
+            handler: SIG_IGN,
+            ..Self::default()
+        }
+    }
+    pub fn with_flags(mut self, flags: usize) -> Self {
+        self.flags = flags;
+        self
+    }
+}
+
+/// Send `sig` to process `pid`, a `sig` of 0 only checks that it exists.
+pub fn kill(pid: usize, sig: usize) -> isize {
+    sys_kill(pid as isize, sig)
+}
+
+/// Send `sig` to every process in process group `pgid`, the one of the caller if it is 0.
+pub fn killpg(pgid: usize, sig: usize) -> isize {
+    sys_kill(-(pgid as isize), sig)
+}
+
+/// Set the action for `sig` unless `act` is `None`, the old one goes to `old`.
+pub fn sigaction(sig: usize, act: Option<&SigAction>, old: Option<&mut SigAction>) -> isize {
+    sys_sigaction(sig, act, old)
+}
+
+/// Change the signals the calling thread blocks as `how` says, the old mask goes to `old`.
+pub fn sigprocmask(how: usize, set: Option<usize>, old: Option<&mut usize>) -> isize {
+    sys_sigprocmask(how, set.as_ref(), old)
+}
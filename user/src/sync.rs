@@ -0,0 +1,171 @@
+//! Locks which spin in user space only as long as nobody waits, the kernel parks the
+//! waiters on the futex of the lock word.
+
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::syscall::{sys_futex, TimeSpec};
+
+pub const FUTEX_WAIT: usize = 0;
+pub const FUTEX_WAKE: usize = 1;
+pub const FUTEX_PRIVATE_FLAG: usize = 128;
+
+pub const EPERM: isize = 1;
+pub const ENOENT: isize = 2;
+pub const ESRCH: isize = 3;
+pub const EIO: isize = 5;
+pub const EBADF: isize = 9;
+pub const ECHILD: isize = 10;
+pub const EAGAIN: isize = 11;
+pub const EINVAL: isize = 22;
+pub const EMFILE: isize = 24;
+pub const ENOTTY: isize = 25;
+pub const ERANGE: isize = 34;
+pub const EDEADLK: isize = 35;
+pub const ETIMEDOUT: isize = 110;
+
+/// Block while `word` holds `val`, returns 0 once woken, `-EAGAIN` if it did not hold `val`
+/// and `-ETIMEDOUT` once `timeout` passed.
+pub fn futex_wait(word: &AtomicU32, val: u32, timeout: Option<&TimeSpec>) -> isize {
+    sys_futex(word, FUTEX_WAIT | FUTEX_PRIVATE_FLAG, val, timeout)
+}
+
+/// Wake up to `n` threads waiting on `word`, returns how many were.
+pub fn futex_wake(word: &AtomicU32, n: u32) -> isize {
+    sys_futex(word, FUTEX_WAKE | FUTEX_PRIVATE_FLAG, n, None)
+}
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+/// locked, and somebody may sleep on it
+const CONTENDED: u32 = 2;
+
+pub struct Mutex<T> {
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicU32::new(UNLOCKED),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        if self
+            .state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // whoever unlocks has to wake somebody up from now on
+            while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+                futex_wait(&self.state, CONTENDED, None);
+            }
+        }
+        MutexGuard { mutex: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            futex_wake(&self.state, 1);
+        }
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+/// Waiters sleep on a sequence number bumped by every notification, so one sent between
+/// unlocking and going to sleep is not lost.
+pub struct Condvar {
+    seq: AtomicU32,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            seq: AtomicU32::new(0),
+        }
+    }
+
+    /// Unlock `guard` and sleep until notified, the mutex is locked again on return. Wakeups
+    /// may be spurious, check the condition in a loop.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        self.wait_inner(guard, None).0
+    }
+
+    /// Like `wait`, but gives up after `ms` milliseconds, the flag tells if it timed out.
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        ms: usize,
+    ) -> (MutexGuard<'a, T>, bool) {
+        let timeout = TimeSpec {
+            sec: ms / 1000,
+            nsec: ms % 1000 * 1_000_000,
+        };
+        self.wait_inner(guard, Some(&timeout))
+    }
+
+    fn wait_inner<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout: Option<&TimeSpec>,
+    ) -> (MutexGuard<'a, T>, bool) {
+        let seq = self.seq.load(Ordering::Relaxed);
+        let mutex = guard.mutex;
+        drop(guard);
+        let timed_out = futex_wait(&self.seq, seq, timeout) == -ETIMEDOUT;
+        (mutex.lock(), timed_out)
+    }
+
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        futex_wake(&self.seq, 1);
+    }
+
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        futex_wake(&self.seq, u32::MAX);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
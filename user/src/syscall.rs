@@ -1,14 +1,71 @@
-use core::arch::asm;
+use core::{
+    arch::{asm, global_asm},
+    sync::atomic::AtomicU32,
+};
 
+use crate::signal::SIGCHLD;
+
+const SYSCALL_IOCTL: usize = 29;
+const SYSCALL_OPENAT: usize = 56;
+const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_EXIT_GROUP: usize = 94;
+const SYSCALL_FUTEX: usize = 98;
+const SYSCALL_NANOSLEEP: usize = 101;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_KILL: usize = 129;
+const SYSCALL_SIGACTION: usize = 134;
+const SYSCALL_SIGPROCMASK: usize = 135;
+const SYSCALL_SIGRETURN: usize = 139;
+const SYSCALL_SET_PRIORITY: usize = 140;
+const SYSCALL_GETPRIORITY: usize = 141;
+/// not the Linux 140, which is the stride `set_priority`
+const SYSCALL_SETPRIORITY: usize = 142;
+const SYSCALL_TIMES: usize = 153;
+const SYSCALL_SETPGID: usize = 154;
+const SYSCALL_GETPGID: usize = 155;
+const SYSCALL_GETSID: usize = 156;
+const SYSCALL_SETSID: usize = 157;
+const SYSCALL_GETRUSAGE: usize = 165;
 const SYSCALL_GET_TIME: usize = 169;
-const SYSCALL_FORK: usize = 220;
+const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETTID: usize = 178;
+const SYSCALL_SYSINFO: usize = 179;
+const SYSCALL_SHMGET: usize = 194;
+const SYSCALL_SHMCTL: usize = 195;
+const SYSCALL_SHMAT: usize = 196;
+const SYSCALL_SHMDT: usize = 197;
+const SYSCALL_CLONE: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_WAITTID: usize = 462;
+const SYSCALL_ENABLE_DEADLOCK_DETECT: usize = 469;
+const SYSCALL_MUTEX_CREATE: usize = 1010;
+const SYSCALL_MUTEX_LOCK: usize = 1011;
+const SYSCALL_MUTEX_UNLOCK: usize = 1012;
+const SYSCALL_SEMAPHORE_CREATE: usize = 1020;
+const SYSCALL_SEMAPHORE_UP: usize = 1021;
+const SYSCALL_SEMAPHORE_DOWN: usize = 1022;
+const SYSCALL_CONDVAR_CREATE: usize = 1030;
+const SYSCALL_CONDVAR_SIGNAL: usize = 1031;
+const SYSCALL_CONDVAR_WAIT: usize = 1032;
+
+// signal handlers return here, with sp at the registers the kernel saved, so this must not
+// touch the stack
+global_asm!(
+    ".globl __sigreturn",
+    "__sigreturn:",
+    "li a7, {sigreturn}",
+    "ecall",
+    sigreturn = const SYSCALL_SIGRETURN,
+);
 
+extern "C" {
+    pub fn __sigreturn();
+}
 
 #[inline(always)]
 fn syscall(op: usize, args: [usize; 3]) -> isize {
@@ -24,6 +81,40 @@ fn syscall(op: usize, args: [usize; 3]) -> isize {
     ret
 }
 
+#[inline(always)]
+fn syscall4(op: usize, args: [usize; 4]) -> isize {
+    let mut ret: isize;
+    unsafe {
+        asm!("ecall",
+           inlateout("x10") args[0] => ret,
+           in("x11") args[1],
+           in("x12") args[2],
+           in("x13") args[3],
+           in("x17") op
+        )
+    }
+    ret
+}
+
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_IOCTL, [fd, cmd, arg])
+}
+
+/// paths are taken as they are, not relative to a directory
+const AT_FDCWD: isize = -100;
+
+/// `path` has to end with a NUL.
+pub fn sys_openat(path: &str, flags: usize) -> isize {
+    syscall4(
+        SYSCALL_OPENAT,
+        [AT_FDCWD as usize, path.as_ptr() as usize, flags, 0],
+    )
+}
+
+pub fn sys_close(fd: usize) -> isize {
+    syscall(SYSCALL_CLOSE, [fd, 0, 0])
+}
+
 pub fn sys_read(fd: usize, buf: &mut [u8]) -> isize {
     syscall(SYSCALL_READ, [fd, buf.as_mut_ptr() as usize, buf.len()])
 }
@@ -34,15 +125,95 @@ pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
 
 pub fn sys_exit(xstate: i32) -> ! {
     syscall(SYSCALL_EXIT, [xstate as usize, 0, 0]);
+    unreachable!("thread should exited!")
+}
+
+pub fn sys_exit_group(xstate: i32) -> ! {
+    syscall(SYSCALL_EXIT_GROUP, [xstate as usize, 0, 0]);
     unreachable!("program should exited!")
 }
 
+pub fn sys_getpid() -> isize {
+    syscall(SYSCALL_GETPID, [0, 0, 0])
+}
+
+pub fn sys_gettid() -> isize {
+    syscall(SYSCALL_GETTID, [0, 0, 0])
+}
+
+/// Start a thread on `stack`, or on one the kernel maps if it is 0. The child calls `entry`
+/// with `args` in a0 and a1 right away, the parent gets the tid of the child.
+pub fn sys_clone(flags: usize, stack: usize, entry: usize, args: [usize; 2]) -> isize {
+    let mut ret: isize;
+    unsafe {
+        asm!("ecall",
+           // the child returns 0 and must not touch its stack before `entry`
+           "bnez a0, 1f",
+           "mv a0, t1",
+           "mv a1, t2",
+           "jr t0",
+           "1:",
+           inlateout("x10") flags => ret,
+           in("x11") stack,
+           in("x17") SYSCALL_CLONE,
+           in("x5") entry,
+           in("x6") args[0],
+           in("x7") args[1],
+        )
+    }
+    ret
+}
+
+pub fn sys_waittid(tid: usize) -> isize {
+    syscall(SYSCALL_WAITTID, [tid, 0, 0])
+}
+
 pub fn sys_yield() -> isize {
     syscall(SYSCALL_YIELD, [0, 0, 0])
 }
 
+pub fn sys_kill(pid: isize, sig: usize) -> isize {
+    syscall(SYSCALL_KILL, [pid as usize, sig, 0])
+}
+
+/// What a signal does, see `signal::SigAction::new` for running a handler.
 #[repr(C)]
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
+pub struct SigAction {
+    pub handler: usize,
+    pub flags: usize,
+    /// where the handler returns to, it has to make the `sigreturn` syscall
+    pub restorer: usize,
+    /// blocked on top of the mask of the thread while the handler runs
+    pub mask: usize,
+}
+
+pub fn sys_sigaction(sig: usize, act: Option<&SigAction>, old: Option<&mut SigAction>) -> isize {
+    let act = act.map_or(0, |act| act as *const SigAction as usize);
+    let old = old.map_or(0, |old| old as *mut SigAction as usize);
+    syscall(SYSCALL_SIGACTION, [sig, act, old])
+}
+
+pub fn sys_sigprocmask(how: usize, set: Option<&usize>, old: Option<&mut usize>) -> isize {
+    let set = set.map_or(0, |set| set as *const usize as usize);
+    let old = old.map_or(0, |old| old as *mut usize as usize);
+    syscall(SYSCALL_SIGPROCMASK, [how, set, old])
+}
+
+pub fn sys_set_priority(prio: isize) -> isize {
+    syscall(SYSCALL_SET_PRIORITY, [prio as usize, 0, 0])
+}
+
+pub fn sys_setpriority(which: usize, who: usize, prio: isize) -> isize {
+    syscall(SYSCALL_SETPRIORITY, [which, who, prio as usize])
+}
+
+pub fn sys_getpriority(which: usize, who: usize) -> isize {
+    syscall(SYSCALL_GETPRIORITY, [which, who, 0])
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
 pub struct TimeVal {
     pub sec: usize,
     pub usec: usize,
@@ -58,9 +229,175 @@ pub fn sys_get_time() -> isize {
     }
 }
 
-pub fn sys_fork() -> isize{0}
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
+}
+
+pub fn sys_clock_gettime(clock_id: usize, ts: &mut TimeSpec) -> isize {
+    syscall(SYSCALL_CLOCK_GETTIME, [clock_id, ts as *mut TimeSpec as usize, 0])
+}
+
+/// `timeout` is relative, `None` waits for as long as it takes.
+pub fn sys_futex(uaddr: &AtomicU32, op: usize, val: u32, timeout: Option<&TimeSpec>) -> isize {
+    let timeout = timeout.map_or(0, |ts| ts as *const TimeSpec as usize);
+    syscall4(
+        SYSCALL_FUTEX,
+        [uaddr as *const AtomicU32 as usize, op, val as usize, timeout],
+    )
+}
+
+pub fn sys_nanosleep(req: &TimeSpec, rem: &mut TimeSpec) -> isize {
+    syscall(
+        SYSCALL_NANOSLEEP,
+        [req as *const TimeSpec as usize, rem as *mut TimeSpec as usize, 0],
+    )
+}
+
+/// CPU times in clock ticks of `CLOCK_TICKS_PER_SEC`.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub struct Tms {
+    pub utime: usize,
+    pub stime: usize,
+    pub cutime: usize,
+    pub cstime: usize,
+}
+
+pub fn sys_times(tms: &mut Tms) -> isize {
+    syscall(SYSCALL_TIMES, [tms as *mut Tms as usize, 0, 0])
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub struct Rusage {
+    pub utime: TimeVal,
+    pub stime: TimeVal,
+    /// in kilobytes
+    pub maxrss: usize,
+    pub ixrss: usize,
+    pub idrss: usize,
+    pub isrss: usize,
+    pub minflt: usize,
+    pub majflt: usize,
+    pub nswap: usize,
+    pub inblock: usize,
+    pub oublock: usize,
+    pub msgsnd: usize,
+    pub msgrcv: usize,
+    pub nsignals: usize,
+    pub nvcsw: usize,
+    pub nivcsw: usize,
+}
+
+pub fn sys_getrusage(who: isize, usage: &mut Rusage) -> isize {
+    syscall(SYSCALL_GETRUSAGE, [who as usize, usage as *mut Rusage as usize, 0])
+}
+
+/// Sizes are in bytes, there are no load averages.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub struct Sysinfo {
+    pub uptime: usize,
+    pub loads: [usize; 3],
+    pub totalram: usize,
+    pub freeram: usize,
+    pub sharedram: usize,
+    pub bufferram: usize,
+    pub totalswap: usize,
+    pub freeswap: usize,
+    pub procs: u16,
+    pub pad: u16,
+    pub totalhigh: usize,
+    pub freehigh: usize,
+    pub mem_unit: u32,
+}
+
+pub fn sys_sysinfo(info: &mut Sysinfo) -> isize {
+    syscall(SYSCALL_SYSINFO, [info as *mut Sysinfo as usize, 0, 0])
+}
+
+/// Fork the calling process, a `clone` with only the signal the parent gets when it exits.
+pub fn sys_fork() -> isize {
+    syscall(SYSCALL_CLONE, [SIGCHLD, 0, 0])
+}
 pub fn sys_exec(path:&str) -> isize{
     syscall(SYSCALL_EXEC, [path.as_ptr() as usize, 0, 0])
 }
-pub fn sys_waitpid(pid: isize, exit_code: *mut i32) -> isize{0}
+pub fn sys_waitpid(pid: isize, status: *mut i32, options: usize) -> isize {
+    syscall(SYSCALL_WAITPID, [pid as usize, status as usize, options])
+}
+
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    syscall(SYSCALL_SETPGID, [pid, pgid, 0])
+}
+
+pub fn sys_getpgid(pid: usize) -> isize {
+    syscall(SYSCALL_GETPGID, [pid, 0, 0])
+}
+
+pub fn sys_getsid(pid: usize) -> isize {
+    syscall(SYSCALL_GETSID, [pid, 0, 0])
+}
 
+pub fn sys_setsid() -> isize {
+    syscall(SYSCALL_SETSID, [0, 0, 0])
+}
+
+pub fn sys_shmget(key: usize, size: usize, flags: usize) -> isize {
+    syscall(SYSCALL_SHMGET, [key, size, flags])
+}
+
+pub fn sys_shmctl(id: usize, cmd: usize) -> isize {
+    syscall(SYSCALL_SHMCTL, [id, cmd, 0])
+}
+
+pub fn sys_shmat(id: usize, flags: usize) -> isize {
+    syscall(SYSCALL_SHMAT, [id, 0, flags])
+}
+
+pub fn sys_shmdt(addr: usize) -> isize {
+    syscall(SYSCALL_SHMDT, [addr, 0, 0])
+}
+
+pub fn sys_mutex_create() -> isize {
+    syscall(SYSCALL_MUTEX_CREATE, [0, 0, 0])
+}
+
+pub fn sys_mutex_lock(id: usize) -> isize {
+    syscall(SYSCALL_MUTEX_LOCK, [id, 0, 0])
+}
+
+pub fn sys_mutex_unlock(id: usize) -> isize {
+    syscall(SYSCALL_MUTEX_UNLOCK, [id, 0, 0])
+}
+
+pub fn sys_semaphore_create(count: usize) -> isize {
+    syscall(SYSCALL_SEMAPHORE_CREATE, [count, 0, 0])
+}
+
+pub fn sys_semaphore_up(id: usize) -> isize {
+    syscall(SYSCALL_SEMAPHORE_UP, [id, 0, 0])
+}
+
+pub fn sys_semaphore_down(id: usize) -> isize {
+    syscall(SYSCALL_SEMAPHORE_DOWN, [id, 0, 0])
+}
+
+pub fn sys_condvar_create() -> isize {
+    syscall(SYSCALL_CONDVAR_CREATE, [0, 0, 0])
+}
+
+pub fn sys_condvar_signal(id: usize) -> isize {
+    syscall(SYSCALL_CONDVAR_SIGNAL, [id, 0, 0])
+}
+
+pub fn sys_condvar_wait(id: usize, mutex_id: usize) -> isize {
+    syscall(SYSCALL_CONDVAR_WAIT, [id, mutex_id, 0])
+}
+
+pub fn sys_enable_deadlock_detect(enabled: usize) -> isize {
+    syscall(SYSCALL_ENABLE_DEADLOCK_DETECT, [enabled, 0, 0])
+}
@@ -0,0 +1,45 @@
+//! Threads sharing the address space of the process, each on a user stack of its own.
+
+use crate::syscall::{sys_clone, sys_exit, sys_gettid, sys_waittid};
+
+pub const CLONE_VM: usize = 0x100;
+pub const CLONE_FILES: usize = 0x400;
+pub const CLONE_THREAD: usize = 0x10000;
+
+pub struct JoinHandle {
+    tid: usize,
+}
+
+impl JoinHandle {
+    pub fn tid(&self) -> usize {
+        self.tid
+    }
+    /// Wait for the thread to exit and return its exit code.
+    pub fn join(self) -> i32 {
+        sys_waittid(self.tid) as i32
+    }
+}
+
+/// Run `f(arg)` in a new thread, it exits with what `f` returns. There is no heap to box a
+/// closure in, so `arg` carries whatever it needs.
+pub fn spawn(f: fn(usize) -> i32, arg: usize) -> Option<JoinHandle> {
+    let flags = CLONE_VM | CLONE_FILES | CLONE_THREAD;
+    match sys_clone(flags, 0, thread_start as usize, [f as usize, arg]) {
+        tid if tid > 0 => Some(JoinHandle { tid: tid as usize }),
+        _ => None,
+    }
+}
+
+extern "C" fn thread_start(f: usize, arg: usize) -> ! {
+    let f: fn(usize) -> i32 = unsafe { core::mem::transmute(f) };
+    sys_exit(f(arg))
+}
+
+/// Exit the calling thread alone, the process exits once its last thread did.
+pub fn exit(exit_code: i32) -> ! {
+    sys_exit(exit_code)
+}
+
+pub fn current_tid() -> usize {
+    sys_gettid() as usize
+}
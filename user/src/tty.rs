@@ -0,0 +1,189 @@
+//! The console as a terminal: which session it belongs to and which process group of that
+//! session is in the foreground, the one which may read and gets Ctrl-C and Ctrl-Z, and how
+//! it treats what is typed and written, its `Termios`.
+//!
+//! Pseudo-terminals are terminals as well. `posix_openpt` opens the master side of a new pair,
+//! what is written to it is taken as typed at the slave side and what the slave side outputs
+//! is read from it.
+
+use crate::{open, sync::ERANGE, syscall::sys_ioctl};
+
+pub const TCGETS: usize = 0x5401;
+pub const TCSETS: usize = 0x5402;
+pub const TCSETSW: usize = 0x5403;
+pub const TCSETSF: usize = 0x5404;
+pub const TIOCSCTTY: usize = 0x540e;
+pub const TIOCGPGRP: usize = 0x540f;
+pub const TIOCSPGRP: usize = 0x5410;
+pub const TIOCSTI: usize = 0x5412;
+pub const TIOCGWINSZ: usize = 0x5413;
+pub const TIOCSWINSZ: usize = 0x5414;
+pub const TIOCGPTN: usize = 0x8004_5430;
+pub const TIOCSPTLCK: usize = 0x4004_5431;
+
+pub const NCCS: usize = 19;
+
+// input modes
+pub const INLCR: u32 = 0o100;
+pub const IGNCR: u32 = 0o200;
+pub const ICRNL: u32 = 0o400;
+
+// output modes
+pub const OPOST: u32 = 0o1;
+pub const ONLCR: u32 = 0o4;
+
+// local modes
+pub const ISIG: u32 = 0o1;
+pub const ICANON: u32 = 0o2;
+pub const ECHO: u32 = 0o10;
+pub const ECHOE: u32 = 0o20;
+pub const ECHOK: u32 = 0o40;
+pub const ECHONL: u32 = 0o100;
+pub const NOFLSH: u32 = 0o200;
+pub const ECHOCTL: u32 = 0o1000;
+pub const ECHOKE: u32 = 0o4000;
+
+// indices of the control characters, a character of 0 is disabled
+pub const VINTR: usize = 0;
+pub const VQUIT: usize = 1;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const VTIME: usize = 5;
+pub const VMIN: usize = 6;
+pub const VSUSP: usize = 10;
+pub const VEOL: usize = 11;
+pub const VWERASE: usize = 14;
+
+/// `when` of `tcsetattr`
+pub const TCSANOW: usize = 0;
+pub const TCSADRAIN: usize = 1;
+/// drop the input not read yet as well
+pub const TCSAFLUSH: usize = 2;
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    pub cflag: u32,
+    pub lflag: u32,
+    pub line: u8,
+    pub cc: [u8; NCCS],
+}
+
+impl Termios {
+    /// No editing, echo or signals, every byte is read as it is typed. `VTIME` is not
+    /// supported, a read waits for `VMIN` bytes.
+    pub fn make_raw(&mut self) {
+        self.iflag &= !(INLCR | IGNCR | ICRNL);
+        self.oflag &= !OPOST;
+        self.lflag &= !(ISIG | ICANON | ECHO | ECHONL);
+        self.cc[VMIN] = 1;
+        self.cc[VTIME] = 0;
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub struct WinSize {
+    pub row: u16,
+    pub col: u16,
+    pub xpixel: u16,
+    pub ypixel: u16,
+}
+
+/// Make the terminal at `fd` the controlling one of the session the caller leads.
+pub fn set_controlling_tty(fd: usize) -> isize {
+    sys_ioctl(fd, TIOCSCTTY, 0)
+}
+
+/// The foreground process group of the terminal at `fd`, or a negated errno.
+pub fn tcgetpgrp(fd: usize) -> isize {
+    let mut pgid = 0i32;
+    match sys_ioctl(fd, TIOCGPGRP, &mut pgid as *mut i32 as usize) {
+        0 => pgid as isize,
+        err => err,
+    }
+}
+
+/// Put process group `pgid` in the foreground of the terminal at `fd`.
+pub fn tcsetpgrp(fd: usize, pgid: usize) -> isize {
+    let pgid = pgid as i32;
+    sys_ioctl(fd, TIOCSPGRP, &pgid as *const i32 as usize)
+}
+
+pub fn tcgetattr(fd: usize, termios: &mut Termios) -> isize {
+    sys_ioctl(fd, TCGETS, termios as *mut Termios as usize)
+}
+
+/// Change the attributes of the terminal at `fd`, `when` is one of `TCSANOW`, `TCSADRAIN`
+/// and `TCSAFLUSH`.
+pub fn tcsetattr(fd: usize, when: usize, termios: &Termios) -> isize {
+    sys_ioctl(fd, TCSETS + when, termios as *const Termios as usize)
+}
+
+pub fn get_winsize(fd: usize, winsize: &mut WinSize) -> isize {
+    sys_ioctl(fd, TIOCGWINSZ, winsize as *mut WinSize as usize)
+}
+
+/// Tell the terminal at `fd` its new size, its foreground process group gets `SIGWINCH`.
+pub fn set_winsize(fd: usize, winsize: &WinSize) -> isize {
+    sys_ioctl(fd, TIOCSWINSZ, winsize as *const WinSize as usize)
+}
+
+/// Have the terminal at `fd` take `byte` as if it was typed.
+pub fn simulate_input(fd: usize, byte: u8) -> isize {
+    sys_ioctl(fd, TIOCSTI, &byte as *const u8 as usize)
+}
+
+/// Open the master side of a new pseudo-terminal, its slave side is locked until `unlockpt`.
+pub fn posix_openpt(flags: usize) -> isize {
+    open("/dev/ptmx\0", flags)
+}
+
+/// There are no owners or permissions to change, there for the sake of the usual sequence.
+pub fn grantpt(_fd: usize) -> isize {
+    0
+}
+
+/// Let the slave side of the pseudo-terminal whose master side is at `fd` be opened.
+pub fn unlockpt(fd: usize) -> isize {
+    let lock = 0i32;
+    sys_ioctl(fd, TIOCSPTLCK, &lock as *const i32 as usize)
+}
+
+/// Write the path of the slave side of the pseudo-terminal whose master side is at `fd` to
+/// `buf`, ending with a NUL so that it can be passed to `open`. Returns its length without
+/// the NUL, or a negated errno, `-ERANGE` if it does not fit.
+pub fn ptsname_r(fd: usize, buf: &mut [u8]) -> isize {
+    let mut index = 0u32;
+    let err = sys_ioctl(fd, TIOCGPTN, &mut index as *mut u32 as usize);
+    if err != 0 {
+        return err;
+    }
+    let prefix = b"/dev/pts/";
+    let mut digits = [0u8; 10];
+    let mut len = 0;
+    loop {
+        digits[len] = b'0' + (index % 10) as u8;
+        len += 1;
+        index /= 10;
+        if index == 0 {
+            break;
+        }
+    }
+    let total = prefix.len() + len;
+    if buf.len() <= total {
+        return -ERANGE;
+    }
+    buf[..prefix.len()].copy_from_slice(prefix);
+    for (dst, &digit) in buf[prefix.len()..total]
+        .iter_mut()
+        .zip(digits[..len].iter().rev())
+    {
+        *dst = digit;
+    }
+    buf[total] = 0;
+    total as isize
+}